@@ -17,8 +17,12 @@
  */
 
 mod client;
+mod lifecycle;
 mod messages;
 mod room;
+#[cfg(test)]
+pub(crate) mod test_util;
 
-pub use client::ClientActor;
+pub use client::{ClientActor, WireFormat, MSGPACK_PROTOCOL};
+pub use messages::{GetMemberCounts, GetRoomSummaries, Shutdown};
 pub use room::RoomManagerActor;
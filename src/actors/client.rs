@@ -18,37 +18,147 @@
 
 use actix::prelude::*;
 use actix_web_actors::ws;
+use bytes::Bytes;
+use serde_json::error::Category;
 use serde_json::Error;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::net::IpAddr;
 use std::time::Duration;
 use std::time::Instant;
 use uuid::Uuid;
 
+use super::lifecycle::{self, Event};
 use super::RoomManagerActor;
-use crate::actors::messages::{ClientRequestMessage, ClientResponseMessage, RoomMessage};
-use crate::data::UserData;
+use crate::actors::messages::{
+    ClientMessage, ClientRequestMessage, ClientResponseMessage, ParseErrorReason, RoomMessage,
+};
+use crate::config::Config;
+use crate::data::{Reference, RevealPolicy, RoomSnapshot, Scale, UserData};
+use crate::redact;
+use tracing::{debug, error, info, info_span, warn, Span};
 
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Window in which the join attempts and error reports of a connection are limited
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// How many client errors a connection may report within the window
+const MAX_ERROR_REPORTS: usize = 5;
+/// Oldest protocol version the server speaks. Clients that never say hello are taken to
+/// speak version 0, so they are accepted while this is 0.
+const MIN_PROTOCOL_VERSION: u32 = 0;
+/// Newest protocol version the server speaks
+const MAX_PROTOCOL_VERSION: u32 = 1;
+/// Protocol version of the clients that do not say hello
+const LEGACY_PROTOCOL_VERSION: u32 = 0;
+/// Longest client text logged or echoed back, longer ones are cut
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// Websocket subprotocol of the clients that speak MessagePack
+pub const MSGPACK_PROTOCOL: &str = "sizematters-msgpack";
+
+/// How messages are encoded on the wire, negotiated through the websocket subprotocol.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    /// Text frames, the default.
+    Json,
+    /// Binary frames, for clients that asked for MSGPACK_PROTOCOL.
+    MessagePack,
+}
+
+/// Why the server closed a connection. Clients tell from the close code whether they
+/// should reconnect.
+#[derive(Clone, Copy)]
+enum ServerClose {
+    /// The client stopped answering the heartbeat. Reconnecting is fine.
+    Timeout,
+    /// The server is going down. Reconnecting should wait a little.
+    ShuttingDown,
+    /// The client broke the websocket protocol. Reconnecting will not help.
+    ProtocolError,
+    /// The client speaks a version of the protocol the server does not. Reconnecting will
+    /// not help.
+    UnsupportedVersion,
+    /// The creator removed the user from their last room. Reconnecting is fine, rejoining that
+    /// room is not.
+    Kicked,
+}
+
+impl ServerClose {
+    fn reason(self) -> ws::CloseReason {
+        let (code, description) = match self {
+            ServerClose::Timeout => (ws::CloseCode::Other(4000), "timeout"),
+            ServerClose::ShuttingDown => (ws::CloseCode::Restart, "server shutting down"),
+            ServerClose::ProtocolError => (ws::CloseCode::Protocol, "protocol error"),
+            ServerClose::UnsupportedVersion => {
+                (ws::CloseCode::Other(4001), "unsupported protocol version")
+            }
+            ServerClose::Kicked => (ws::CloseCode::Other(4002), "kicked"),
+        };
+        ws::CloseReason {
+            code,
+            description: Some(description.to_string()),
+        }
+    }
+}
 
 /// websocket connection is long running connection, it easier
 /// to handle with an actor
 pub struct ClientActor {
-    /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
-    /// otherwise we drop connection.
+    /// Client must send ping at least once per client timeout, otherwise we drop connection.
     last_heartbeat: Instant,
+    /// Whether the client was asked if it is still there since its last heartbeat.
+    still_there_sent: bool,
+    /// Whether a diagnostic ping is waiting for the connection to answer.
+    ping_check_pending: bool,
+    /// Protocol version the client speaks, known once it sent its first message.
+    protocol_version: Option<u32>,
     user: UserData,
+    /// Secret that lets a new connection take back the user id, known only to this client.
+    resume_token: String,
+    /// Rooms the user is a host of.
+    hosted_rooms: HashSet<String>,
+    /// Rooms the user is in, as the rooms told the connection.
+    joined_rooms: HashSet<String>,
+    /// Whether the manager was told the connection is going away.
+    manager_told: bool,
     room_manager: Addr<RoomManagerActor>,
+    config: Config,
+    /// Address the client connected from, if known.
+    ip: Option<IpAddr>,
+    wire_format: WireFormat,
+    /// When the recent attempts to join a room were made, oldest first.
+    join_attempts: VecDeque<Instant>,
+    /// When the recent client errors were reported, oldest first.
+    error_reports: VecDeque<Instant>,
 }
 
 impl ClientActor {
-    pub fn new(room_manager: Addr<RoomManagerActor>) -> Self {
+    pub fn new(
+        room_manager: Addr<RoomManagerActor>,
+        config: Config,
+        ip: Option<IpAddr>,
+        wire_format: WireFormat,
+    ) -> Self {
         let user_id = Uuid::new_v4().simple().to_string();
         Self {
             last_heartbeat: Instant::now(),
-            user: UserData::new(user_id),
+            still_there_sent: false,
+            ping_check_pending: false,
+            protocol_version: None,
+            user: UserData::new(
+                user_id,
+                config.default_name.clone(),
+                config.gravatar_style.clone(),
+            ),
+            resume_token: Uuid::new_v4().simple().to_string(),
+            hosted_rooms: HashSet::new(),
+            joined_rooms: HashSet::new(),
+            manager_told: false,
             room_manager,
+            config,
+            ip,
+            wire_format,
+            join_attempts: VecDeque::new(),
+            error_reports: VecDeque::new(),
         }
     }
 }
@@ -58,65 +168,306 @@ impl Actor for ClientActor {
 
     /// Method is called on actor start. We start the heartbeat process here.
     fn started(&mut self, ctx: &mut Self::Context) {
+        lifecycle::emit(Event::Connect, None, Some(&self.user.user_id));
         self.heartbeat(ctx);
+
+        let msg = RoomMessage::ClientConnected {
+            user_id: self.user.user_id.clone(),
+            resume_token: self.resume_token.clone(),
+            recipient: ctx.address().recipient(),
+            responder: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    /// A connection that ended without a close frame, as when the network dropped, may come
+    /// back within the grace period.
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if !self.manager_told {
+            self.user_dropped();
+        }
+        lifecycle::emit(Event::Disconnect, None, Some(&self.user.user_id));
     }
 }
 
 /// Handler for `ws::Message`
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ClientActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let _span = self.span().entered();
         // process websocket messages
         match msg {
             Ok(ws::Message::Ping(msg)) => {
-                self.last_heartbeat = Instant::now();
+                self.alive();
                 ctx.pong(&msg);
             }
-            Ok(ws::Message::Pong(_)) => {
-                self.last_heartbeat = Instant::now();
-            }
+            Ok(ws::Message::Pong(_)) => self.alive(),
             Ok(ws::Message::Text(text)) => self.text(text, ctx),
-            Ok(ws::Message::Binary(_bin)) => {} // ignore binary
+            Ok(ws::Message::Binary(bin)) => self.binary(bin, ctx),
             Ok(ws::Message::Close(reason)) => {
                 self.user_left();
                 ctx.close(reason);
                 ctx.stop();
             }
-            _ => ctx.stop(),
+            _ => self.close(ServerClose::ProtocolError, ctx),
         }
     }
 }
 
 impl ClientActor {
+    /// Span of everything the connection logs, carrying its user id.
+    fn span(&self) -> Span {
+        info_span!("client", user_id = %redact::id(&self.user.user_id))
+    }
+
     fn text(&mut self, msg: String, ctx: &mut <Self as Actor>::Context) {
-        // println!("WS: {:?}", msg);
         let client_msg: Result<ClientRequestMessage, Error> = serde_json::from_str(msg.as_str());
         match client_msg {
             Ok(client_msg) => self.client_msg(client_msg, ctx),
             Err(error) => {
-                println!("Error processing message: {}", error);
-                self::Handler::handle(self, ClientResponseMessage::Error { msg }, ctx);
+                debug!(error = %error, "Unable to parse message.");
+                let reason = parse_error_reason(&error);
+                let raw = truncate(&msg).to_string();
+                let msg = ClientResponseMessage::ParseError { reason, raw };
+                self::Handler::handle(self, msg, ctx);
+            }
+        };
+    }
+
+    /// Reads a MessagePack message. Binary frames of JSON clients are ignored.
+    fn binary(&mut self, bin: Bytes, ctx: &mut <Self as Actor>::Context) {
+        if self.wire_format != WireFormat::MessagePack {
+            return;
+        }
+        match rmp_serde::from_slice(&bin) {
+            Ok(client_msg) => self.client_msg(client_msg, ctx),
+            Err(error) => {
+                debug!(error = %error, "Unable to parse message.");
+                let reason = msgpack_error_reason(&error);
+                // the message is binary, so what the decoder said is sent instead
+                let raw = truncate(&error.to_string()).to_string();
+                let msg = ClientResponseMessage::ParseError { reason, raw };
+                self::Handler::handle(self, msg, ctx);
             }
         };
     }
 
     fn client_msg(&mut self, msg: ClientRequestMessage, ctx: &mut <Self as Actor>::Context) {
+        if self.protocol_version.is_none() {
+            let is_hello = matches!(msg, ClientRequestMessage::Hello { .. });
+            if !is_hello && !self.accept_version(LEGACY_PROTOCOL_VERSION, ctx) {
+                return;
+            }
+        }
+
         match msg {
+            ClientRequestMessage::Hello { protocol_version } => self.hello(protocol_version, ctx),
             ClientRequestMessage::Register => self.register(ctx),
+            ClientRequestMessage::Here => self.alive(),
             ClientRequestMessage::SetName { name } => self.set_name(name, ctx),
             ClientRequestMessage::SetAvatar { avatar } => self.set_avatar(avatar, ctx),
+            ClientRequestMessage::SetColor { color } => self.set_color(color, ctx),
+            ClientRequestMessage::SetAvatarStyle { style } => self.set_avatar_style(style, ctx),
+            ClientRequestMessage::ClientError { context, message } => {
+                self.client_error(context, message)
+            }
             ClientRequestMessage::JoinRoom {
                 room_name,
                 password,
                 password_is_hash,
-            } => self.join_room(room_name, password, password_is_hash, ctx),
+                custom_scale,
+                public,
+                join_as_spectator,
+            } => self.join_room(
+                room_name,
+                password,
+                password_is_hash,
+                custom_scale,
+                public,
+                join_as_spectator,
+                ctx,
+            ),
             ClientRequestMessage::LeaveRoom { room_name } => self.leave_room(room_name, ctx),
-            ClientRequestMessage::Vote { room_name, size } => self.vote(room_name, size, ctx),
-            ClientRequestMessage::NewVote { room_name } => self.new_vote(room_name),
+            ClientRequestMessage::Resume {
+                room_name,
+                since_seq,
+                user_id,
+                resume_token,
+            } => self.resume(room_name, since_seq, user_id, resume_token),
+            ClientRequestMessage::SetAllActive { room_name, active } => {
+                self.set_all_active(room_name, active)
+            }
+            ClientRequestMessage::UpdateActive { room_name, active } => {
+                self.update_active(room_name, active)
+            }
+            ClientRequestMessage::Vote {
+                room_name,
+                size,
+                confidence,
+            } => self.vote(room_name, size, confidence, ctx),
+            ClientRequestMessage::Abstain { room_name } => self.abstain(room_name),
+            ClientRequestMessage::StartTimer {
+                room_name,
+                seconds,
+                reveal_on_timeout,
+            } => self.start_timer(room_name, seconds, reveal_on_timeout),
+            ClientRequestMessage::ExportEstimates { room_name } => self.export_estimates(room_name),
+            ClientRequestMessage::SetEstimate {
+                room_name,
+                topic,
+                size,
+            } => self.set_estimate(room_name, topic, size),
+            ClientRequestMessage::NextTicket { room_name } => self.next_ticket(room_name),
+            ClientRequestMessage::LoadTickets { room_name, tickets } => {
+                self.load_tickets(room_name, tickets)
+            }
+            ClientRequestMessage::SetTopic { room_name, topic } => self.set_topic(room_name, topic),
+            ClientRequestMessage::NewVote { room_name, topic } => self.new_vote(room_name, topic),
+            ClientRequestMessage::RevealSubPoll { room_name, poll_id } => {
+                self.reveal_sub_poll(room_name, poll_id)
+            }
+            ClientRequestMessage::SubPollVote {
+                room_name,
+                poll_id,
+                size,
+            } => self.sub_poll_vote(room_name, poll_id, size),
+            ClientRequestMessage::CreateSubPoll { room_name, poll_id } => {
+                self.create_sub_poll(room_name, poll_id)
+            }
+            ClientRequestMessage::ResetRoom { room_name } => self.reset_room(room_name),
+            ClientRequestMessage::GetRoomStatistics { room_name } => {
+                self.get_room_statistics(room_name)
+            }
+            ClientRequestMessage::GetSessionSummary { room_name } => {
+                self.get_session_summary(room_name)
+            }
+            ClientRequestMessage::GetUsers { room_name } => self.get_users(room_name),
+            ClientRequestMessage::GetHistory { room_name } => self.get_history(room_name),
+            ClientRequestMessage::GetVoteStatus { room_name } => self.get_vote_status(room_name),
+            ClientRequestMessage::RevoteOutliers { room_name } => self.revote_outliers(room_name),
+            ClientRequestMessage::RevoteDissenters { room_name } => {
+                self.revote_dissenters(room_name)
+            }
             ClientRequestMessage::Randomize { room_name } => self.randomize(room_name),
+            ClientRequestMessage::GetLastRandomize { room_name } => {
+                self.get_last_randomize(room_name)
+            }
+            ClientRequestMessage::PauseRoom { room_name } => self.pause_room(room_name),
+            ClientRequestMessage::ResumeRoom { room_name } => self.resume_room(room_name),
+            ClientRequestMessage::ChangeScale {
+                room_name,
+                selected_scale_name,
+            } => self.change_scale(room_name, selected_scale_name),
+            ClientRequestMessage::CreateScale { room_name, scale } => {
+                self.create_scale(room_name, scale)
+            }
+            ClientRequestMessage::DeleteScale { room_name, name } => {
+                self.delete_scale(room_name, name)
+            }
+            ClientRequestMessage::RevealToHost { room_name } => self.reveal_to_host(room_name),
+            ClientRequestMessage::RevealToAll { room_name } => self.reveal_to_all(room_name),
+            ClientRequestMessage::SetAnonymousReveal {
+                room_name,
+                anonymous,
+            } => self.set_anonymous_reveal(room_name, anonymous),
+            ClientRequestMessage::SetReference {
+                room_name,
+                reference,
+            } => self.set_reference(room_name, reference),
+            ClientRequestMessage::RenameRoom {
+                room_name,
+                new_name,
+            } => self.rename_room(room_name, new_name),
+            ClientRequestMessage::SetLocked { room_name, locked } => {
+                self.set_locked(room_name, locked)
+            }
+            ClientRequestMessage::SetRevealOnComplete {
+                room_name,
+                reveal_on_complete,
+            } => self.set_reveal_on_complete(room_name, reveal_on_complete),
+            ClientRequestMessage::SetRevealPolicy { room_name, policy } => {
+                self.set_reveal_policy(room_name, policy)
+            }
+            ClientRequestMessage::SetUniqueNames { room_name, unique } => {
+                self.set_unique_names(room_name, unique)
+            }
+            ClientRequestMessage::SetAbstentionsCount {
+                room_name,
+                abstentions_count,
+            } => self.set_abstentions_count(room_name, abstentions_count),
+            ClientRequestMessage::SetAutoNewVote {
+                room_name,
+                auto_new_vote,
+            } => self.set_auto_new_vote(room_name, auto_new_vote),
+            ClientRequestMessage::SetAnonymizeHistory {
+                room_name,
+                anonymize_history,
+            } => self.set_anonymize_history(room_name, anonymize_history),
+            ClientRequestMessage::AttributeVotes { room_name } => self.attribute_votes(room_name),
+            ClientRequestMessage::AddCoHost { room_name, user_id } => {
+                self.add_co_host(room_name, user_id)
+            }
+            ClientRequestMessage::Kick { room_name, user_id } => self.kick(room_name, user_id),
+            ClientRequestMessage::TransferHost { room_name, user_id } => {
+                self.transfer_host(room_name, user_id)
+            }
+            ClientRequestMessage::RemoveCoHost { room_name, user_id } => {
+                self.remove_co_host(room_name, user_id)
+            }
+            ClientRequestMessage::ListScales => self.list_scales(ctx),
+            ClientRequestMessage::ListRooms => self.list_rooms(ctx),
+            ClientRequestMessage::PingAll { secret } => self.ping_all(secret, ctx),
+            ClientRequestMessage::SnapshotRoom { secret, room_name } => {
+                self.snapshot_room(secret, room_name, ctx)
+            }
+            ClientRequestMessage::RestoreRoom { secret, snapshot } => {
+                self.restore_room(secret, *snapshot, ctx)
+            }
+        }
+    }
+
+    fn hello(&mut self, protocol_version: u32, ctx: &mut <Self as Actor>::Context) {
+        if self.protocol_version.is_some() {
+            debug!("Client said hello after its first message.");
+            return;
+        }
+        if !self.accept_version(protocol_version, ctx) {
+            return;
+        }
+
+        let msg = ClientResponseMessage::Welcome {
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version,
+        };
+        self::Handler::handle(self, msg, ctx);
+    }
+
+    /// Settles the protocol version of the connection, or closes it if the server does not
+    /// speak that version.
+    fn accept_version(
+        &mut self,
+        protocol_version: u32,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> bool {
+        if (MIN_PROTOCOL_VERSION..=MAX_PROTOCOL_VERSION).contains(&protocol_version) {
+            self.protocol_version = Some(protocol_version);
+            return true;
         }
+
+        debug!(
+            protocol_version,
+            "Client speaks an unsupported protocol version."
+        );
+        let msg = ClientResponseMessage::UnsupportedVersion {
+            min: MIN_PROTOCOL_VERSION,
+            max: MAX_PROTOCOL_VERSION,
+        };
+        self::Handler::handle(self, msg, ctx);
+        self.close(ServerClose::UnsupportedVersion, ctx);
+        false
     }
 
     fn register(&mut self, ctx: &mut <Self as Actor>::Context) {
+        lifecycle::emit(Event::Register, None, Some(&self.user.user_id));
         self.notify_data_updated(ctx);
     }
 
@@ -130,21 +481,70 @@ impl ClientActor {
         self.notify_data_updated(ctx);
     }
 
+    fn set_avatar_style(&mut self, style: String, ctx: &mut <Self as Actor>::Context) {
+        if self.user.set_gravatar_style(&style) {
+            self.notify_data_updated(ctx);
+        } else {
+            self::Handler::handle(
+                self,
+                ClientResponseMessage::InvalidAvatarStyle { style },
+                ctx,
+            );
+        }
+    }
+
+    fn set_color(&mut self, color: String, ctx: &mut <Self as Actor>::Context) {
+        if self.user.set_color(&color) {
+            self.notify_data_updated(ctx);
+        } else {
+            self::Handler::handle(self, ClientResponseMessage::InvalidColor { color }, ctx);
+        }
+    }
+
+    /// Records a join attempt, unless the connection already made too many within the
+    /// last minute.
+    fn allow_join(&mut self) -> bool {
+        within_rate(&mut self.join_attempts, self.config.max_joins_per_minute)
+    }
+
+    /// Logs an error of the client. Reports beyond the rate limit are dropped.
+    fn client_error(&mut self, context: String, message: String) {
+        if !within_rate(&mut self.error_reports, MAX_ERROR_REPORTS) {
+            return;
+        }
+        warn!(
+            context = ?truncate(&context),
+            message = ?truncate(&message),
+            "Client reported an error."
+        );
+    }
+
     fn notify_data_updated(&mut self, ctx: &mut <Self as Actor>::Context) {
         let user = self.user.clone();
-        self::Handler::handle(self, ClientResponseMessage::OwnData { user }, ctx);
+        let resume_token = self.resume_token.clone();
+        let msg = ClientResponseMessage::OwnData { user, resume_token };
+        self::Handler::handle(self, msg, ctx);
 
         let user = self.user.clone();
         self.room_manager.do_send(RoomMessage::UserUpdated { user });
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn join_room(
         &mut self,
         room_name: String,
-        password: String,
+        password: Option<String>,
         password_is_hash: bool,
+        custom_scale: Option<Scale>,
+        public: bool,
+        spectator: bool,
         ctx: &mut <Self as Actor>::Context,
     ) {
+        if !self.allow_join() {
+            self::Handler::handle(self, ClientResponseMessage::TooManyRequests, ctx);
+            return;
+        }
+
         let user = self.user.clone();
         let recipient = ctx.address().recipient();
         let msg = RoomMessage::JoinRoom {
@@ -153,82 +553,1091 @@ impl ClientActor {
             password_is_hash,
             user,
             recipient,
+            ip: self.ip,
+            custom_scale,
+            public,
+            spectator,
         };
         self.room_manager.do_send(msg);
     }
 
-    fn leave_room(&mut self, room_name: String, _ctx: &mut <Self as Actor>::Context) {
+    fn leave_room(&mut self, room_name: String, ctx: &mut <Self as Actor>::Context) {
+        self.hosted_rooms.remove(&room_name);
         let msg = RoomMessage::LeaveRoom {
             user_id: self.user.user_id.clone(),
             room_name,
+            recipient: Some(ctx.address().recipient()),
         };
         self.room_manager.do_send(msg);
     }
 
-    fn vote(&mut self, room_name: String, size: u64, _ctx: &mut <Self as Actor>::Context) {
+    fn vote(
+        &mut self,
+        room_name: String,
+        size: String,
+        confidence: Option<u8>,
+        _ctx: &mut <Self as Actor>::Context,
+    ) {
         let msg = RoomMessage::Vote {
             room_name,
             user_id: self.user.user_id.clone(),
             size,
+            confidence,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn abstain(&self, room_name: String) {
+        let msg = RoomMessage::Abstain {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn start_timer(&self, room_name: String, seconds: u64, reveal_on_timeout: bool) {
+        let msg = RoomMessage::StartTimer {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            seconds,
+            reveal_on_timeout,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn export_estimates(&self, room_name: String) {
+        let msg = RoomMessage::ExportEstimates {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_estimate(&self, room_name: String, topic: String, size: String) {
+        let msg = RoomMessage::SetEstimate {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            topic,
+            size,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn next_ticket(&self, room_name: String) {
+        let msg = RoomMessage::NextTicket {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn load_tickets(&self, room_name: String, tickets: Vec<String>) {
+        let msg = RoomMessage::LoadTickets {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            tickets,
         };
         self.room_manager.do_send(msg);
     }
 
-    fn new_vote(&self, room_name: String) {
+    fn set_topic(&self, room_name: String, topic: String) {
+        let msg = RoomMessage::SetTopic {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            topic,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn new_vote(&self, room_name: String, topic: Option<String>) {
         let msg = RoomMessage::NewVote {
             room_name,
             user_id: self.user.user_id.clone(),
+            topic,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn reveal_sub_poll(&self, room_name: String, poll_id: String) {
+        let msg = RoomMessage::RevealSubPoll {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            poll_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn sub_poll_vote(&self, room_name: String, poll_id: String, size: String) {
+        let msg = RoomMessage::SubPollVote {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            poll_id,
+            size,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn create_sub_poll(&self, room_name: String, poll_id: String) {
+        let msg = RoomMessage::CreateSubPoll {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            poll_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn reset_room(&self, room_name: String) {
+        let msg = RoomMessage::ResetRoom {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn get_room_statistics(&self, room_name: String) {
+        let msg = RoomMessage::GetRoomStatistics {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn get_session_summary(&self, room_name: String) {
+        let msg = RoomMessage::GetSessionSummary {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn get_users(&self, room_name: String) {
+        let msg = RoomMessage::GetUsers {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn get_history(&self, room_name: String) {
+        let msg = RoomMessage::GetHistory {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn get_vote_status(&self, room_name: String) {
+        let msg = RoomMessage::GetVoteStatus {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn revote_outliers(&self, room_name: String) {
+        let msg = RoomMessage::RevoteOutliers {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn revote_dissenters(&self, room_name: String) {
+        let msg = RoomMessage::RevoteDissenters {
+            room_name,
+            user_id: self.user.user_id.clone(),
         };
         self.room_manager.do_send(msg);
     }
 
     fn user_left(&mut self) {
+        self.manager_told = true;
         let msg = RoomMessage::UserLeft {
             user_id: self.user.user_id.clone(),
         };
         self.room_manager.do_send(msg);
     }
 
+    /// The connection is going away without the user asking to leave.
+    fn user_dropped(&mut self) {
+        self.manager_told = true;
+        let msg = RoomMessage::UserDropped {
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
     fn randomize(&self, room_name: String) {
         let msg = RoomMessage::Randomize {
             room_name,
+            user_id: self.user.user_id.clone(),
         };
         self.room_manager.do_send(msg);
     }
 
-    /// helper method that sends ping to client on a fixed interval
-    fn heartbeat(&self, ctx: &mut <Self as Actor>::Context) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
-            // check client heartbeats
-            if Instant::now().duration_since(act.last_heartbeat) > CLIENT_TIMEOUT {
-                // heartbeat timed out
-                println!("Websocket Client heartbeat failed, disconnecting!");
+    fn get_last_randomize(&self, room_name: String) {
+        let msg = RoomMessage::GetLastRandomize {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
 
-                act.user_left();
-                ctx.stop();
-                return;
-            }
+    fn pause_room(&self, room_name: String) {
+        let msg = RoomMessage::PauseRoom {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
 
-            ctx.ping(b"");
-        });
+    fn resume_room(&self, room_name: String) {
+        let msg = RoomMessage::ResumeRoom {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
     }
-}
 
-impl Handler<ClientResponseMessage> for ClientActor {
-    type Result = ();
+    fn change_scale(&self, room_name: String, selected_scale_name: String) {
+        let msg = RoomMessage::ChangeScale {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            selected_scale_name,
+        };
+        self.room_manager.do_send(msg);
+    }
 
-    fn handle(
-        &mut self,
-        server_msg: ClientResponseMessage,
-        ctx: &mut Self::Context,
-    ) -> Self::Result {
-        let msg = serde_json::to_string(&server_msg);
-        match msg {
-            Ok(msg) => ctx.text(msg),
-            Err(err) => println!(
-                "ClientActor: error sending data back to user: {}. Error: {}",
-                &self.user.user_id, err
-            ),
-        }
+    fn create_scale(&self, room_name: String, scale: Scale) {
+        let msg = RoomMessage::CreateScale {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            scale,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn delete_scale(&self, room_name: String, name: String) {
+        let msg = RoomMessage::DeleteScale {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            name,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn reveal_to_host(&self, room_name: String) {
+        let msg = RoomMessage::RevealToHost {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn reveal_to_all(&self, room_name: String) {
+        let msg = RoomMessage::RevealToAll {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_anonymous_reveal(&self, room_name: String, anonymous: bool) {
+        let msg = RoomMessage::SetAnonymousReveal {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            anonymous,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_reference(&self, room_name: String, reference: Option<Reference>) {
+        let msg = RoomMessage::SetReference {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            reference,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn rename_room(&self, room_name: String, new_name: String) {
+        let msg = RoomMessage::RenameRoom {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            new_name,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_locked(&self, room_name: String, locked: bool) {
+        let msg = RoomMessage::SetLocked {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            locked,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_reveal_on_complete(&self, room_name: String, reveal_on_complete: bool) {
+        let msg = RoomMessage::SetRevealOnComplete {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            reveal_on_complete,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_reveal_policy(&self, room_name: String, policy: RevealPolicy) {
+        let msg = RoomMessage::SetRevealPolicy {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            policy,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    /// Replays what the connection missed. Given the id of a dropped connection, the room is
+    /// asked to take this connection in its place instead.
+    fn resume(
+        &self,
+        room_name: String,
+        since_seq: u64,
+        user_id: Option<String>,
+        resume_token: Option<String>,
+    ) {
+        if let Some(user_id) = user_id.filter(|user_id| *user_id != self.user.user_id) {
+            let msg = RoomMessage::Reclaim {
+                room_name,
+                user_id,
+                resume_token: resume_token.unwrap_or_default(),
+                new_user_id: self.user.user_id.clone(),
+                since_seq,
+            };
+            self.room_manager.do_send(msg);
+            return;
+        }
+
+        let msg = RoomMessage::Resume {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            since_seq,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_all_active(&self, room_name: String, active: bool) {
+        let msg = RoomMessage::SetAllActive {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            active,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn update_active(&self, room_name: String, active: bool) {
+        let msg = RoomMessage::UpdateActive {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            active,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_abstentions_count(&self, room_name: String, abstentions_count: bool) {
+        let msg = RoomMessage::SetAbstentionsCount {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            abstentions_count,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_auto_new_vote(&self, room_name: String, auto_new_vote: bool) {
+        let msg = RoomMessage::SetAutoNewVote {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            auto_new_vote,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_anonymize_history(&self, room_name: String, anonymize_history: bool) {
+        let msg = RoomMessage::SetAnonymizeHistory {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            anonymize_history,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn set_unique_names(&self, room_name: String, unique: bool) {
+        let msg = RoomMessage::SetUniqueNames {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            unique,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn attribute_votes(&self, room_name: String) {
+        let msg = RoomMessage::AttributeVotes {
+            room_name,
+            user_id: self.user.user_id.clone(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn add_co_host(&self, room_name: String, co_host_id: String) {
+        let msg = RoomMessage::AddCoHost {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            co_host_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn kick(&self, room_name: String, kicked_id: String) {
+        let msg = RoomMessage::Kick {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            kicked_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn transfer_host(&self, room_name: String, new_host_id: String) {
+        let msg = RoomMessage::TransferHost {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            new_host_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn remove_co_host(&self, room_name: String, co_host_id: String) {
+        let msg = RoomMessage::RemoveCoHost {
+            room_name,
+            user_id: self.user.user_id.clone(),
+            co_host_id,
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn ping_all(&self, secret: String, ctx: &mut <Self as Actor>::Context) {
+        let msg = RoomMessage::PingAll {
+            secret,
+            recipient: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn list_scales(&self, ctx: &mut <Self as Actor>::Context) {
+        let msg = RoomMessage::ListScales {
+            recipient: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn list_rooms(&self, ctx: &mut <Self as Actor>::Context) {
+        let msg = RoomMessage::ListRooms {
+            recipient: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn snapshot_room(&self, secret: String, room_name: String, ctx: &mut <Self as Actor>::Context) {
+        let msg = RoomMessage::SnapshotRoom {
+            room_name,
+            secret,
+            recipient: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    fn restore_room(
+        &self,
+        secret: String,
+        snapshot: RoomSnapshot,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let msg = RoomMessage::RestoreRoom {
+            room_name: snapshot.name.clone(),
+            secret,
+            snapshot,
+            recipient: ctx.address().recipient(),
+        };
+        self.room_manager.do_send(msg);
+    }
+
+    /// Sends a message in the wire format of the connection.
+    fn send(&self, msg: serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        match self.wire_format {
+            WireFormat::Json => match serde_json::to_string(&msg) {
+                Ok(msg) => ctx.text(msg),
+                Err(err) => error!(error = %err, "Unable to send data back to user."),
+            },
+            WireFormat::MessagePack => match rmp_serde::to_vec(&msg) {
+                Ok(msg) => ctx.binary(msg),
+                Err(err) => error!(error = %err, "Unable to send data back to user."),
+            },
+        }
+    }
+
+    /// Closes the connection, telling the manager first so the user does not linger in
+    /// their rooms. Only a client that timed out may still come back.
+    fn close(&mut self, close: ServerClose, ctx: &mut <Self as Actor>::Context) {
+        match close {
+            ServerClose::Timeout => self.user_dropped(),
+            _ => self.user_left(),
+        }
+        ctx.close(Some(close.reason()));
+        ctx.stop();
+    }
+
+    /// resets the heartbeat timeout, called whenever the client shows it is alive
+    fn alive(&mut self) {
+        self.last_heartbeat = Instant::now();
+        self.still_there_sent = false;
+
+        if self.ping_check_pending {
+            self.ping_check_pending = false;
+            let msg = RoomMessage::PingReport {
+                user_id: self.user.user_id.clone(),
+            };
+            self.room_manager.do_send(msg);
+        }
+    }
+
+    fn set_host(&mut self, room_name: &str, is_host: bool) {
+        if is_host {
+            self.hosted_rooms.insert(room_name.to_string());
+        } else {
+            self.hosted_rooms.remove(room_name);
+        }
+    }
+
+    /// hosts may get a longer timeout, so the room is not left without them on a short break
+    fn client_timeout(&self) -> Duration {
+        if !self.hosted_rooms.is_empty() {
+            self.config.host_timeout.max(self.config.client_timeout)
+        } else {
+            self.config.client_timeout
+        }
+    }
+
+    /// helper method that sends ping to client on a fixed interval
+    fn heartbeat(&self, ctx: &mut <Self as Actor>::Context) {
+        ctx.run_interval(self.config.heartbeat_interval, |act, ctx| {
+            let _span = act.span().entered();
+            // check client heartbeats
+            let elapsed = Instant::now().duration_since(act.last_heartbeat);
+            let timeout = act.client_timeout();
+            if elapsed > timeout + act.config.still_there_timeout {
+                // heartbeat timed out
+                info!("Heartbeat failed, disconnecting.");
+                act.close(ServerClose::Timeout, ctx);
+                return;
+            }
+
+            if elapsed > timeout && !act.still_there_sent {
+                act.still_there_sent = true;
+                self::Handler::handle(act, ClientResponseMessage::StillThere, ctx);
+            }
+
+            ctx.ping(b"");
+        });
+    }
+}
+
+impl Handler<ClientResponseMessage> for ClientActor {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        server_msg: ClientResponseMessage,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let _span = self.span().entered();
+        let (seq, server_msg) = match server_msg {
+            ClientResponseMessage::Sequenced { seq, message } => (Some(seq), *message),
+            server_msg => (None, server_msg),
+        };
+        match &server_msg {
+            ClientResponseMessage::RoomJoined {
+                room_name,
+                host_ids,
+                ..
+            } => {
+                self.joined_rooms.insert(room_name.clone());
+                let is_host = host_ids.contains(&self.user.user_id);
+                self.set_host(room_name, is_host);
+            }
+            ClientResponseMessage::HostsChanged {
+                room_name,
+                host_ids,
+            } => {
+                let is_host = host_ids.contains(&self.user.user_id);
+                self.set_host(room_name, is_host);
+            }
+            ClientResponseMessage::Resumed {
+                room_name,
+                user,
+                is_host,
+            } => {
+                self.user = user.clone();
+                self.joined_rooms.insert(room_name.clone());
+                self.set_host(room_name, *is_host);
+            }
+            ClientResponseMessage::RoomClosedIdle { room_name }
+            | ClientResponseMessage::Kicked { room_name }
+            | ClientResponseMessage::LeftRoom { room_name } => {
+                self.joined_rooms.remove(room_name);
+                self.hosted_rooms.remove(room_name);
+            }
+            ClientResponseMessage::RoomRenamed { old_name, new_name } => {
+                if self.joined_rooms.remove(old_name) {
+                    self.joined_rooms.insert(new_name.clone());
+                }
+                let is_host = self.hosted_rooms.remove(old_name);
+                self.set_host(new_name, is_host);
+            }
+            _ => {}
+        }
+
+        match serde_json::to_value(&server_msg) {
+            Ok(mut msg) => {
+                if let (Some(seq), Some(fields)) = (seq, msg.as_object_mut()) {
+                    fields.insert("seq".to_string(), seq.into());
+                }
+                self.send(msg, ctx);
+            }
+            Err(err) => error!(error = %err, "Unable to send data back to user."),
+        }
+        match server_msg {
+            ClientResponseMessage::ServerShuttingDown => self.close(ServerClose::ShuttingDown, ctx),
+            // a kick is from one room, the connection stays for the others
+            ClientResponseMessage::Kicked { .. } if self.joined_rooms.is_empty() => {
+                self.close(ServerClose::Kicked, ctx)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Handler<ClientMessage> for ClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) -> Self::Result {
+        match msg {
+            ClientMessage::PingCheck => {
+                self.ping_check_pending = true;
+                ctx.ping(b"");
+            }
+        }
+    }
+}
+
+/// Records an attempt, unless there were already `limit` of them within the window.
+fn within_rate(attempts: &mut VecDeque<Instant>, limit: usize) -> bool {
+    while let Some(attempt) = attempts.front() {
+        if attempt.elapsed() < RATE_WINDOW {
+            break;
+        }
+        attempts.pop_front();
+    }
+    if attempts.len() >= limit {
+        return false;
+    }
+    attempts.push_back(Instant::now());
+    true
+}
+
+/// Tells what was wrong with a message from the serde error. serde_json only classifies
+/// its errors broadly, so data errors are told apart by their message.
+fn parse_error_reason(error: &Error) -> ParseErrorReason {
+    match error.classify() {
+        Category::Syntax | Category::Io => ParseErrorReason::Syntax,
+        Category::Eof => ParseErrorReason::Incomplete,
+        Category::Data => data_error_reason(&error.to_string()),
+    }
+}
+
+/// Tells what was wrong with a MessagePack message from the rmp_serde error.
+fn msgpack_error_reason(error: &rmp_serde::decode::Error) -> ParseErrorReason {
+    use rmp_serde::decode::Error as DecodeError;
+    match error {
+        DecodeError::InvalidMarkerRead(err) | DecodeError::InvalidDataRead(err)
+            if err.kind() == io::ErrorKind::UnexpectedEof =>
+        {
+            ParseErrorReason::Incomplete
+        }
+        DecodeError::InvalidMarkerRead(_)
+        | DecodeError::InvalidDataRead(_)
+        | DecodeError::DepthLimitExceeded => ParseErrorReason::Syntax,
+        DecodeError::Syntax(description) | DecodeError::Uncategorized(description) => {
+            data_error_reason(description)
+        }
+        _ => ParseErrorReason::InvalidData,
+    }
+}
+
+/// Tells apart the errors serde reports for a well formed message that does not fit.
+fn data_error_reason(description: &str) -> ParseErrorReason {
+    if description.starts_with("unknown variant") {
+        ParseErrorReason::UnknownType
+    } else if description.starts_with("missing field") {
+        ParseErrorReason::MissingField
+    } else {
+        ParseErrorReason::InvalidData
+    }
+}
+
+/// Cuts client supplied text so it cannot flood the logs.
+fn truncate(text: &str) -> &str {
+    match text.char_indices().nth(MAX_ERROR_LENGTH) {
+        None => text,
+        Some((end, _)) => &text[..end],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::test_util::{self, close_reason, join_room, next_of_type, register, ROOM};
+    use futures::{SinkExt, StreamExt};
+
+    #[test]
+    fn close_reasons_are_distinct() {
+        let closes = [
+            ServerClose::Timeout,
+            ServerClose::ShuttingDown,
+            ServerClose::ProtocolError,
+            ServerClose::UnsupportedVersion,
+            ServerClose::Kicked,
+        ];
+        let codes: HashSet<u16> = closes
+            .iter()
+            .map(|close| close.reason().code.into())
+            .collect();
+        assert_eq!(codes.len(), closes.len());
+    }
+
+    #[actix_rt::test]
+    async fn kicked_client_is_closed_as_kicked() {
+        let mut srv = test_util::server(test_util::config());
+        let mut host = srv.ws().await.unwrap();
+        join_room(&mut host, ROOM).await;
+        next_of_type(&mut host, "RoomJoined").await;
+        let mut guest = srv.ws().await.unwrap();
+        let guest_id = register(&mut guest).await;
+        join_room(&mut guest, ROOM).await;
+        next_of_type(&mut guest, "RoomJoined").await;
+
+        let kick = serde_json::json!({
+            "type": "Kick",
+            "data": { "room_name": ROOM, "user_id": guest_id },
+        });
+        test_util::send_json(&mut host, kick).await;
+
+        let reason = close_reason(&mut guest).await.unwrap();
+        assert_eq!(reason, ServerClose::Kicked.reason());
+        assert_ne!(reason, ServerClose::Timeout.reason());
+    }
+
+    #[actix_rt::test]
+    async fn kicked_client_stays_connected_for_its_other_rooms() {
+        let mut srv = test_util::server(test_util::config());
+        let mut host = srv.ws().await.unwrap();
+        join_room(&mut host, ROOM).await;
+        next_of_type(&mut host, "RoomJoined").await;
+        let mut guest = srv.ws().await.unwrap();
+        let guest_id = register(&mut guest).await;
+        join_room(&mut guest, ROOM).await;
+        next_of_type(&mut guest, "RoomJoined").await;
+        join_room(&mut guest, "other").await;
+        next_of_type(&mut guest, "RoomJoined").await;
+
+        let kick = serde_json::json!({
+            "type": "Kick",
+            "data": { "room_name": ROOM, "user_id": guest_id },
+        });
+        test_util::send_json(&mut host, kick).await;
+        next_of_type(&mut guest, "Kicked").await;
+
+        let get_users = serde_json::json!({ "type": "GetUsers", "data": { "room_name": "other" } });
+        test_util::send_json(&mut guest, get_users).await;
+        let users = next_of_type(&mut guest, "Users").await;
+        assert_eq!(users["data"]["members"][0]["user"]["user_id"], guest_id);
+    }
+
+    #[actix_rt::test]
+    async fn client_closed_by_the_server_leaves_its_rooms() {
+        let mut srv = test_util::server(test_util::config());
+        let mut host = srv.ws().await.unwrap();
+        join_room(&mut host, ROOM).await;
+        next_of_type(&mut host, "RoomJoined").await;
+        let mut guest = srv.ws().await.unwrap();
+        let guest_id = register(&mut guest).await;
+        join_room(&mut guest, ROOM).await;
+        next_of_type(&mut guest, "RoomJoined").await;
+        next_of_type(&mut host, "UserJoined").await;
+
+        // larger than a frame may be, which breaks the protocol
+        let frame = ws::Message::Text("x".repeat(128 * 1024));
+        guest.send(frame).await.unwrap();
+        let reason = close_reason(&mut guest).await.unwrap();
+        assert_eq!(reason, ServerClose::ProtocolError.reason());
+
+        let left = next_of_type(&mut host, "UserLeft").await;
+        let user_ids = left["data"]["user_ids"].as_array().unwrap();
+        assert!(!user_ids.iter().any(|user_id| *user_id == guest_id.as_str()));
+    }
+
+    #[actix_rt::test]
+    async fn rate_limited_client_stays_connected() {
+        let mut config = test_util::config();
+        config.max_joins_per_minute = 1;
+        let mut srv = test_util::server(config);
+        let mut client = srv.ws().await.unwrap();
+        join_room(&mut client, ROOM).await;
+        next_of_type(&mut client, "RoomJoined").await;
+        join_room(&mut client, "other").await;
+        next_of_type(&mut client, "TooManyRequests").await;
+
+        let get_users = serde_json::json!({ "type": "GetUsers", "data": { "room_name": ROOM } });
+        test_util::send_json(&mut client, get_users).await;
+        let users = next_of_type(&mut client, "Users").await;
+        assert_eq!(users["data"]["members"].as_array().unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn msgpack_parse_error_does_not_echo_the_message() {
+        let srv = test_util::server(test_util::config());
+        let mut client = test_util::msgpack_client(&srv).await;
+        let bin = Bytes::from_static(b"\xc1secret");
+        client.send(ws::Message::Binary(bin)).await.unwrap();
+
+        let msg = test_util::next_msgpack(&mut client).await;
+        assert_eq!(msg["type"], "ParseError");
+        let raw = msg["data"]["raw"].as_str().unwrap();
+        assert!(!raw.is_empty());
+        assert!(!raw.contains("secret"));
+    }
+
+    /// What the server said about a message it could not parse.
+    async fn parse_error(text: String) -> serde_json::Value {
+        let mut srv = test_util::server(test_util::config());
+        let mut client = srv.ws().await.unwrap();
+        client.send(ws::Message::Text(text)).await.unwrap();
+        next_of_type(&mut client, "ParseError").await
+    }
+
+    #[actix_rt::test]
+    async fn unknown_message_type_is_told_apart() {
+        let text = serde_json::json!({ "type": "Dance", "data": {} }).to_string();
+        let error = parse_error(text.clone()).await;
+        assert_eq!(error["data"]["reason"], "unknown_type");
+        assert_eq!(error["data"]["raw"], text);
+    }
+
+    #[actix_rt::test]
+    async fn missing_field_is_told_apart() {
+        let text = serde_json::json!({ "type": "JoinRoom", "data": {} }).to_string();
+        let error = parse_error(text).await;
+        assert_eq!(error["data"]["reason"], "missing_field");
+    }
+
+    #[actix_rt::test]
+    async fn huge_messages_are_not_echoed_whole() {
+        let text = format!("{{{}", "x".repeat(MAX_ERROR_LENGTH * 4));
+        let error = parse_error(text).await;
+        assert_eq!(error["data"]["reason"], "syntax");
+        let raw = error["data"]["raw"].as_str().unwrap();
+        assert_eq!(raw.chars().count(), MAX_ERROR_LENGTH);
+    }
+
+    fn prompting_config() -> Config {
+        let mut config = test_util::config();
+        config.heartbeat_interval = Duration::from_millis(50);
+        config.client_timeout = Duration::from_millis(200);
+        config.host_timeout = Duration::from_millis(200);
+        config.still_there_timeout = Duration::from_millis(300);
+        config
+    }
+
+    #[actix_rt::test]
+    async fn heartbeat_follows_the_configured_interval() {
+        let mut srv = test_util::server(prompting_config());
+        let mut client = srv.ws().await.unwrap();
+
+        let mut pings = 0;
+        let until = Instant::now() + Duration::from_millis(180);
+        while let Some(left) = until.checked_duration_since(Instant::now()) {
+            match actix_rt::time::timeout(left, client.next()).await {
+                Ok(Some(Ok(ws::Frame::Ping(_)))) => pings += 1,
+                Ok(Some(Ok(_))) => {}
+                _ => break,
+            }
+        }
+        assert!(pings >= 2, "only {} pings", pings);
+    }
+
+    #[actix_rt::test]
+    async fn answering_the_prompt_extends_the_session() {
+        let mut srv = test_util::server(prompting_config());
+        let mut client = srv.ws().await.unwrap();
+        next_of_type(&mut client, "StillThere").await;
+        test_util::send_json(&mut client, serde_json::json!({ "type": "Here" })).await;
+
+        // without the answer the connection would be closed before a second prompt
+        next_of_type(&mut client, "StillThere").await;
+    }
+
+    #[actix_rt::test]
+    async fn not_answering_the_prompt_disconnects() {
+        let mut srv = test_util::server(prompting_config());
+        let mut client = srv.ws().await.unwrap();
+        next_of_type(&mut client, "StillThere").await;
+
+        let reason = close_reason(&mut client).await.unwrap();
+        assert_eq!(reason, ServerClose::Timeout.reason());
+    }
+
+    #[actix_rt::test]
+    async fn host_outlasts_the_timeout_of_other_users() {
+        let mut config = prompting_config();
+        config.host_timeout = Duration::from_secs(2);
+        let mut srv = test_util::server(config);
+        let mut host = srv.ws().await.unwrap();
+        join_room(&mut host, ROOM).await;
+        next_of_type(&mut host, "RoomJoined").await;
+        let mut guest = srv.ws().await.unwrap();
+        join_room(&mut guest, ROOM).await;
+        next_of_type(&mut guest, "RoomJoined").await;
+
+        let reason = close_reason(&mut guest).await.unwrap();
+        assert_eq!(reason, ServerClose::Timeout.reason());
+        let prompt = actix_rt::time::timeout(
+            Duration::from_millis(500),
+            next_of_type(&mut host, "StillThere"),
+        )
+        .await;
+        assert!(prompt.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn new_users_get_the_configured_default_name() {
+        let mut config = test_util::config();
+        config.default_name = "Anonymous Llama".to_string();
+        let mut srv = test_util::server(config);
+        let mut client = srv.ws().await.unwrap();
+
+        test_util::send_json(&mut client, serde_json::json!({ "type": "Register" })).await;
+        let own_data = next_of_type(&mut client, "OwnData").await;
+        assert_eq!(own_data["data"]["user"]["name"], "Anonymous Llama");
+    }
+
+    #[actix_rt::test]
+    async fn selected_avatar_style_round_trips_through_user_data() {
+        let mut config = test_util::config();
+        config.gravatar_style = "retro".to_string();
+        let mut srv = test_util::server(config);
+        let mut client = srv.ws().await.unwrap();
+
+        test_util::send_json(&mut client, serde_json::json!({ "type": "Register" })).await;
+        let own_data = next_of_type(&mut client, "OwnData").await;
+        assert_eq!(own_data["data"]["user"]["gravatar_style"], "retro");
+
+        let style =
+            serde_json::json!({ "type": "SetAvatarStyle", "data": { "style": "monsterid" } });
+        test_util::send_json(&mut client, style).await;
+        let own_data = next_of_type(&mut client, "OwnData").await;
+        assert_eq!(own_data["data"]["user"]["gravatar_style"], "monsterid");
+
+        test_util::join_room(&mut client, "room").await;
+        let joined = next_of_type(&mut client, "RoomJoined").await;
+        assert_eq!(joined["data"]["users"][0]["gravatar_style"], "monsterid");
+    }
+
+    #[actix_rt::test]
+    async fn unknown_avatar_style_is_refused() {
+        let mut srv = test_util::server(test_util::config());
+        let mut client = srv.ws().await.unwrap();
+        test_util::register(&mut client).await;
+
+        let style = serde_json::json!({ "type": "SetAvatarStyle", "data": { "style": "clown" } });
+        test_util::send_json(&mut client, style).await;
+        let refused = next_of_type(&mut client, "InvalidAvatarStyle").await;
+        assert_eq!(refused["data"]["style"], "clown");
+    }
+
+    #[test]
+    fn attempts_beyond_the_limit_are_refused() {
+        let mut attempts = VecDeque::new();
+        for _ in 0..3 {
+            assert!(within_rate(&mut attempts, 3));
+        }
+        assert!(!within_rate(&mut attempts, 3));
+        assert_eq!(attempts.len(), 3);
+    }
+
+    #[test]
+    fn attempts_older_than_the_window_are_forgotten() {
+        let old = Instant::now().checked_sub(RATE_WINDOW + Duration::from_secs(1));
+        let mut attempts: VecDeque<Instant> = old.into_iter().collect();
+        assert!(within_rate(&mut attempts, 1));
+        assert_eq!(attempts.len(), 1);
+    }
+
+    /// A connection that is never started, to call its handlers directly.
+    fn unstarted_client() -> ClientActor {
+        let config = test_util::config();
+        let room_manager = RoomManagerActor::new(config.clone()).start();
+        ClientActor::new(room_manager, config, None, WireFormat::Json)
+    }
+
+    #[actix_rt::test]
+    async fn client_errors_are_logged_until_throttled() {
+        let (log, _guard) = test_util::Log::capture();
+        let mut client = unstarted_client();
+        for _ in 0..MAX_ERROR_REPORTS + 2 {
+            client.client_error(
+                "vote".to_string(),
+                "undefined is not a function".to_string(),
+            );
+        }
+
+        let text = log.text();
+        assert_eq!(
+            text.matches("Client reported an error.").count(),
+            MAX_ERROR_REPORTS
+        );
+        assert!(text.contains("undefined is not a function"));
+    }
+
+    #[actix_rt::test]
+    async fn long_client_errors_are_cut() {
+        let (log, _guard) = test_util::Log::capture();
+        let mut client = unstarted_client();
+        client.client_error("vote".to_string(), "x".repeat(MAX_ERROR_LENGTH * 2));
+
+        let text = log.text();
+        assert!(text.contains(&"x".repeat(MAX_ERROR_LENGTH)));
+        assert!(!text.contains(&"x".repeat(MAX_ERROR_LENGTH + 1)));
+    }
+
+    #[actix_rt::test]
+    async fn rapid_repeated_joins_are_throttled() {
+        let mut config = test_util::config();
+        config.max_joins_per_minute = 3;
+        let mut srv = test_util::server(config);
+        let mut client = srv.ws().await.unwrap();
+        join_room(&mut client, ROOM).await;
+        next_of_type(&mut client, "RoomJoined").await;
+        for _ in 0..3 {
+            join_room(&mut client, ROOM).await;
+        }
+
+        next_of_type(&mut client, "TooManyRequests").await;
     }
 }
@@ -0,0 +1,110 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::redact;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+/// Transitions in the life of a connection that are worth recording.
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    Connect,
+    Register,
+    Join,
+    Leave,
+    Disconnect,
+    Reveal,
+}
+
+#[derive(Serialize)]
+struct Record {
+    event: Event,
+    room_name: Option<String>,
+    user_id: Option<String>,
+    /// milliseconds since the unix epoch
+    timestamp: u128,
+}
+
+/// Emits a lifecycle event. Every lifecycle transition must be reported through here.
+pub fn emit(event: Event, room_name: Option<&str>, user_id: Option<&str>) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let record = Record {
+        event,
+        room_name: room_name.map(redact::id),
+        user_id: user_id.map(redact::id),
+        timestamp,
+    };
+    match serde_json::to_string(&record) {
+        Ok(record) => info!("Lifecycle: {}", record),
+        Err(err) => error!(error = %err, "Unable to serialize lifecycle event."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::test_util::{self, Log, TestRoom, REDACTION, ROOM};
+    use serde_json::Value;
+
+    /// The lifecycle events logged so far.
+    fn events(log: &Log) -> Vec<Value> {
+        log.text()
+            .lines()
+            .filter_map(|line| line.split_once("Lifecycle: "))
+            .map(|(_, record)| serde_json::from_str(record).unwrap())
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn join_emits_a_join_event() {
+        let _redaction = REDACTION.lock().await;
+        let (log, _guard) = Log::capture();
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+
+        let events = events(&log);
+        let join = events
+            .iter()
+            .find(|event| event["event"] == "join")
+            .unwrap();
+        assert_eq!(join["room_name"], ROOM);
+        assert_eq!(join["user_id"], "alice");
+        assert!(join["timestamp"].as_u64().unwrap() > 0);
+    }
+
+    #[actix_rt::test]
+    async fn reveal_emits_a_reveal_event_for_the_room() {
+        let _redaction = REDACTION.lock().await;
+        let (log, _guard) = Log::capture();
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.vote("alice", "3").await;
+
+        let events = events(&log);
+        let reveal = events
+            .iter()
+            .find(|event| event["event"] == "reveal")
+            .unwrap();
+        assert_eq!(reveal["room_name"], ROOM);
+        assert!(reveal["user_id"].is_null());
+    }
+}
@@ -16,67 +16,405 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::data::UserData;
+use crate::data::{
+    Member, MemberCounts, Reference, RevealPolicy, RoomSnapshot, RoomStatistics, RoomSummary,
+    Round, Scale, SessionSummary, UserData, VoteSummary,
+};
 use actix::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 /// Messages sent from the client to the server.
 #[derive(Message, Deserialize)]
 #[serde(tag = "type", content = "data")]
 #[rtype(result = "()")]
 pub enum ClientRequestMessage {
+    /// Must be the first message of a connection, so the server knows which protocol it
+    /// speaks.
+    Hello {
+        protocol_version: u32,
+    },
     Register,
+    Here,
     SetName {
         name: String,
     },
     SetAvatar {
         avatar: String,
     },
+    SetColor {
+        color: String,
+    },
+    SetAvatarStyle {
+        style: String,
+    },
+    /// Something went wrong on the client. It is only logged.
+    ClientError {
+        context: String,
+        message: String,
+    },
     JoinRoom {
         room_name: String,
-        password: String,
+        /// None for a room anyone may join. An empty password is the same as none.
+        #[serde(default)]
+        password: Option<String>,
+        #[serde(default)]
         password_is_hash: bool,
+        /// Scale selected in the room, if the join creates it. Ignored otherwise.
+        #[serde(default)]
+        custom_scale: Option<Scale>,
+        /// Whether the room is listed for everyone, if the join creates it. Ignored otherwise.
+        #[serde(default)]
+        public: bool,
+        /// Joins passive, to follow the room without voting.
+        #[serde(default)]
+        join_as_spectator: bool,
     },
     LeaveRoom {
         room_name: String,
     },
+    /// Asks for the broadcasts sent after the given sequence number, after a reconnect.
+    /// A new connection may take back the id of the dropped one, given its resume token.
+    Resume {
+        room_name: String,
+        since_seq: u64,
+        #[serde(default)]
+        user_id: Option<String>,
+        #[serde(default)]
+        resume_token: Option<String>,
+    },
+    /// Passive users follow the room without voting.
+    UpdateActive {
+        room_name: String,
+        active: bool,
+    },
+    SetAllActive {
+        room_name: String,
+        active: bool,
+    },
     Vote {
         room_name: String,
-        size: u64,
+        size: String,
+        /// How sure the voter is, from 1 to 5.
+        #[serde(default)]
+        confidence: Option<u8>,
     },
+    Abstain {
+        room_name: String,
+    },
+    /// Starts a new round, about the topic if one is given.
     NewVote {
         room_name: String,
+        #[serde(default)]
+        topic: Option<String>,
+    },
+    SetTopic {
+        room_name: String,
+        topic: String,
+    },
+    LoadTickets {
+        room_name: String,
+        tickets: Vec<String>,
+    },
+    NextTicket {
+        room_name: String,
+    },
+    SetEstimate {
+        room_name: String,
+        topic: String,
+        size: String,
+    },
+    ExportEstimates {
+        room_name: String,
+    },
+    StartTimer {
+        room_name: String,
+        seconds: u64,
+        /// Votes are revealed when the timer expires, with everyone left counted as abstaining.
+        #[serde(default)]
+        reveal_on_timeout: bool,
+    },
+    RevoteDissenters {
+        room_name: String,
+    },
+    RevoteOutliers {
+        room_name: String,
+    },
+    GetVoteStatus {
+        room_name: String,
+    },
+    GetHistory {
+        room_name: String,
+    },
+    GetUsers {
+        room_name: String,
+    },
+    GetSessionSummary {
+        room_name: String,
+    },
+    GetRoomStatistics {
+        room_name: String,
+    },
+    ResetRoom {
+        room_name: String,
+    },
+    CreateSubPoll {
+        room_name: String,
+        poll_id: String,
+    },
+    SubPollVote {
+        room_name: String,
+        poll_id: String,
+        size: String,
+    },
+    RevealSubPoll {
+        room_name: String,
+        poll_id: String,
     },
     Randomize {
         room_name: String,
     },
+    GetLastRandomize {
+        room_name: String,
+    },
+    PauseRoom {
+        room_name: String,
+    },
+    ResumeRoom {
+        room_name: String,
+    },
+    ChangeScale {
+        room_name: String,
+        selected_scale_name: String,
+    },
+    CreateScale {
+        room_name: String,
+        scale: Scale,
+    },
+    DeleteScale {
+        room_name: String,
+        name: String,
+    },
+    RevealToHost {
+        room_name: String,
+    },
+    RevealToAll {
+        room_name: String,
+    },
+    SetAnonymousReveal {
+        room_name: String,
+        anonymous: bool,
+    },
+    SetRevealPolicy {
+        room_name: String,
+        policy: RevealPolicy,
+    },
+    SetRevealOnComplete {
+        room_name: String,
+        reveal_on_complete: bool,
+    },
+    /// While locked, no one else may join the room. Only hosts may lock it.
+    SetLocked {
+        room_name: String,
+        locked: bool,
+    },
+    /// Gives the room another name. Only hosts may rename it.
+    RenameRoom {
+        room_name: String,
+        new_name: String,
+    },
+    SetReference {
+        room_name: String,
+        reference: Option<Reference>,
+    },
+    SetUniqueNames {
+        room_name: String,
+        unique: bool,
+    },
+    SetAutoNewVote {
+        room_name: String,
+        auto_new_vote: bool,
+    },
+    /// Names are stripped from the rounds of the history some time after they end.
+    SetAnonymizeHistory {
+        room_name: String,
+        anonymize_history: bool,
+    },
+    SetAbstentionsCount {
+        room_name: String,
+        abstentions_count: bool,
+    },
+    AttributeVotes {
+        room_name: String,
+    },
+    AddCoHost {
+        room_name: String,
+        user_id: String,
+    },
+    RemoveCoHost {
+        room_name: String,
+        user_id: String,
+    },
+    /// Hands the host role of the requester over to another member.
+    TransferHost {
+        room_name: String,
+        user_id: String,
+    },
+    /// Removes a member from the room. Only the creator of the room may kick.
+    Kick {
+        room_name: String,
+        user_id: String,
+    },
+    ListScales,
+    /// Lists the public rooms.
+    ListRooms,
+    PingAll {
+        secret: String,
+    },
+    SnapshotRoom {
+        secret: String,
+        room_name: String,
+    },
+    RestoreRoom {
+        secret: String,
+        snapshot: Box<RoomSnapshot>,
+    },
 }
 
 /// messages sent to a RoomActor
-#[derive(Message, Clone)]
+#[derive(Message, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", content = "data")]
 #[rtype(result = "()")]
 pub enum RoomMessage {
     JoinRoom {
         room_name: String,
-        password: String,
+        #[serde(skip)]
+        password: Option<String>,
         password_is_hash: bool,
         user: UserData,
+        #[serde(skip, default = "nowhere")]
         recipient: Recipient<ClientResponseMessage>,
+        ip: Option<IpAddr>,
+        custom_scale: Option<Scale>,
+        public: bool,
+        spectator: bool,
     },
     LeaveRoom {
         room_name: String,
         user_id: String,
+        /// Where to confirm the user left. Absent when the user disconnected.
+        #[serde(skip)]
+        recipient: Option<Recipient<ClientResponseMessage>>,
+    },
+    Resume {
+        room_name: String,
+        user_id: String,
+        since_seq: u64,
+    },
+    UpdateActive {
+        room_name: String,
+        user_id: String,
+        active: bool,
+    },
+    SetAllActive {
+        room_name: String,
+        user_id: String,
+        active: bool,
     },
     Vote {
         room_name: String,
         user_id: String,
-        size: u64,
+        size: String,
+        confidence: Option<u8>,
+    },
+    Abstain {
+        room_name: String,
+        user_id: String,
     },
     NewVote {
         room_name: String,
         user_id: String,
+        topic: Option<String>,
+    },
+    SetTopic {
+        room_name: String,
+        user_id: String,
+        topic: String,
+    },
+    LoadTickets {
+        room_name: String,
+        user_id: String,
+        tickets: Vec<String>,
+    },
+    NextTicket {
+        room_name: String,
+        user_id: String,
+    },
+    SetEstimate {
+        room_name: String,
+        user_id: String,
+        topic: String,
+        size: String,
+    },
+    ExportEstimates {
+        room_name: String,
+        user_id: String,
+    },
+    StartTimer {
+        room_name: String,
+        user_id: String,
+        seconds: u64,
+        reveal_on_timeout: bool,
+    },
+    RevoteDissenters {
+        room_name: String,
+        user_id: String,
+    },
+    RevoteOutliers {
+        room_name: String,
+        user_id: String,
+    },
+    GetVoteStatus {
+        room_name: String,
+        user_id: String,
+    },
+    GetHistory {
+        room_name: String,
+        user_id: String,
+    },
+    GetUsers {
+        room_name: String,
+        user_id: String,
+    },
+    GetSessionSummary {
+        room_name: String,
+        user_id: String,
+    },
+    GetRoomStatistics {
+        room_name: String,
+        user_id: String,
+    },
+    ResetRoom {
+        room_name: String,
+        user_id: String,
+    },
+    CreateSubPoll {
+        room_name: String,
+        user_id: String,
+        poll_id: String,
+    },
+    SubPollVote {
+        room_name: String,
+        user_id: String,
+        poll_id: String,
+        size: String,
+    },
+    RevealSubPoll {
+        room_name: String,
+        user_id: String,
+        poll_id: String,
     },
     UserUpdated {
         user: UserData,
@@ -84,12 +422,271 @@ pub enum RoomMessage {
     UserLeft {
         user_id: String,
     },
+    /// The room turned the user away or removed them, they are not in it.
+    UserRemoved {
+        room_name: String,
+        user_id: String,
+    },
+    /// The connection of the user stopped answering. The room keeps them for a while.
+    UserDropped {
+        user_id: String,
+    },
     RoomClosing {
         room_name: String,
     },
     Randomize {
         room_name: String,
+        user_id: String,
+    },
+    GetLastRandomize {
+        room_name: String,
+        user_id: String,
+    },
+    PauseRoom {
+        room_name: String,
+        user_id: String,
+    },
+    ResumeRoom {
+        room_name: String,
+        user_id: String,
+    },
+    ChangeScale {
+        room_name: String,
+        user_id: String,
+        selected_scale_name: String,
+    },
+    CreateScale {
+        room_name: String,
+        user_id: String,
+        scale: Scale,
+    },
+    DeleteScale {
+        room_name: String,
+        user_id: String,
+        name: String,
+    },
+    RevealToHost {
+        room_name: String,
+        user_id: String,
+    },
+    RevealToAll {
+        room_name: String,
+        user_id: String,
+    },
+    SetAnonymousReveal {
+        room_name: String,
+        user_id: String,
+        anonymous: bool,
     },
+    SetRevealPolicy {
+        room_name: String,
+        user_id: String,
+        policy: RevealPolicy,
+    },
+    SetRevealOnComplete {
+        room_name: String,
+        user_id: String,
+        reveal_on_complete: bool,
+    },
+    SetLocked {
+        room_name: String,
+        user_id: String,
+        locked: bool,
+    },
+    RenameRoom {
+        room_name: String,
+        user_id: String,
+        new_name: String,
+    },
+    SetReference {
+        room_name: String,
+        user_id: String,
+        reference: Option<Reference>,
+    },
+    SetUniqueNames {
+        room_name: String,
+        user_id: String,
+        unique: bool,
+    },
+    SetAutoNewVote {
+        room_name: String,
+        user_id: String,
+        auto_new_vote: bool,
+    },
+    SetAnonymizeHistory {
+        room_name: String,
+        user_id: String,
+        anonymize_history: bool,
+    },
+    SetAbstentionsCount {
+        room_name: String,
+        user_id: String,
+        abstentions_count: bool,
+    },
+    AttributeVotes {
+        room_name: String,
+        user_id: String,
+    },
+    AddCoHost {
+        room_name: String,
+        user_id: String,
+        co_host_id: String,
+    },
+    RemoveCoHost {
+        room_name: String,
+        user_id: String,
+        co_host_id: String,
+    },
+    TransferHost {
+        room_name: String,
+        user_id: String,
+        new_host_id: String,
+    },
+    Kick {
+        room_name: String,
+        user_id: String,
+        kicked_id: String,
+    },
+    ClientConnected {
+        user_id: String,
+        #[serde(skip)]
+        resume_token: String,
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientMessage>,
+        #[serde(skip, default = "nowhere")]
+        responder: Recipient<ClientResponseMessage>,
+    },
+    ListScales {
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+    ListRooms {
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+    /// A host of the room asked for the new name. The manager checks it is free.
+    RenameApproved {
+        room_name: String,
+        user_id: String,
+        new_name: String,
+    },
+    /// The manager knows the room by its new name now.
+    SetName {
+        new_name: String,
+    },
+    /// A new connection taking back the id of a dropped one.
+    Reclaim {
+        room_name: String,
+        user_id: String,
+        #[serde(skip)]
+        resume_token: String,
+        new_user_id: String,
+        since_seq: u64,
+    },
+    /// The user is back on a new connection.
+    Reconnected {
+        user_id: String,
+        /// None when the client resumes the room on its own afterwards.
+        since_seq: Option<u64>,
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+    PingAll {
+        secret: String,
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+    PingReport {
+        user_id: String,
+    },
+    SnapshotRoom {
+        room_name: String,
+        secret: String,
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+    RestoreRoom {
+        room_name: String,
+        secret: String,
+        snapshot: RoomSnapshot,
+        #[serde(skip, default = "nowhere")]
+        recipient: Recipient<ClientResponseMessage>,
+    },
+}
+
+/// Asks for how many active and passive members a room has.
+#[derive(Message)]
+#[rtype(result = "Result<MemberCounts, ()>")]
+pub struct GetMemberCounts {
+    pub room_name: String,
+}
+
+/// Asks for the state of every room, as seen by an operator.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<RoomSummary>, ()>")]
+pub struct GetRoomSummaries;
+
+/// Asks a room for its state, as seen by an operator.
+#[derive(Message)]
+#[rtype(result = "Result<RoomSummary, ()>")]
+pub struct GetRoomSummary;
+
+/// Reuses an idle room actor from the pool for a new room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Recycle {
+    pub room_name: String,
+    pub password: Option<String>,
+    pub password_is_hash: bool,
+    pub custom_scale: Option<Scale>,
+}
+
+/// Stops an idle room actor that does not fit in the pool.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Retire;
+
+/// Asks an actor to export what it holds and stop, because the server is going down.
+#[derive(Message)]
+#[rtype(result = "Result<(), ()>")]
+pub struct Shutdown;
+
+/// Stands in for the connections of messages read back from a recording, which were not
+/// recorded. Nothing sent to it arrives.
+pub struct Nowhere;
+
+impl Actor for Nowhere {
+    type Context = Context<Self>;
+}
+
+impl Handler<ClientResponseMessage> for Nowhere {
+    type Result = ();
+
+    fn handle(&mut self, _: ClientResponseMessage, _: &mut Context<Self>) {}
+}
+
+impl Handler<ClientMessage> for Nowhere {
+    type Result = ();
+
+    fn handle(&mut self, _: ClientMessage, _: &mut Context<Self>) {}
+}
+
+fn nowhere<M>() -> Recipient<M>
+where
+    M: Message + Send + 'static,
+    M::Result: Send,
+    Nowhere: Handler<M>,
+{
+    let (sender, _) = actix::dev::channel::channel(0);
+    Addr::<Nowhere>::new(sender).recipient()
+}
+
+/// Messages sent to a ClientActor by the server
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub enum ClientMessage {
+    /// Asks the client actor to ping its connection and report back when it answers.
+    PingCheck,
 }
 
 /// Messages sent to the client
@@ -97,55 +694,393 @@ pub enum RoomMessage {
 #[serde(tag = "type", content = "data")]
 #[rtype(result = "()")]
 pub enum ClientResponseMessage {
+    /// A room broadcast with its sequence number. Sent as the inner message with a `seq` field.
+    #[serde(skip)]
+    Sequenced {
+        seq: u64,
+        message: Box<ClientResponseMessage>,
+    },
+    Welcome {
+        server_version: String,
+        protocol_version: u32,
+    },
+    /// The client speaks a protocol the server does not. The connection is closed.
+    UnsupportedVersion {
+        min: u32,
+        max: u32,
+    },
     RoomJoined {
         room_name: String,
-        hashed_password: String,
+        /// None for a room anyone may join.
+        hashed_password: Option<String>,
+        password_protected: bool,
         users: Vec<UserData>,
         votes_cast: usize,
+        host_ids: Vec<String>,
+        paused: bool,
+        scale_values: HashMap<String, Scale>,
+        selected_scale_name: String,
+        last_randomized: Option<String>,
+        anonymous_reveal: bool,
+        reveal_policy: RevealPolicy,
+        reveal_on_complete: bool,
+        unique_names: bool,
+        auto_new_vote: bool,
+        anonymize_history: bool,
+        abstentions_count: bool,
+        locked: bool,
+        passive_ids: Vec<String>,
+        topic: Option<String>,
+        reference: Option<Reference>,
+        /// Sequence number of the last broadcast of the room.
+        seq: u64,
     },
+    /// `user_ids` is everyone in the room after the join, so clients can check their view.
     UserJoined {
         room_name: String,
         user: UserData,
+        user_ids: Vec<String>,
+        /// False for a spectator.
+        active: bool,
     },
+    /// `user_ids` is everyone in the room after the leave, so clients can check their view.
     UserLeft {
         room_name: String,
         user_id: String,
+        user_ids: Vec<String>,
+    },
+    /// The connection of the user dropped. They leave unless they resume soon.
+    UserDisconnected {
+        room_name: String,
+        user_id: String,
+    },
+    UserReconnected {
+        room_name: String,
+        user_id: String,
     },
+    /// The user as seen in that room, where their name may have been made unique.
     UserUpdated {
+        room_name: String,
         user: UserData,
     },
+    UserActiveChanged {
+        room_name: String,
+        user_id: String,
+        active: bool,
+    },
+    /// Several members changed at once, only those that changed are listed.
+    AllActiveChanged {
+        room_name: String,
+        user_ids: Vec<String>,
+        active: bool,
+    },
     OwnData {
         user: UserData,
+        /// Lets a new connection take back this user id, after this one drops.
+        resume_token: String,
     },
     OwnVote {
         room_name: String,
-        size: u64,
+        size: Option<String>,
     },
     VoteStatus {
         room_name: String,
         votes: HashMap<String, bool>,
     },
+    /// The summary is null when no size was voted.
     VoteResults {
         room_name: String,
-        votes: HashMap<String, u64>,
+        votes: HashMap<String, Option<String>>,
+        summary: Option<VoteSummary>,
+        /// In relative mode, the summary in the sizes of the reference. Null otherwise.
+        implied_summary: Option<VoteSummary>,
+        /// How sure each voter that said so is.
+        confidence: HashMap<String, u8>,
+    },
+    /// Results without saying who voted what.
+    AnonymousVoteResults {
+        room_name: String,
+        votes: Vec<Option<String>>,
+        summary: Option<VoteSummary>,
+        implied_summary: Option<VoteSummary>,
+        /// Sorted apart from the votes, so they cannot be matched.
+        confidence: Vec<u8>,
+    },
+    /// Every member of the room, sorted by user id.
+    Users {
+        room_name: String,
+        members: Vec<Member>,
+    },
+    TimerTick {
+        room_name: String,
+        remaining: u64,
+    },
+    TimerExpired {
+        room_name: String,
+    },
+    /// Who voted the smallest and the largest size of a revealed round. Only for numeric votes.
+    Outliers {
+        room_name: String,
+        lowest: Vec<String>,
+        highest: Vec<String>,
+    },
+    /// The finished rounds of the room, oldest first.
+    VoteHistory {
+        room_name: String,
+        rounds: Vec<Round>,
+    },
+    SessionSummary {
+        summary: SessionSummary,
+    },
+    RoomStatistics {
+        room_name: String,
+        statistics: RoomStatistics,
+    },
+    AnonymousRevealChanged {
+        room_name: String,
+        anonymous: bool,
+    },
+    ReferenceChanged {
+        room_name: String,
+        reference: Option<Reference>,
+    },
+    LockChanged {
+        room_name: String,
+        locked: bool,
+    },
+    RevealOnCompleteChanged {
+        room_name: String,
+        reveal_on_complete: bool,
+    },
+    RevealPolicyChanged {
+        room_name: String,
+        policy: RevealPolicy,
+    },
+    UniqueNamesChanged {
+        room_name: String,
+        unique: bool,
+    },
+    AbstentionsCountChanged {
+        room_name: String,
+        abstentions_count: bool,
+    },
+    AutoNewVoteChanged {
+        room_name: String,
+        auto_new_vote: bool,
+    },
+    AnonymizeHistoryChanged {
+        room_name: String,
+        anonymize_history: bool,
     },
     NewVote {
         room_name: String,
     },
+    TicketsLoaded {
+        room_name: String,
+        remaining: usize,
+    },
+    /// The next ticket of the queue is the topic of a new round.
+    TicketAdvanced {
+        room_name: String,
+        current: String,
+        remaining: usize,
+    },
+    /// There was no ticket left to advance to.
+    QueueFinished {
+        room_name: String,
+    },
+    /// The agreed size of every ticket estimated so far, in the order they were estimated.
+    Estimates {
+        room_name: String,
+        items: Vec<(String, String)>,
+    },
+    /// What the rounds are about. Null when not set.
+    TopicChanged {
+        room_name: String,
+        topic: Option<String>,
+    },
+    /// Only the listed users vote again, everyone else keeps their vote.
+    RevoteRequested {
+        room_name: String,
+        user_ids: Vec<String>,
+    },
+    SubPollCreated {
+        room_name: String,
+        poll_id: String,
+    },
+    SubPollStatus {
+        room_name: String,
+        poll_id: String,
+        votes: HashMap<String, bool>,
+    },
+    SubPollResults {
+        room_name: String,
+        poll_id: String,
+        votes: HashMap<String, Option<String>>,
+        summary: Option<VoteSummary>,
+    },
+    SubPollError {
+        room_name: String,
+        poll_id: String,
+        reason: String,
+    },
     AlreadyInRoom {
         room_name: String,
     },
     WrongPassword {
         room_name: String,
     },
+    /// The creator of the room removed the user from it.
+    Kicked {
+        room_name: String,
+    },
+    /// A host locked the room, no one else may join it.
+    RoomLocked {
+        room_name: String,
+    },
+    /// The room already has as many users as it may have.
+    RoomFull {
+        room_name: String,
+    },
+    /// The room was created moments ago with another password, most likely by someone
+    /// trying to create it at the same time.
+    RoomExistsWithDifferentPassword {
+        room_name: String,
+    },
+    LeftRoom {
+        room_name: String,
+    },
+    /// The connection keeps its own id, the one asked for is unknown or still connected.
+    ResumeFailed {
+        room_name: String,
+        user_id: String,
+    },
+    /// The connection took back the id of a dropped one.
+    Resumed {
+        room_name: String,
+        user: UserData,
+        is_host: bool,
+    },
+    /// The user sent a message to a room they are not in.
+    NotInRoom {
+        room_name: String,
+    },
     Randomized {
         room_name: String,
         selected_user_id: String,
     },
+    /// The room was randomized too recently, try again later.
+    TooSoon {
+        room_name: String,
+    },
+    LastRandomized {
+        room_name: String,
+        selected_user_id: Option<String>,
+    },
+    HostsChanged {
+        room_name: String,
+        host_ids: Vec<String>,
+    },
+    PauseChanged {
+        room_name: String,
+        paused: bool,
+    },
+    /// The room was closed after a long time without activity. Its members are no longer in it.
+    RoomClosedIdle {
+        room_name: String,
+    },
+    RoomPaused {
+        room_name: String,
+    },
+    InvalidVote {
+        room_name: String,
+        size: String,
+    },
+    InvalidConfidence {
+        room_name: String,
+        confidence: u8,
+    },
+    NotAuthorized {
+        room_name: String,
+    },
+    ScaleChanged {
+        room_name: String,
+        selected_scale_name: String,
+        values: Vec<String>,
+    },
+    ScalesUpdated {
+        room_name: String,
+        scale_values: HashMap<String, Scale>,
+    },
+    ScaleError {
+        room_name: String,
+        reason: String,
+    },
+    StillThere,
+    PingResults {
+        responsive: usize,
+        unresponsive: usize,
+    },
+    NotAdmin,
+    RoomSnapshot {
+        snapshot: RoomSnapshot,
+    },
+    RoomRestored {
+        room_name: String,
+    },
+    /// The scales every room starts with.
+    ScaleCatalog {
+        scales: HashMap<String, Scale>,
+    },
+    /// The scale a room was to be created with cannot be used. The room was not created.
+    InvalidScale {
+        room_name: String,
+        reason: String,
+    },
+    InvalidAvatarStyle {
+        style: String,
+    },
+    InvalidColor {
+        color: String,
+    },
     InvalidRoomName,
+    /// The public rooms, sorted by name.
+    RoomList {
+        rooms: Vec<RoomSummary>,
+    },
+    RoomNameTaken {
+        room_name: String,
+    },
+    RoomRenamed {
+        old_name: String,
+        new_name: String,
+    },
+    ServerAtCapacity,
+    /// The connection tried to join rooms too often, it has to wait a bit.
+    TooManyRequests,
+    ServerShuttingDown,
     VotingOver,
-    CannotJoinMultipleRooms,
+    /// A message from the client could not be read. The raw message is cut if too long. For a
+    /// MessagePack message, raw is what the decoder said about it.
+    ParseError {
+        reason: ParseErrorReason,
+        raw: String,
+    },
     Error {
         msg: String,
     },
 }
+
+/// Why a message from the client could not be read.
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorReason {
+    /// Not valid JSON.
+    Syntax,
+    /// The JSON ended before the message did.
+    Incomplete,
+    UnknownType,
+    MissingField,
+    /// A field has the wrong type or value.
+    InvalidData,
+}
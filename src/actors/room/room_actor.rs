@@ -16,112 +16,496 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+mod active;
+mod broadcast;
+mod disconnect;
+mod estimates;
+mod history;
+mod host;
+mod idle;
 mod join_room;
 mod leave_room;
+mod names;
+mod outliers;
+mod pause;
+mod pool;
+mod record;
+mod relative;
+mod rename;
+mod replay;
+mod reset;
+mod reveal;
+mod scale;
+mod shutdown;
+mod snapshot;
+mod sub_poll;
+mod summary;
+mod tickets;
+mod timer;
+mod topic;
 mod vote;
 
-use crate::actors::messages::{ClientResponseMessage, RoomMessage};
-use crate::data::UserData;
-use actix::{Actor, Context, Handler, Recipient};
-use std::collections::HashMap;
+use crate::actors::messages::{
+    ClientResponseMessage, GetMemberCounts, GetRoomSummary, Recycle, Retire, RoomMessage, Shutdown,
+};
+use crate::config::Config;
+use crate::data::{MemberCounts, Reference, RevealPolicy, RoomSummary, Round, Scale, UserData};
+use crate::redact;
+use actix::{Actor, ActorContext, Context, Handler, Recipient, SpawnHandle};
+use estimates::Estimate;
 use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+use sub_poll::SubPoll;
+use timer::Timer;
+use tracing::{debug, error, info_span, warn, Span};
 
 pub struct RoomActor {
     name: String,
-    hashed_password: String,
+    /// None for a room anyone may join.
+    hashed_password: Option<String>,
     user_map: HashMap<String, ConnectionInfo>,
-    vote_map: HashMap<String, u64>,
+    /// Votes of the current round. Abstentions are recorded as `None`.
+    vote_map: HashMap<String, Option<String>>,
+    /// How sure the voters of the current round are, for those that said so. Only entries of
+    /// users in the vote map count.
+    confidence_map: HashMap<String, u8>,
     room_manager: Recipient<RoomMessage>,
+    /// Results were revealed to everyone, even if not everyone has voted.
     voting_over: bool,
+    /// Results were privately revealed to the host, but not yet to everyone.
+    revealed_to_host: bool,
+    /// Results are revealed without saying who voted what.
+    anonymous_reveal: bool,
+    reveal_policy: RevealPolicy,
+    /// What the current round is about.
+    current_topic: Option<String>,
+    /// Agreed size per topic, in the order they were estimated.
+    estimates: Vec<Estimate>,
+    /// Tickets to estimate next, in order.
+    ticket_queue: VecDeque<String>,
+    /// No one else may join the room.
+    locked: bool,
+    /// Results are revealed as soon as the quorum is reached. Otherwise they wait for a reveal.
+    reveal_on_complete: bool,
+    /// Item votes on the relative scale are compared to.
+    reference: Option<Reference>,
+    /// Members with the same name get a suffix to tell them apart.
+    unique_names: bool,
+    /// A new round starts as soon as the results are revealed to everyone.
+    auto_new_vote: bool,
+    /// Names are stripped from the rounds of the history once the configured delay is over.
+    anonymize_history: bool,
+    /// Abstentions count toward the quorum. Otherwise a round cannot end on abstentions alone.
+    abstentions_count: bool,
+    /// The host attributed the anonymously revealed votes of the current round.
+    votes_attributed: bool,
+    host_ids: HashSet<String>,
+    /// User that created the room, the only one who may kick.
+    creator_id: Option<String>,
+    paused: bool,
+    scale_values: HashMap<String, Scale>,
+    selected_scale_name: String,
+    /// Scale to select once the host stops changing it, with the timer that will select it.
+    pending_scale: Option<(String, SpawnHandle)>,
+    /// User selected by the last randomize of the current round.
+    last_randomized: Option<String>,
+    /// When the room was last randomized, to enforce the cooldown between randomizes.
+    last_randomized_at: Option<Instant>,
+    /// Items estimated in parallel with the main round, by id.
+    sub_polls: HashMap<String, SubPoll>,
+    /// Countdown shown to the room, if one is running.
+    timer: Option<Timer>,
+    /// When the current round started.
+    round_started_at: Instant,
+    /// Finished rounds, oldest first.
+    history: VecDeque<Round>,
+    /// Names of everyone who joined the room, by user id.
+    participants: HashMap<String, String>,
+    /// When the room was created, for the duration of the session.
+    created_at: Instant,
+    /// Last vote, join or scale change. The room closes after being idle for too long.
+    last_activity: Instant,
+    /// Sequence number of the last broadcast.
+    seq: u64,
+    /// The latest broadcasts, oldest first, for clients that missed them.
+    recent_broadcasts: VecDeque<(u64, ClientResponseMessage)>,
+    /// Sends the broadcasts of the room once it got too large to send them at once.
+    broadcaster: Option<broadcast::Broadcaster>,
+    /// Last VoteStatus sent in the current round, so identical ones are not sent again.
+    last_vote_status: Option<HashMap<String, bool>>,
+    config: Config,
 }
 
 impl RoomActor {
     pub fn new(
         name: String,
-        password: String,
+        password: Option<String>,
         password_is_hash: bool,
+        custom_scale: Option<Scale>,
         room_manager: Recipient<RoomMessage>,
+        config: Config,
     ) -> RoomActor {
         let hashed_password = compute_password(password, password_is_hash);
+        let mut scale_values = RoomActor::scale_catalog();
+        let selected_scale_name = match custom_scale {
+            None => scale::DEFAULT_SCALE.to_string(),
+            Some(scale) => {
+                let name = scale.name.clone();
+                scale_values.insert(name.clone(), scale);
+                name
+            }
+        };
+
         RoomActor {
             name,
             hashed_password,
             user_map: HashMap::new(),
             vote_map: HashMap::new(),
+            confidence_map: HashMap::new(),
             room_manager,
             voting_over: false,
+            revealed_to_host: false,
+            anonymous_reveal: false,
+            reveal_policy: RevealPolicy::Host,
+            current_topic: None,
+            ticket_queue: VecDeque::new(),
+            estimates: Vec::new(),
+            locked: false,
+            reveal_on_complete: true,
+            reference: None,
+            unique_names: false,
+            auto_new_vote: false,
+            anonymize_history: false,
+            abstentions_count: true,
+            votes_attributed: false,
+            host_ids: HashSet::new(),
+            creator_id: None,
+            paused: false,
+            scale_values,
+            selected_scale_name,
+            pending_scale: None,
+            last_randomized: None,
+            last_randomized_at: None,
+            sub_polls: HashMap::new(),
+            timer: None,
+            round_started_at: Instant::now(),
+            history: VecDeque::new(),
+            participants: HashMap::new(),
+            created_at: Instant::now(),
+            last_activity: Instant::now(),
+            seq: 0,
+            recent_broadcasts: VecDeque::new(),
+            last_vote_status: None,
+            broadcaster: None,
+            config,
         }
     }
 }
 
 impl Actor for RoomActor {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.watch_idle(ctx);
+        self.watch_history(ctx);
+    }
+}
+
+impl Handler<Shutdown> for RoomActor {
+    type Result = Result<(), ()>;
+
+    fn handle(&mut self, _: Shutdown, ctx: &mut Context<Self>) -> Self::Result {
+        self.drain(ctx);
+        Ok(())
+    }
+}
+
+impl Handler<Recycle> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Recycle, _: &mut Context<Self>) -> Self::Result {
+        self.recycle(
+            msg.room_name,
+            msg.password,
+            msg.password_is_hash,
+            msg.custom_scale,
+        );
+    }
+}
+
+impl Handler<Retire> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, _: Retire, ctx: &mut Context<Self>) -> Self::Result {
+        ctx.stop();
+    }
+}
+
+impl Handler<GetMemberCounts> for RoomActor {
+    type Result = Result<MemberCounts, ()>;
+
+    fn handle(&mut self, _: GetMemberCounts, _: &mut Context<Self>) -> Self::Result {
+        Ok(self.member_counts())
+    }
+}
+
+impl Handler<GetRoomSummary> for RoomActor {
+    type Result = Result<RoomSummary, ()>;
+
+    fn handle(&mut self, _: GetRoomSummary, _: &mut Context<Self>) -> Self::Result {
+        Ok(RoomSummary {
+            name: self.name.clone(),
+            members: self.member_counts(),
+            selected_scale_name: self.selected_scale_name.clone(),
+            round_in_progress: !self.voting_over(),
+            password_protected: self.hashed_password.is_some(),
+            topic: self.current_topic.clone(),
+        })
+    }
 }
 
 impl Handler<RoomMessage> for RoomActor {
     type Result = ();
 
     fn handle(&mut self, msg: RoomMessage, ctx: &mut Context<Self>) -> Self::Result {
+        let _span = self.span().entered();
+        self.record_request(&msg);
         match msg {
             RoomMessage::JoinRoom {
                 password,
                 password_is_hash,
                 user,
                 recipient,
+                spectator,
                 ..
-            } => self.join_room(password, password_is_hash, user, recipient),
+            } => self.join_room(password, password_is_hash, user, recipient, spectator),
             RoomMessage::LeaveRoom { user_id, .. } => self.leave_room(user_id, ctx),
-            RoomMessage::Vote { user_id, size, .. } => self.vote(user_id, size),
-            RoomMessage::NewVote { user_id, .. } => self.new_vote(user_id),
+            RoomMessage::Vote {
+                user_id,
+                size,
+                confidence,
+                ..
+            } => self.vote(user_id, size, confidence),
+            RoomMessage::Abstain { user_id, .. } => self.abstain(user_id),
+            RoomMessage::StartTimer {
+                user_id,
+                seconds,
+                reveal_on_timeout,
+                ..
+            } => self.start_timer(user_id, seconds, reveal_on_timeout, ctx),
+            RoomMessage::ExportEstimates { user_id, .. } => self.export_estimates(user_id),
+            RoomMessage::SetEstimate {
+                user_id,
+                topic,
+                size,
+                ..
+            } => self.set_estimate(user_id, topic, size),
+            RoomMessage::NextTicket { user_id, .. } => self.next_ticket(user_id),
+            RoomMessage::LoadTickets {
+                user_id, tickets, ..
+            } => self.load_tickets(user_id, tickets),
+            RoomMessage::SetTopic { user_id, topic, .. } => self.set_topic(user_id, topic),
+            RoomMessage::NewVote { user_id, topic, .. } => self.new_vote(user_id, topic),
+            RoomMessage::RevealSubPoll {
+                user_id, poll_id, ..
+            } => self.reveal_sub_poll(user_id, poll_id),
+            RoomMessage::SubPollVote {
+                user_id,
+                poll_id,
+                size,
+                ..
+            } => self.sub_poll_vote(user_id, poll_id, size),
+            RoomMessage::CreateSubPoll {
+                user_id, poll_id, ..
+            } => self.create_sub_poll(user_id, poll_id),
+            RoomMessage::ResetRoom { user_id, .. } => self.reset_room(user_id),
+            RoomMessage::GetRoomStatistics { user_id, .. } => self.get_room_statistics(user_id),
+            RoomMessage::GetSessionSummary { user_id, .. } => self.get_session_summary(user_id),
+            RoomMessage::GetUsers { user_id, .. } => self.get_users(user_id),
+            RoomMessage::GetHistory { user_id, .. } => self.get_history(user_id),
+            RoomMessage::GetVoteStatus { user_id, .. } => self.get_vote_status(user_id),
+            RoomMessage::RevoteOutliers { user_id, .. } => self.revote_outliers(user_id),
+            RoomMessage::RevoteDissenters { user_id, .. } => self.revote_dissenters(user_id),
             RoomMessage::UserUpdated { user } => self.user_updated(user),
-            RoomMessage::Randomize { .. } => self.randomize(),
-            _ => println!("RoomActor: Unhandled message."),
+            RoomMessage::Resume {
+                user_id, since_seq, ..
+            } => self.resume(user_id, since_seq),
+            RoomMessage::Reconnected {
+                user_id,
+                since_seq,
+                recipient,
+            } => self.reconnected(user_id, since_seq, recipient, ctx),
+            RoomMessage::UserDropped { user_id } => self.user_dropped(user_id, ctx),
+            RoomMessage::SetAllActive {
+                user_id, active, ..
+            } => self.set_all_active(user_id, active),
+            RoomMessage::UpdateActive {
+                user_id, active, ..
+            } => self.update_active(user_id, active),
+            RoomMessage::Randomize { user_id, .. } => self.randomize(user_id),
+            RoomMessage::GetLastRandomize { user_id, .. } => self.get_last_randomize(user_id),
+            RoomMessage::PauseRoom { user_id, .. } => self.set_paused(user_id, true),
+            RoomMessage::ResumeRoom { user_id, .. } => self.set_paused(user_id, false),
+            RoomMessage::ChangeScale {
+                user_id,
+                selected_scale_name,
+                ..
+            } => self.change_scale(user_id, selected_scale_name, ctx),
+            RoomMessage::CreateScale { user_id, scale, .. } => self.create_scale(user_id, scale),
+            RoomMessage::DeleteScale { user_id, name, .. } => self.delete_scale(user_id, name),
+            RoomMessage::RevealToHost { user_id, .. } => self.reveal_to_host(user_id),
+            RoomMessage::RevealToAll { user_id, .. } => self.reveal_to_all(user_id),
+            RoomMessage::SetAnonymousReveal {
+                user_id, anonymous, ..
+            } => self.set_anonymous_reveal(user_id, anonymous),
+            RoomMessage::SetReference {
+                user_id, reference, ..
+            } => self.set_reference(user_id, reference),
+            RoomMessage::RenameRoom {
+                user_id, new_name, ..
+            } => self.rename_room(user_id, new_name),
+            RoomMessage::SetName { new_name } => self.set_name(new_name),
+            RoomMessage::SetLocked {
+                user_id, locked, ..
+            } => self.set_locked(user_id, locked),
+            RoomMessage::SetRevealOnComplete {
+                user_id,
+                reveal_on_complete,
+                ..
+            } => self.set_reveal_on_complete(user_id, reveal_on_complete),
+            RoomMessage::SetRevealPolicy {
+                user_id, policy, ..
+            } => self.set_reveal_policy(user_id, policy),
+            RoomMessage::SetUniqueNames {
+                user_id, unique, ..
+            } => self.set_unique_names(user_id, unique),
+            RoomMessage::SetAbstentionsCount {
+                user_id,
+                abstentions_count,
+                ..
+            } => self.set_abstentions_count(user_id, abstentions_count),
+            RoomMessage::SetAutoNewVote {
+                user_id,
+                auto_new_vote,
+                ..
+            } => self.set_auto_new_vote(user_id, auto_new_vote),
+            RoomMessage::SetAnonymizeHistory {
+                user_id,
+                anonymize_history,
+                ..
+            } => self.set_anonymize_history(user_id, anonymize_history),
+            RoomMessage::AttributeVotes { user_id, .. } => self.attribute_votes(user_id),
+            RoomMessage::AddCoHost {
+                user_id,
+                co_host_id,
+                ..
+            } => self.add_co_host(user_id, co_host_id),
+            RoomMessage::Kick {
+                user_id, kicked_id, ..
+            } => self.kick(user_id, kicked_id, ctx),
+            RoomMessage::TransferHost {
+                user_id,
+                new_host_id,
+                ..
+            } => self.transfer_host(user_id, new_host_id),
+            RoomMessage::RemoveCoHost {
+                user_id,
+                co_host_id,
+                ..
+            } => self.remove_co_host(user_id, co_host_id),
+            RoomMessage::SnapshotRoom { recipient, .. } => self.send_snapshot(recipient),
+            RoomMessage::RestoreRoom {
+                snapshot,
+                recipient,
+                ..
+            } => self.restore(snapshot, recipient),
+            _ => warn!("Unhandled message."),
         }
     }
 }
 
 impl RoomActor {
     fn user_updated(&mut self, user: UserData) {
+        let user = self.unique_name(user);
         match self.user_map.get_mut(&user.user_id) {
-            None => println!("RoomActor: Updating user not found in room."),
+            None => debug!("Updating user not found in room."),
             Some(conn_info) => {
                 conn_info.user = user.clone();
-                self.notify_users(ClientResponseMessage::UserUpdated { user });
+                self.participants
+                    .insert(user.user_id.clone(), user.name.clone());
+                self.notify_users(ClientResponseMessage::UserUpdated {
+                    room_name: self.name.clone(),
+                    user,
+                });
             }
         };
     }
 
-    fn notify_users(&self, msg: ClientResponseMessage) {
-        for (user_id, conn_info) in self.user_map.iter() {
+    /// Broadcasts a message to every user of the room, numbering it so it can be replayed.
+    /// Users whose connection dropped are skipped, they get it replayed if they resume.
+    fn notify_users(&mut self, msg: ClientResponseMessage) {
+        let msg = self.sequence(msg);
+        let msg = match self.chunked_broadcast(msg) {
+            None => return,
+            Some(msg) => msg,
+        };
+        for (user_id, conn_info) in self.connected_users() {
             self.notify_user(user_id, &conn_info.recipient, msg.clone());
         }
     }
 
+    /// Users whose connection has not dropped.
+    fn connected_users(&self) -> impl Iterator<Item = (&String, &ConnectionInfo)> {
+        self.user_map
+            .iter()
+            .filter(|(_, conn_info)| conn_info.dropped.is_none())
+    }
+
     fn notify_user(
         &self,
         user_id: &str,
         recipient: &Recipient<ClientResponseMessage>,
         msg: ClientResponseMessage,
     ) {
-        if let Err(err) = recipient.do_send(msg) {
-            println!("RoomActor: Unable to reach ClientActor.\nError: {}", err);
-            self.remove_user(user_id.to_owned());
+        let delivery = self.config.delivery;
+        broadcast::deliver(delivery, user_id, recipient, msg, &self.room_manager);
+    }
+
+    /// Span of everything the room logs, carrying its name.
+    fn span(&self) -> Span {
+        info_span!("room", room_name = %redact::id(&self.name))
+    }
+
+    /// Sends a message to a single user of the room, if they are still in it and their
+    /// connection has not dropped.
+    fn notify_user_id(&self, user_id: &str, msg: ClientResponseMessage) {
+        match self.user_map.get(user_id) {
+            None => debug!(user_id = %redact::id(user_id), "User not found in room."),
+            Some(conn_info) if conn_info.dropped.is_some() => {
+                debug!(user_id = %redact::id(user_id), "User connection dropped, not sending.")
+            }
+            Some(conn_info) => self.notify_user(user_id, &conn_info.recipient, msg),
         }
     }
 
     fn notify_manager(&self, msg: RoomMessage) {
         if let Err(err) = self.room_manager.do_send(msg) {
-            println!("RoomActor: Unable to reach room manager.\nError: {}", err);
+            error!(error = %err, "Unable to reach room manager.");
         }
     }
 
-    fn remove_user(&self, user_id: String) {
-        let msg = RoomMessage::UserLeft { user_id };
-        self.notify_manager(msg);
-    }
+    fn randomize(&mut self, user_id: String) {
+        let cooldown = self.config.randomize_cooldown;
+        if let Some(last) = self.last_randomized_at {
+            if last.elapsed() < cooldown {
+                let room_name = self.name.clone();
+                self.notify_user_id(&user_id, ClientResponseMessage::TooSoon { room_name });
+                return;
+            }
+        }
 
-    fn randomize(&self) {
-        let users : Vec<String> = self.user_map.keys().cloned().collect();
+        let users: Vec<String> = self.user_map.keys().cloned().collect();
         let mut user_index = 0;
         if self.user_map.len() > 1 {
             user_index = rand::thread_rng().gen_range(0..self.user_map.len());
@@ -129,24 +513,154 @@ impl RoomActor {
         let selected_user = users.get(user_index);
         let room_name = self.name.clone();
         match selected_user {
-            None => println!("RoomActor: User not found in room."),
+            None => debug!(user_id = %redact::id(&user_id), "User not found in room."),
             Some(user_id) => {
                 let selected_user_id = user_id.clone();
-                self.notify_users(ClientResponseMessage::Randomized { room_name, selected_user_id });
+                self.last_randomized = Some(selected_user_id.clone());
+                self.last_randomized_at = Some(Instant::now());
+                self.notify_users(ClientResponseMessage::Randomized {
+                    room_name,
+                    selected_user_id,
+                });
             }
         }
     }
+
+    fn get_last_randomize(&self, user_id: String) {
+        let msg = ClientResponseMessage::LastRandomized {
+            room_name: self.name.clone(),
+            selected_user_id: self.last_randomized.clone(),
+        };
+        self.notify_user_id(&user_id, msg);
+    }
 }
 
-fn compute_password(password: String, password_is_hash: bool) -> String {
+/// The hash a room keeps of its password. An empty password is no password at all.
+fn compute_password(password: Option<String>, password_is_hash: bool) -> Option<String> {
+    let password = password.filter(|password| !password.is_empty())?;
     if password_is_hash {
-        password
+        Some(password)
     } else {
-        format!("{:x}", md5::compute(password))
+        Some(format!("{:x}", md5::compute(password)))
     }
 }
 
 struct ConnectionInfo {
     user: UserData,
     recipient: Recipient<ClientResponseMessage>,
+    joined_at: Instant,
+    /// Passive users follow the room without voting.
+    active: bool,
+    /// Set while the connection of the user is dropped, removes them once the grace period
+    /// ends.
+    dropped: Option<SpawnHandle>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::time::Duration;
+
+    fn last_randomized(received: &[ClientResponseMessage]) -> Option<Option<String>> {
+        received.iter().find_map(|msg| match msg {
+            ClientResponseMessage::LastRandomized {
+                selected_user_id, ..
+            } => Some(selected_user_id.clone()),
+            _ => None,
+        })
+    }
+
+    async fn get_last_randomize(room: &TestRoom, user_id: &str) -> Option<String> {
+        room.send(RoomMessage::GetLastRandomize {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await;
+        last_randomized(&room.received(user_id).await).unwrap()
+    }
+
+    /// A room where alice randomized while she was alone, so she was the one selected.
+    async fn randomized_room() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.send(RoomMessage::Randomize {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn late_joiner_gets_the_last_randomized_user() {
+        let mut room = randomized_room().await;
+        room.join("bob").await;
+
+        let joined = room
+            .received("bob")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::RoomJoined {
+                    last_randomized, ..
+                } => Some(last_randomized),
+                _ => None,
+            });
+        assert_eq!(joined, Some(Some("alice".to_string())));
+        assert_eq!(
+            get_last_randomize(&room, "bob").await,
+            Some("alice".to_string())
+        );
+    }
+
+    #[actix_rt::test]
+    async fn new_vote_clears_the_last_randomized_user() {
+        let mut room = randomized_room().await;
+        room.join("bob").await;
+        room.send(RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: None,
+        })
+        .await;
+
+        assert_eq!(get_last_randomize(&room, "bob").await, None);
+    }
+
+    fn randomized(received: &[ClientResponseMessage]) -> usize {
+        received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::Randomized { .. }))
+            .count()
+    }
+
+    async fn randomize(room: &TestRoom) {
+        room.send(RoomMessage::Randomize {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn randomize_within_the_cooldown_is_suppressed() {
+        let mut config = test_util::config();
+        config.randomize_cooldown = Duration::from_millis(100);
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        randomize(&room).await;
+        assert_eq!(randomized(&room.received("alice").await), 1);
+
+        randomize(&room).await;
+        let received = room.received("alice").await;
+        assert_eq!(randomized(&received), 0);
+        assert!(received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::TooSoon { .. })));
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        randomize(&room).await;
+        assert_eq!(randomized(&room.received("alice").await), 1);
+    }
 }
@@ -0,0 +1,276 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::{Member, MemberCounts};
+use crate::redact;
+use tracing::debug;
+
+impl RoomActor {
+    /// Makes a member active or passive. A member going passive loses their vote, which
+    /// may end the round if everyone else has voted. Revealed results are left untouched.
+    pub(super) fn update_active(&mut self, user_id: String, active: bool) {
+        match self.user_map.get_mut(&user_id) {
+            None => {
+                debug!(
+                    user_id = %redact::id(&user_id),
+                    "User tried to change their status in a room they is not in."
+                );
+                return;
+            }
+            Some(conn_info) if conn_info.active == active => return,
+            Some(conn_info) => conn_info.active = active,
+        }
+
+        let round_closed = self.round_closed();
+        if !active && !round_closed {
+            self.vote_map.remove(&user_id);
+        }
+        self.notify_users(ClientResponseMessage::UserActiveChanged {
+            room_name: self.name.clone(),
+            user_id,
+            active,
+        });
+        if !round_closed {
+            self.send_vote_info();
+        }
+    }
+
+    /// Makes every member active or passive at once, like `update_active`.
+    pub(super) fn set_all_active(&mut self, user_id: String, active: bool) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+
+        let round_closed = self.round_closed();
+        let mut user_ids = Vec::new();
+        for (member_id, conn_info) in self.user_map.iter_mut() {
+            if conn_info.active == active {
+                continue;
+            }
+            conn_info.active = active;
+            if !active && !round_closed {
+                self.vote_map.remove(member_id);
+            }
+            user_ids.push(member_id.clone());
+        }
+        if user_ids.is_empty() {
+            return;
+        }
+
+        user_ids.sort();
+        self.notify_users(ClientResponseMessage::AllActiveChanged {
+            room_name: self.name.clone(),
+            user_ids,
+            active,
+        });
+        if !round_closed {
+            self.send_vote_info();
+        }
+    }
+
+    /// Sends the requester every member of the room, with their roles.
+    pub(super) fn get_users(&self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User asked for the users of a room they is not in."
+            );
+            return;
+        }
+
+        let members = self
+            .member_ids()
+            .into_iter()
+            .map(|member_id| Member {
+                user: self.user_map[&member_id].user.clone(),
+                active: self.is_active(&member_id),
+                host: self.is_host(&member_id),
+            })
+            .collect();
+        let msg = ClientResponseMessage::Users {
+            room_name: self.name.clone(),
+            members,
+        };
+        self.notify_user_id(&user_id, msg);
+    }
+
+    pub(super) fn is_active(&self, user_id: &str) -> bool {
+        self.user_map
+            .get(user_id)
+            .is_some_and(|conn_info| conn_info.active)
+    }
+
+    /// Everyone in the room, sorted.
+    pub(super) fn member_ids(&self) -> Vec<String> {
+        let mut member_ids: Vec<String> = self.user_map.keys().cloned().collect();
+        member_ids.sort();
+        member_ids
+    }
+
+    pub(super) fn passive_ids(&self) -> Vec<String> {
+        let mut passive_ids: Vec<String> = self
+            .user_map
+            .iter()
+            .filter(|(_, conn_info)| !conn_info.active)
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        passive_ids.sort();
+        passive_ids
+    }
+
+    pub(super) fn member_counts(&self) -> MemberCounts {
+        let active = self
+            .user_map
+            .values()
+            .filter(|conn_info| conn_info.active)
+            .count();
+        MemberCounts {
+            active,
+            passive: self.user_map.len() - active,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    fn set_all_active(user_id: &str, active: bool) -> RoomMessage {
+        RoomMessage::SetAllActive {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            active,
+        }
+    }
+
+    fn changes(received: &[ClientResponseMessage]) -> Vec<(Vec<String>, bool)> {
+        received
+            .iter()
+            .filter_map(|msg| match msg {
+                ClientResponseMessage::AllActiveChanged {
+                    user_ids, active, ..
+                } => Some((user_ids.clone(), *active)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn everyone_going_passive_drops_the_votes() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.vote("alice", "3").await;
+        room.received("bob").await;
+
+        room.send(set_all_active("alice", false)).await;
+        let received = room.received("bob").await;
+        let everyone = vec!["alice".to_string(), "bob".to_string()];
+        assert_eq!(changes(&received), [(everyone, false)]);
+        let (votes, voting_over) = room
+            .run(|act, _| (act.vote_map.len(), act.voting_over()))
+            .await;
+        assert_eq!(votes, 0);
+        assert!(!voting_over);
+    }
+
+    #[actix_rt::test]
+    async fn going_active_again_reopens_the_round() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(set_all_active("alice", false)).await;
+        room.send(set_all_active("alice", true)).await;
+
+        room.vote("alice", "3").await;
+        assert!(!room.run(|act, _| act.voting_over()).await);
+        room.vote("bob", "5").await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+    }
+
+    #[actix_rt::test]
+    async fn only_hosts_change_everyone() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.vote("alice", "3").await;
+        room.received("alice").await;
+
+        room.send(set_all_active("bob", false)).await;
+        assert!(changes(&room.received("alice").await).is_empty());
+        assert_eq!(room.run(|act, _| act.vote_map.len()).await, 1);
+    }
+
+    async fn get_users(room: &TestRoom, user_id: &str) {
+        room.send(RoomMessage::GetUsers {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn roster_matches_the_members_and_their_roles() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.join_with("carol", None, true).await;
+        room.received("alice").await;
+        room.received("bob").await;
+
+        get_users(&room, "bob").await;
+        let roster = room
+            .received("bob")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::Users { members, .. } => Some(members),
+                _ => None,
+            })
+            .unwrap();
+        let roles: Vec<_> = roster
+            .iter()
+            .map(|member| (member.user.user_id.as_str(), member.active, member.host))
+            .collect();
+        assert_eq!(
+            roles,
+            [
+                ("alice", true, true),
+                ("bob", true, false),
+                ("carol", false, false)
+            ]
+        );
+        let fields = serde_json::to_value(&roster[0]).unwrap();
+        let fields: Vec<_> = fields.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(fields, ["active", "host", "user"]);
+        assert!(room.received("alice").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn roster_is_only_for_members() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.received("alice").await;
+
+        get_users(&room, "mallory").await;
+        assert!(room.received("alice").await.is_empty());
+    }
+}
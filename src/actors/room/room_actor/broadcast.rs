@@ -0,0 +1,213 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::room::RoomActor;
+use crate::config::Delivery;
+use crate::redact;
+use actix::prelude::SendError;
+use actix::Recipient;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::{error, warn, Instrument};
+
+/// A broadcast to a large room, sent a chunk of recipients at a time.
+pub(super) struct Broadcast {
+    recipients: Vec<(String, Recipient<ClientResponseMessage>)>,
+    msg: ClientResponseMessage,
+}
+
+pub(super) type Broadcaster = UnboundedSender<Broadcast>;
+
+impl RoomActor {
+    /// Hands the broadcast to the broadcaster of the room when the room is larger than the
+    /// chunk size. Once a room has a broadcaster every broadcast goes through it, so they
+    /// keep their order. Returns the message back when it should be sent right away.
+    pub(super) fn chunked_broadcast(
+        &mut self,
+        msg: ClientResponseMessage,
+    ) -> Option<ClientResponseMessage> {
+        let chunk_size = self.config.broadcast_chunk_size;
+        if self.broadcaster.is_none() && (chunk_size == 0 || self.user_map.len() <= chunk_size) {
+            return Some(msg);
+        }
+
+        let recipients = self
+            .connected_users()
+            .map(|(user_id, conn_info)| (user_id.clone(), conn_info.recipient.clone()))
+            .collect();
+        let delivery = self.config.delivery;
+        let room_manager = self.room_manager.clone();
+        let span = self.span();
+        let broadcaster = self.broadcaster.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded();
+            let broadcast = broadcast(receiver, chunk_size, delivery, room_manager);
+            actix_rt::spawn(broadcast.instrument(span));
+            sender
+        });
+        if let Err(err) = broadcaster.unbounded_send(Broadcast { recipients, msg }) {
+            error!(error = %err, "Unable to reach the broadcaster.");
+        }
+        None
+    }
+}
+
+/// Sends the broadcasts in the order they arrive, letting the room run between chunks.
+/// Ends when the room drops its broadcaster.
+async fn broadcast(
+    mut receiver: UnboundedReceiver<Broadcast>,
+    chunk_size: usize,
+    delivery: Delivery,
+    room_manager: Recipient<RoomMessage>,
+) {
+    while let Some(broadcast) = receiver.next().await {
+        for chunk in broadcast.recipients.chunks(chunk_size.max(1)) {
+            for (user_id, recipient) in chunk {
+                deliver(
+                    delivery,
+                    user_id,
+                    recipient,
+                    broadcast.msg.clone(),
+                    &room_manager,
+                );
+            }
+            YieldNow(false).await;
+        }
+    }
+}
+
+/// Sends a message to a user, asking the manager to remove them if they are gone. Users
+/// whose connection dropped are kept for the grace period instead, so they must not be sent
+/// anything until they resume.
+pub(super) fn deliver(
+    delivery: Delivery,
+    user_id: &str,
+    recipient: &Recipient<ClientResponseMessage>,
+    msg: ClientResponseMessage,
+    room_manager: &Recipient<RoomMessage>,
+) {
+    let result = match delivery {
+        Delivery::Immediate => recipient.try_send(msg),
+        Delivery::Queued => recipient.do_send(msg),
+    };
+    match result {
+        Ok(_) => {}
+        Err(SendError::Full(_)) => {
+            warn!(user_id = %redact::id(user_id), "ClientActor mailbox is full, message dropped.")
+        }
+        Err(err) => {
+            warn!(user_id = %redact::id(user_id), error = %err, "Unable to reach ClientActor.");
+            let msg = RoomMessage::UserLeft {
+                user_id: user_id.to_owned(),
+            };
+            if let Err(err) = room_manager.do_send(msg) {
+                error!(error = %err, "Unable to reach room manager.");
+            }
+        }
+    }
+}
+
+/// Lets the other tasks of the thread run once before resuming.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::test_util::{self, Inbox, Manager, TakeRoomMessages, TestRoom};
+    use actix::Actor;
+    use std::time::Duration;
+
+    const BURST: usize = 40;
+
+    /// Delivers a burst to a member without letting their actor run, so the mailbox fills.
+    /// Returns how many messages the member got and what the manager was told.
+    async fn burst(delivery: Delivery) -> (usize, Vec<RoomMessage>) {
+        let inbox = Inbox::default().start();
+        let manager = Manager::default().start();
+        let recipient = inbox.clone().recipient();
+        let room_manager = manager.clone().recipient();
+        for _ in 0..BURST {
+            let msg = ClientResponseMessage::VotingOver;
+            deliver(delivery, "alice", &recipient, msg, &room_manager);
+        }
+        let received = test_util::take(&inbox).await.len();
+        (received, manager.send(TakeRoomMessages).await.unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn immediate_delivery_drops_on_full_mailbox() {
+        let (received, manager_received) = burst(Delivery::Immediate).await;
+        assert!(received < BURST);
+        assert!(manager_received.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn queued_delivery_keeps_every_message() {
+        let (received, manager_received) = burst(Delivery::Queued).await;
+        assert_eq!(received, BURST);
+        assert!(manager_received.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn large_room_gets_every_chunk_and_keeps_answering() {
+        let mut config = test_util::config();
+        config.broadcast_chunk_size = 5;
+        config.delivery = Delivery::Queued;
+        let mut room = TestRoom::start(config);
+        let user_ids: Vec<String> = (0..40).map(|i| format!("user{}", i)).collect();
+        for user_id in user_ids.iter() {
+            room.join(user_id).await;
+        }
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        for user_id in user_ids.iter() {
+            room.received(user_id).await;
+        }
+
+        let members = room.run(|act, _| {
+            act.notify_users(ClientResponseMessage::VotingOver);
+            act.user_map.len()
+        });
+        let members = actix_rt::time::timeout(Duration::from_secs(1), members).await;
+        assert_eq!(members.unwrap(), user_ids.len());
+
+        actix_rt::time::delay_for(Duration::from_millis(50)).await;
+        for user_id in user_ids.iter() {
+            let received = room.received(user_id).await;
+            let got_it = received
+                .iter()
+                .any(|msg| matches!(msg, ClientResponseMessage::VotingOver));
+            assert!(got_it, "{} missed the broadcast", user_id);
+        }
+    }
+}
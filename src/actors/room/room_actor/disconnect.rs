@@ -0,0 +1,162 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::room::RoomActor;
+use crate::redact;
+use actix::{AsyncContext, Context};
+use tracing::debug;
+
+impl RoomActor {
+    /// Keeps the user in the room for the grace period. They leave when it ends, unless they
+    /// resume on a new connection before that. Only this room is left, the grace period of
+    /// their other rooms ends on its own.
+    pub(super) fn user_dropped(&mut self, user_id: String, ctx: &mut Context<Self>) {
+        let grace = self.config.disconnect_grace;
+        let conn_info = match self.user_map.get_mut(&user_id) {
+            None => {
+                debug!(user_id = %redact::id(&user_id), "User dropped from a room they is not in.");
+                return;
+            }
+            Some(conn_info) => conn_info,
+        };
+        if conn_info.dropped.is_some() {
+            return;
+        }
+
+        let timed_out_id = user_id.clone();
+        conn_info.dropped = Some(ctx.run_later(grace, move |act, _ctx| {
+            let msg = RoomMessage::LeaveRoom {
+                room_name: act.name.clone(),
+                user_id: timed_out_id,
+                recipient: None,
+            };
+            act.notify_manager(msg);
+        }));
+        self.notify_users(ClientResponseMessage::UserDisconnected {
+            room_name: self.name.clone(),
+            user_id,
+        });
+    }
+
+    /// Keeps the user in the room for good, if their connection had dropped.
+    pub(super) fn user_back(&mut self, user_id: &str, ctx: &mut Context<Self>) {
+        let dropped = self
+            .user_map
+            .get_mut(user_id)
+            .and_then(|conn_info| conn_info.dropped.take());
+        if let Some(dropped) = dropped {
+            ctx.cancel_future(dropped);
+            self.notify_users(ClientResponseMessage::UserReconnected {
+                room_name: self.name.clone(),
+                user_id: user_id.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, hang_up, take, Inbox, TestRoom};
+    use actix::Actor;
+    use std::time::Duration;
+
+    const GRACE: Duration = Duration::from_millis(100);
+
+    async fn room_with_dropped_user() -> TestRoom {
+        let mut config = test_util::config();
+        config.disconnect_grace = GRACE;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join("bob").await;
+        hang_up(room.inbox("alice")).await;
+        let user_id = "alice".to_string();
+        room.send(RoomMessage::UserDropped { user_id }).await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn broadcasts_during_the_grace_period_do_not_remove_the_user() {
+        let room = room_with_dropped_user().await;
+        room.run(|act, _| act.vote("bob".to_string(), "5".to_string(), None))
+            .await;
+
+        let removals = room
+            .manager_received()
+            .await
+            .into_iter()
+            .filter(|msg| matches!(msg, RoomMessage::UserLeft { .. }))
+            .count();
+        assert_eq!(removals, 0);
+        assert!(room.run(|act, _| act.user_map.contains_key("alice")).await);
+    }
+
+    #[actix_rt::test]
+    async fn reconnect_within_the_grace_period_keeps_the_seat() {
+        let room = room_with_dropped_user().await;
+        room.run(|act, _| act.vote("bob".to_string(), "5".to_string(), None))
+            .await;
+
+        let inbox = Inbox::default().start();
+        let msg = RoomMessage::Reconnected {
+            user_id: "alice".to_string(),
+            since_seq: None,
+            recipient: inbox.clone().recipient(),
+        };
+        room.send(msg).await;
+        actix_rt::time::delay_for(GRACE * 2).await;
+
+        let received = take(&inbox).await;
+        assert!(matches!(
+            received.first(),
+            Some(ClientResponseMessage::Resumed { .. })
+        ));
+        assert!(received.iter().any(|msg| matches!(
+            msg,
+            ClientResponseMessage::UserReconnected { user_id, .. } if user_id == "alice"
+        )));
+        let left = room.manager_received().await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                RoomMessage::LeaveRoom { .. } | RoomMessage::UserLeft { .. }
+            )
+        });
+        assert!(!left);
+        assert!(room.run(|act, _| act.user_map.contains_key("alice")).await);
+    }
+
+    #[actix_rt::test]
+    async fn user_leaves_once_the_grace_period_ends() {
+        let room = room_with_dropped_user().await;
+        let disconnected = room.received("bob").await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::UserDisconnected { user_id, .. } if user_id == "alice"
+            )
+        });
+        assert!(disconnected);
+
+        actix_rt::time::delay_for(GRACE * 2).await;
+        let left =
+            room.manager_received().await.into_iter().any(
+                |msg| matches!(msg, RoomMessage::LeaveRoom { user_id, .. } if user_id == "alice"),
+            );
+        assert!(left);
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use tracing::debug;
+
+/// The agreed size of a ticket.
+pub(super) struct Estimate {
+    topic: String,
+    size: String,
+    /// Set by a host. Later reveals of the same topic do not replace it.
+    explicit: bool,
+}
+
+impl RoomActor {
+    /// Keeps the most voted size of the revealed round as the estimate of its topic.
+    pub(super) fn record_estimate(&mut self) {
+        let topic = match &self.current_topic {
+            None => return,
+            Some(topic) => topic.clone(),
+        };
+        if let Some(size) = self.mode() {
+            self.store_estimate(topic, size, false);
+        }
+    }
+
+    /// Sets the agreed size of a ticket, overriding the one the votes gave.
+    pub(super) fn set_estimate(&mut self, user_id: String, topic: String, size: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        let topic = topic.trim().to_string();
+        if topic.is_empty() || size.trim().is_empty() {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to set an estimate without a topic or a size."
+            );
+            return;
+        }
+
+        self.store_estimate(topic, size, true);
+    }
+
+    fn store_estimate(&mut self, topic: String, size: String, explicit: bool) {
+        match self
+            .estimates
+            .iter_mut()
+            .find(|estimate| estimate.topic == topic)
+        {
+            None => self.estimates.push(Estimate {
+                topic,
+                size,
+                explicit,
+            }),
+            Some(estimate) if estimate.explicit && !explicit => {}
+            Some(estimate) => {
+                estimate.size = size;
+                estimate.explicit = explicit;
+            }
+        }
+    }
+
+    pub(super) fn export_estimates(&self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User asked for the estimates of a room they is not in."
+            );
+            return;
+        }
+
+        let items = self
+            .estimates
+            .iter()
+            .map(|estimate| (estimate.topic.clone(), estimate.size.clone()))
+            .collect();
+        let msg = ClientResponseMessage::Estimates {
+            room_name: self.name.clone(),
+            items,
+        };
+        self.notify_user_id(&user_id, msg);
+    }
+}
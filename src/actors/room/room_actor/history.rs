@@ -0,0 +1,334 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::{RoomStatistics, Round, VoteSummary};
+use crate::redact;
+use actix::{AsyncContext, Context};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How often a room checks whether names are due to be stripped from its history.
+const HISTORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+impl RoomActor {
+    /// Keeps the current round in the history if its results were revealed. Beyond the
+    /// configured maximum, the oldest rounds are dropped.
+    pub(super) fn archive_round(&mut self) {
+        if !self.voting_over() {
+            return;
+        }
+
+        let mut round = Round {
+            votes: self.vote_map.clone(),
+            summary: VoteSummary::from_votes(self.vote_map.values()),
+            topic: self.current_topic.clone(),
+            duration_secs: self.round_started_at.elapsed().as_secs(),
+            anonymous: self.anonymous_reveal && !self.votes_attributed,
+            anonymize_at: None,
+        };
+        if self.anonymize_history {
+            let delay = self.config.history_anonymize_delay;
+            if delay == Duration::from_secs(0) {
+                round.strip_names();
+            } else {
+                round.anonymize_at = Some(Instant::now() + delay);
+            }
+        }
+        self.history.push_back(round);
+        while self.history.len() > self.config.max_rounds {
+            self.history.pop_front();
+        }
+    }
+
+    /// Checks now and then whether names are due to be stripped from the history. Rounds keep
+    /// the names they were revealed with until then.
+    pub(super) fn watch_history(&self, ctx: &mut Context<Self>) {
+        let delay = self.config.history_anonymize_delay;
+        if delay == Duration::from_secs(0) {
+            return;
+        }
+        ctx.run_interval(HISTORY_CHECK_INTERVAL.min(delay), |act, _| {
+            let now = Instant::now();
+            act.history
+                .iter_mut()
+                .filter(|round| round.anonymize_at.is_some_and(|at| at <= now))
+                .for_each(Round::strip_names);
+        });
+    }
+
+    /// Rounds that end while the option is on lose their names once the configured delay is
+    /// over. Turning it off keeps the names of the rounds still waiting for it.
+    pub(super) fn set_anonymize_history(&mut self, user_id: String, anonymize_history: bool) {
+        if !self.check_host(&user_id) || self.anonymize_history == anonymize_history {
+            return;
+        }
+
+        self.anonymize_history = anonymize_history;
+        if !anonymize_history {
+            for round in self.history.iter_mut() {
+                round.anonymize_at = None;
+            }
+        }
+        self.notify_users(ClientResponseMessage::AnonymizeHistoryChanged {
+            room_name: self.name.clone(),
+            anonymize_history,
+        });
+    }
+
+    pub(super) fn get_history(&self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User asked for the history of a room they is not in."
+            );
+            return;
+        }
+
+        let msg = ClientResponseMessage::VoteHistory {
+            room_name: self.name.clone(),
+            rounds: self.history.iter().map(public_round).collect(),
+        };
+        self.notify_user_id(&user_id, msg);
+    }
+
+    /// Totals and averages over the finished rounds.
+    fn statistics(&self) -> RoomStatistics {
+        let points: Vec<f64> = self
+            .history
+            .iter()
+            .filter_map(|round| round.summary.as_ref())
+            .map(|summary| summary.average)
+            .collect();
+        let total_points: f64 = points.iter().sum();
+        let rounds = self.history.len();
+        let total_secs: u64 = self.history.iter().map(|round| round.duration_secs).sum();
+        let average_points = if points.is_empty() {
+            None
+        } else {
+            Some(total_points / points.len() as f64)
+        };
+        let average_round_secs = if rounds == 0 {
+            None
+        } else {
+            Some(total_secs as f64 / rounds as f64)
+        };
+        RoomStatistics {
+            rounds,
+            total_points,
+            average_points,
+            average_round_secs,
+        }
+    }
+
+    pub(super) fn get_room_statistics(&self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User asked for the statistics of a room they is not in."
+            );
+            return;
+        }
+
+        let msg = ClientResponseMessage::RoomStatistics {
+            room_name: self.name.clone(),
+            statistics: self.statistics(),
+        };
+        self.notify_user_id(&user_id, msg);
+    }
+}
+
+/// The round as members may see it: who voted what is left out of anonymous rounds.
+fn public_round(round: &Round) -> Round {
+    let mut round = round.clone();
+    if round.anonymous {
+        round.votes.clear();
+    }
+    round
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use crate::data::{RoomStatistics, Round};
+    use std::time::Duration;
+
+    const DELAY: Duration = Duration::from_millis(100);
+
+    /// A room that anonymizes its history, with one finished round.
+    async fn room_with_round() -> TestRoom {
+        let mut config = test_util::config();
+        config.history_anonymize_delay = DELAY;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(RoomMessage::SetAnonymizeHistory {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            anonymize_history: true,
+        })
+        .await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+        room.send(RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: None,
+        })
+        .await;
+        room
+    }
+
+    async fn history(room: &TestRoom) -> Vec<Round> {
+        room.received("alice").await;
+        room.send(RoomMessage::GetHistory {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room.received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::VoteHistory { rounds, .. } => Some(rounds),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn history_has_names_before_the_delay() {
+        let room = room_with_round().await;
+        let rounds = history(&room).await;
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(rounds[0].votes["alice"].as_deref(), Some("3"));
+        assert!(!rounds[0].anonymous);
+    }
+
+    #[actix_rt::test]
+    async fn history_has_no_names_after_the_delay() {
+        let room = room_with_round().await;
+        actix_rt::time::delay_for(DELAY * 3).await;
+
+        let rounds = history(&room).await;
+        assert_eq!(rounds.len(), 1);
+        assert!(rounds[0].anonymous);
+        assert!(rounds[0].votes.is_empty());
+        let stored_names = room
+            .run(|act, _| act.history[0].votes.contains_key("alice"))
+            .await;
+        assert!(!stored_names);
+    }
+
+    #[actix_rt::test]
+    async fn oldest_round_is_dropped_beyond_the_maximum() {
+        let mut config = test_util::config();
+        config.max_rounds = 2;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        for size in ["1", "2", "3"] {
+            room.vote("alice", size).await;
+            room.send(RoomMessage::NewVote {
+                room_name: ROOM.to_string(),
+                user_id: "alice".to_string(),
+                topic: None,
+            })
+            .await;
+        }
+
+        let sizes: Vec<Option<String>> = room
+            .run(|act, _| {
+                act.history
+                    .iter()
+                    .map(|round| round.votes["alice"].clone())
+                    .collect()
+            })
+            .await;
+        assert_eq!(sizes, vec![Some("2".to_string()), Some("3".to_string())]);
+    }
+
+    async fn statistics(room: &TestRoom) -> RoomStatistics {
+        room.received("alice").await;
+        room.send(RoomMessage::GetRoomStatistics {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room.received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::RoomStatistics { statistics, .. } => Some(statistics),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn statistics_total_and_average_the_rounds() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        for (alice, bob) in [(Some("3"), Some("5")), (Some("8"), Some("8")), (None, None)] {
+            for (user_id, size) in [("alice", alice), ("bob", bob)] {
+                match size {
+                    Some(size) => room.vote(user_id, size).await,
+                    None => {
+                        room.send(RoomMessage::Abstain {
+                            room_name: ROOM.to_string(),
+                            user_id: user_id.to_string(),
+                        })
+                        .await
+                    }
+                }
+            }
+            room.send(RoomMessage::NewVote {
+                room_name: ROOM.to_string(),
+                user_id: "alice".to_string(),
+                topic: None,
+            })
+            .await;
+        }
+        room.run(|act, _| {
+            for (round, secs) in act.history.iter_mut().zip([60, 120, 30]) {
+                round.duration_secs = secs;
+            }
+        })
+        .await;
+
+        let statistics = statistics(&room).await;
+        assert_eq!(statistics.rounds, 3);
+        assert_eq!(statistics.total_points, 12.0);
+        assert_eq!(statistics.average_points, Some(6.0));
+        assert_eq!(statistics.average_round_secs, Some(70.0));
+    }
+
+    #[actix_rt::test]
+    async fn statistics_of_a_new_room_are_empty() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+
+        let statistics = statistics(&room).await;
+        assert_eq!(statistics.rounds, 0);
+        assert_eq!(statistics.total_points, 0.0);
+        assert_eq!(statistics.average_points, None);
+        assert_eq!(statistics.average_round_secs, None);
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use tracing::debug;
+
+impl RoomActor {
+    pub(super) fn is_host(&self, user_id: &str) -> bool {
+        self.host_ids.contains(user_id)
+    }
+
+    /// Checks that the user is a host, warning them if they are not.
+    pub(super) fn check_host(&self, user_id: &str) -> bool {
+        if self.is_host(user_id) {
+            return true;
+        }
+        let room_name = self.name.clone();
+        self.notify_user_id(user_id, ClientResponseMessage::NotAuthorized { room_name });
+        false
+    }
+
+    pub(super) fn host_list(&self) -> Vec<String> {
+        let mut host_ids: Vec<String> = self.host_ids.iter().cloned().collect();
+        host_ids.sort();
+        host_ids
+    }
+
+    pub(super) fn notify_hosts(&self, msg: ClientResponseMessage) {
+        for host_id in self.host_ids.iter() {
+            self.notify_user_id(host_id, msg.clone());
+        }
+    }
+
+    pub(super) fn add_co_host(&mut self, user_id: String, co_host_id: String) {
+        if !self.check_host(&user_id) || self.is_host(&co_host_id) {
+            return;
+        }
+        if !self.user_map.contains_key(&co_host_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to promote a user that is not in the room."
+            );
+            return;
+        }
+
+        self.host_ids.insert(co_host_id);
+        self.notify_hosts_changed();
+    }
+
+    pub(super) fn remove_co_host(&mut self, user_id: String, co_host_id: String) {
+        if !self.check_host(&user_id) || !self.is_host(&co_host_id) {
+            return;
+        }
+        if self.host_ids.len() == 1 {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to remove the last host of the room."
+            );
+            return;
+        }
+
+        self.host_ids.remove(&co_host_id);
+        self.notify_hosts_changed();
+    }
+
+    /// Makes another member a host in place of the requester.
+    pub(super) fn transfer_host(&mut self, user_id: String, new_host_id: String) {
+        if !self.check_host(&user_id) || self.is_host(&new_host_id) {
+            return;
+        }
+        if !self.user_map.contains_key(&new_host_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to hand the role to a user that is not in the room."
+            );
+            return;
+        }
+
+        self.host_ids.remove(&user_id);
+        self.host_ids.insert(new_host_id);
+        self.notify_hosts_changed();
+    }
+
+    /// Removes a leaving user from the hosts. When the last host leaves, the role is handed
+    /// to the active user that has been in the room the longest, or to a passive one if no
+    /// user is active.
+    pub(super) fn host_left(&mut self, user_id: &str) {
+        if !self.host_ids.remove(user_id) {
+            return;
+        }
+
+        if self.host_ids.is_empty() {
+            // active users are preferred, passive ones may not be following the round
+            let oldest = self
+                .user_map
+                .iter()
+                .min_by_key(|(_, conn_info)| (!conn_info.active, conn_info.joined_at))
+                .map(|(user_id, _)| user_id.clone());
+            match oldest {
+                None => return,
+                Some(user_id) => self.host_ids.insert(user_id),
+            };
+        }
+        self.notify_hosts_changed();
+    }
+
+    fn notify_hosts_changed(&mut self) {
+        self.notify_users(ClientResponseMessage::HostsChanged {
+            room_name: self.name.clone(),
+            host_ids: self.host_list(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::RoomMessage;
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    fn add_co_host(user_id: &str, co_host_id: &str) -> RoomMessage {
+        RoomMessage::AddCoHost {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            co_host_id: co_host_id.to_string(),
+        }
+    }
+
+    fn remove_co_host(user_id: &str, co_host_id: &str) -> RoomMessage {
+        RoomMessage::RemoveCoHost {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            co_host_id: co_host_id.to_string(),
+        }
+    }
+
+    async fn hosts(room: &TestRoom) -> Vec<String> {
+        room.run(|act, _| act.host_list()).await
+    }
+
+    async fn room_with(users: &[&str]) -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        for user_id in users {
+            room.join(user_id).await;
+        }
+        room
+    }
+
+    #[actix_rt::test]
+    async fn hosts_promote_co_hosts() {
+        let room = room_with(&["alice", "bob"]).await;
+        room.send(add_co_host("alice", "bob")).await;
+        assert_eq!(hosts(&room).await, vec!["alice", "bob"]);
+    }
+
+    #[actix_rt::test]
+    async fn members_do_not_promote_themselves() {
+        let room = room_with(&["alice", "bob"]).await;
+        room.send(add_co_host("bob", "bob")).await;
+        assert_eq!(hosts(&room).await, vec!["alice"]);
+    }
+
+    #[actix_rt::test]
+    async fn co_hosts_are_authorized_as_hosts() {
+        let room = room_with(&["alice", "bob", "carol"]).await;
+        room.send(add_co_host("alice", "bob")).await;
+
+        room.send(add_co_host("bob", "carol")).await;
+        room.send(remove_co_host("bob", "alice")).await;
+        assert_eq!(hosts(&room).await, vec!["bob", "carol"]);
+
+        room.send(RoomMessage::PauseRoom {
+            room_name: ROOM.to_string(),
+            user_id: "carol".to_string(),
+        })
+        .await;
+        assert!(room.run(|act, _| act.paused).await);
+    }
+
+    #[actix_rt::test]
+    async fn last_host_is_not_removed() {
+        let room = room_with(&["alice"]).await;
+        room.send(remove_co_host("alice", "alice")).await;
+        assert_eq!(hosts(&room).await, vec!["alice"]);
+    }
+
+    #[actix_rt::test]
+    async fn last_host_leaving_hands_off_to_the_oldest_active_member() {
+        let mut room = room_with(&["alice"]).await;
+        room.join_with("bob", None, true).await;
+        room.join("carol").await;
+        room.join("dave").await;
+
+        room.send(RoomMessage::LeaveRoom {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            recipient: None,
+        })
+        .await;
+        assert_eq!(hosts(&room).await, vec!["carol"]);
+    }
+}
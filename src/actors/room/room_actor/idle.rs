@@ -0,0 +1,100 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use actix::{AsyncContext, Context};
+use std::time::Duration;
+use tracing::info;
+
+/// How often a room checks whether it has been idle for too long.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+impl RoomActor {
+    /// Checks now and then whether the room has been idle for too long. The checks go on
+    /// while the actor waits in the pool, but an empty or paused room is never closed by them.
+    pub(super) fn watch_idle(&self, ctx: &mut Context<Self>) {
+        let timeout = self.config.room_idle_timeout;
+        if timeout == Duration::from_secs(0) {
+            return;
+        }
+        ctx.run_interval(IDLE_CHECK_INTERVAL.min(timeout), move |act, ctx| {
+            if !act.paused && !act.user_map.is_empty() && act.last_activity.elapsed() >= timeout {
+                act.close_idle(ctx);
+            }
+        });
+    }
+
+    /// Tells everyone still in the room it is closing, and closes it.
+    fn close_idle(&mut self, ctx: &mut Context<Self>) {
+        let _span = self.span().entered();
+        info!("Closing idle room.");
+        self.notify_users(ClientResponseMessage::RoomClosedIdle {
+            room_name: self.name.clone(),
+        });
+        for (_, conn_info) in self.user_map.drain() {
+            if let Some(dropped) = conn_info.dropped {
+                ctx.cancel_future(dropped);
+            }
+        }
+        self.close(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    async fn idle_room() -> TestRoom {
+        let mut config = test_util::config();
+        config.room_idle_timeout = TIMEOUT;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room
+    }
+
+    fn closed_idle(received: Vec<ClientResponseMessage>) -> bool {
+        received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::RoomClosedIdle { .. }))
+    }
+
+    #[actix_rt::test]
+    async fn idle_room_is_closed() {
+        let room = idle_room().await;
+        actix_rt::time::delay_for(TIMEOUT * 3).await;
+        assert!(closed_idle(room.received("alice").await));
+    }
+
+    #[actix_rt::test]
+    async fn paused_room_is_not_closed() {
+        let room = idle_room().await;
+        room.send(RoomMessage::PauseRoom {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        actix_rt::time::delay_for(TIMEOUT * 3).await;
+        assert!(!closed_idle(room.received("alice").await));
+        assert!(room.run(|act, _| act.user_map.contains_key("alice")).await);
+    }
+}
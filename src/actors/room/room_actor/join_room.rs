@@ -16,76 +16,289 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::actors::messages::ClientResponseMessage;
+use crate::actors::lifecycle::{self, Event};
+use crate::actors::messages::{ClientResponseMessage, RoomMessage};
 use crate::actors::room::room_actor::{compute_password, ConnectionInfo};
 use crate::actors::room::RoomActor;
 use crate::data::UserData;
 use actix::Recipient;
 use std::borrow::Borrow;
+use std::time::{Duration, Instant};
+
+/// How long after its creation a room is considered new, so a wrong password is likely
+/// from someone trying to create the same room.
+const NEW_ROOM_WINDOW: Duration = Duration::from_secs(10);
 
 impl RoomActor {
     pub(super) fn join_room(
         &mut self,
-        password: String,
+        password: Option<String>,
         password_is_hash: bool,
         user: UserData,
         recipient: Recipient<ClientResponseMessage>,
+        spectator: bool,
     ) {
         let user_id = user.user_id.clone();
-        let hashed_password = compute_password(password, password_is_hash);
 
         if self.user_map.contains_key(&user_id) {
             self.user_already_in_room(&recipient, &user_id);
-        } else if !(self.hashed_password.eq(&hashed_password)) {
+        } else if !self.password_matches(password, password_is_hash) {
             self.wrong_password(&recipient, &user_id);
+        } else if self.locked {
+            let room_name = self.name.clone();
+            self.reject_join(
+                &recipient,
+                &user_id,
+                ClientResponseMessage::RoomLocked { room_name },
+            );
+        } else if self.is_full() {
+            let room_name = self.name.clone();
+            self.reject_join(
+                &recipient,
+                &user_id,
+                ClientResponseMessage::RoomFull { room_name },
+            );
         } else {
-            self.do_join_room(user, recipient, &user_id);
+            self.do_join_room(user, recipient, &user_id, !spectator);
         };
     }
 
+    /// Any password opens a room without one.
+    fn password_matches(&self, password: Option<String>, password_is_hash: bool) -> bool {
+        match &self.hashed_password {
+            None => true,
+            Some(hashed_password) => {
+                compute_password(password, password_is_hash).as_ref() == Some(hashed_password)
+            }
+        }
+    }
+
     fn user_already_in_room(
         &mut self,
         recipient: &Recipient<ClientResponseMessage>,
-        user_id: &String,
+        user_id: &str,
     ) {
         let room_name = self.name.clone();
         let msg = ClientResponseMessage::AlreadyInRoom { room_name };
-        self.notify_user(&user_id, &recipient, msg);
+        self.notify_user(user_id, recipient, msg);
+    }
+
+    fn wrong_password(&mut self, recipient: &Recipient<ClientResponseMessage>, user_id: &str) {
+        let room_name = self.name.clone();
+        let msg = if self.created_at.elapsed() < NEW_ROOM_WINDOW {
+            ClientResponseMessage::RoomExistsWithDifferentPassword { room_name }
+        } else {
+            ClientResponseMessage::WrongPassword { room_name }
+        };
+        self.reject_join(recipient, user_id, msg);
+    }
+
+    fn is_full(&self) -> bool {
+        let max_room_size = self.config.max_room_size;
+        max_room_size > 0 && self.user_map.len() >= max_room_size
     }
 
-    fn wrong_password(&mut self, recipient: &Recipient<ClientResponseMessage>, user_id: &String) {
+    /// Tells the user why they cannot join, and the manager that they are not in the room.
+    fn reject_join(
+        &mut self,
+        recipient: &Recipient<ClientResponseMessage>,
+        user_id: &str,
+        msg: ClientResponseMessage,
+    ) {
+        self.notify_user(user_id, recipient, msg);
         let room_name = self.name.clone();
-        let msg = ClientResponseMessage::WrongPassword { room_name };
-        self.notify_user(&user_id, &recipient, msg);
+        let user_id = user_id.to_string();
+        self.notify_manager(RoomMessage::UserRemoved { room_name, user_id });
+    }
+
+    pub(super) fn set_locked(&mut self, user_id: String, locked: bool) {
+        if !self.check_host(&user_id) || self.locked == locked {
+            return;
+        }
+
+        self.locked = locked;
+        self.notify_users(ClientResponseMessage::LockChanged {
+            room_name: self.name.clone(),
+            locked,
+        });
     }
 
+    /// Adds the user to the room. Spectators join passive, so they never hold up a round.
     fn do_join_room(
         &mut self,
         user: UserData,
         recipient: Recipient<ClientResponseMessage>,
         user_id: &String,
+        active: bool,
     ) {
+        let user = self.unique_name(user);
+        self.participants.insert(user_id.clone(), user.name.clone());
+        let mut user_ids = self.member_ids();
+        user_ids.push(user_id.clone());
+        user_ids.sort();
         let user_entered_msg = ClientResponseMessage::UserJoined {
             room_name: self.name.clone(),
             user: user.clone(),
+            user_ids,
+            active,
         };
         self.notify_users(user_entered_msg);
 
-        let connection_info = ConnectionInfo { user, recipient };
+        lifecycle::emit(Event::Join, Some(&self.name), Some(user_id));
+        if self.user_map.is_empty() {
+            self.host_ids.insert(user_id.clone());
+            self.creator_id.get_or_insert_with(|| user_id.clone());
+        }
+        let connection_info = ConnectionInfo {
+            user,
+            recipient,
+            joined_at: Instant::now(),
+            active,
+            dropped: None,
+        };
         self.user_map.insert(user_id.clone(), connection_info);
+        self.last_activity = Instant::now();
 
         let joiner = self.user_map.get(user_id).unwrap().recipient.borrow();
+        let join_msg = self.room_joined_msg();
+        self.notify_user(user_id, joiner, join_msg);
+    }
+
+    /// The current state of the room, as seen by someone joining it.
+    pub(super) fn room_joined_msg(&self) -> ClientResponseMessage {
         let users: Vec<UserData> = self
             .user_map
             .values()
             .map(|conn_info| conn_info.user.clone())
             .collect();
-        let join_msg = ClientResponseMessage::RoomJoined {
+        ClientResponseMessage::RoomJoined {
             room_name: self.name.clone(),
             hashed_password: self.hashed_password.clone(),
+            password_protected: self.hashed_password.is_some(),
             users,
             votes_cast: self.vote_map.len(),
-        };
-        self.notify_user(&user_id, joiner, join_msg);
+            host_ids: self.host_list(),
+            paused: self.paused,
+            scale_values: self.scale_values.clone(),
+            selected_scale_name: self.selected_scale_name.clone(),
+            last_randomized: self.last_randomized.clone(),
+            anonymous_reveal: self.anonymous_reveal,
+            reveal_policy: self.reveal_policy,
+            reveal_on_complete: self.reveal_on_complete,
+            unique_names: self.unique_names,
+            auto_new_vote: self.auto_new_vote,
+            anonymize_history: self.anonymize_history,
+            abstentions_count: self.abstentions_count,
+            locked: self.locked,
+            passive_ids: self.passive_ids(),
+            topic: self.current_topic.clone(),
+            reference: self.reference.clone(),
+            seq: self.seq,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::time::{Duration, Instant};
+
+    /// Who is in the room according to the membership messages a member received, the last
+    /// one being the latest view.
+    fn membership(received: &[ClientResponseMessage]) -> Option<Vec<String>> {
+        received.iter().rev().find_map(|msg| match msg {
+            ClientResponseMessage::RoomJoined { users, .. } => {
+                let mut user_ids: Vec<String> =
+                    users.iter().map(|user| user.user_id.clone()).collect();
+                user_ids.sort();
+                Some(user_ids)
+            }
+            ClientResponseMessage::UserJoined { user_ids, .. }
+            | ClientResponseMessage::UserLeft { user_ids, .. } => Some(user_ids.clone()),
+            _ => None,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn interleaved_joins_and_leaves_end_in_the_same_view() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.join("carol").await;
+        room.send(RoomMessage::LeaveRoom {
+            room_name: ROOM.to_string(),
+            user_id: "bob".to_string(),
+            recipient: None,
+        })
+        .await;
+        room.join("dave").await;
+
+        let expected = vec!["alice", "carol", "dave"];
+        let mut actual: Vec<String> = room.run(|act, _| act.member_ids()).await;
+        actual.sort();
+        assert_eq!(actual, expected);
+        for user_id in expected.iter() {
+            let view = membership(&room.received(user_id).await);
+            assert_eq!(view.unwrap(), expected, "view of {}", user_id);
+        }
+    }
+
+    fn refusals(received: &[ClientResponseMessage]) -> (bool, bool) {
+        let different = received.iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::RoomExistsWithDifferentPassword { .. }
+            )
+        });
+        let wrong = received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::WrongPassword { .. }));
+        (different, wrong)
+    }
+
+    #[actix_rt::test]
+    async fn racing_creation_with_another_password_is_explained() {
+        let mut room = TestRoom::start_with(Some("first"), test_util::config());
+        room.join_with("alice", Some("first"), false).await;
+        room.join_with("bob", Some("second"), false).await;
+
+        assert_eq!(refusals(&room.received("bob").await), (true, false));
+        let members = room.run(|act, _| act.member_ids()).await;
+        assert_eq!(members, ["alice"]);
+    }
+
+    #[actix_rt::test]
+    async fn wrong_password_for_an_older_room_is_plain() {
+        let mut room = TestRoom::start_with(Some("first"), test_util::config());
+        room.join_with("alice", Some("first"), false).await;
+        room.run(|act, _| {
+            act.created_at = Instant::now().checked_sub(Duration::from_secs(60)).unwrap();
+        })
+        .await;
+        room.join_with("bob", Some("second"), false).await;
+
+        assert_eq!(refusals(&room.received("bob").await), (false, true));
+    }
+
+    #[actix_rt::test]
+    async fn joiner_past_the_capacity_is_turned_away() {
+        let mut config = test_util::config();
+        config.max_room_size = 2;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join_with("bob", None, true).await;
+        room.received("alice").await;
+        room.received("bob").await;
+
+        room.join("carol").await;
+        let full = room.received("carol").await.into_iter().any(
+            |msg| matches!(msg, ClientResponseMessage::RoomFull { room_name } if room_name == ROOM),
+        );
+        assert!(full);
+        let members = room.run(|act, _| act.member_ids()).await;
+        assert_eq!(members, ["alice", "bob"]);
+        assert!(room.received("alice").await.is_empty());
+        assert!(room.received("bob").await.is_empty());
     }
 }
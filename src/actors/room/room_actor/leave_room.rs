@@ -16,29 +16,168 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::actors::lifecycle::{self, Event};
 use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::room::room_actor::ConnectionInfo;
 use crate::actors::room::RoomActor;
-use actix::{ActorContext, Context};
+use crate::redact;
+use actix::{ActorContext, AsyncContext, Context};
+use tracing::debug;
 
 impl RoomActor {
+    /// Every way out of the room ends here: leaving, being kicked, the grace period of a
+    /// dropped connection running out and the manager giving up on an unreachable client.
     pub(super) fn leave_room(&mut self, user_id: String, ctx: &mut Context<Self>) {
+        lifecycle::emit(Event::Leave, Some(&self.name), Some(&user_id));
+        let user_ids = self
+            .member_ids()
+            .into_iter()
+            .filter(|member_id| member_id != &user_id)
+            .collect();
         let msg = ClientResponseMessage::UserLeft {
             user_id: user_id.clone(),
             room_name: self.name.clone(),
+            user_ids,
         };
         self.notify_users(msg);
+        self.remove_member(&user_id, ctx);
+    }
 
-        self.user_map.remove(&user_id);
-        self.vote_map.remove(&user_id);
+    /// Takes the user out of every map of the room, then sees whether the round is over
+    /// without them. The room closes when no one is left.
+    fn remove_member(&mut self, user_id: &str, ctx: &mut Context<Self>) {
+        if let Some(ConnectionInfo {
+            dropped: Some(dropped),
+            ..
+        }) = self.user_map.remove(user_id)
+        {
+            ctx.cancel_future(dropped);
+        }
+        self.vote_map.remove(user_id);
+        self.confidence_map.remove(user_id);
 
+        self.host_left(user_id);
         self.send_vote_info();
 
         if self.user_map.is_empty() {
-            let msg = RoomMessage::RoomClosing {
-                room_name: self.name.clone(),
-            };
-            self.notify_manager(msg);
+            self.close(ctx);
+        }
+    }
+
+    /// Removes a member on behalf of the creator of the room. Co-hosts may not kick, so they
+    /// cannot remove the creator or each other. The kicked user is told before the others.
+    pub(super) fn kick(&mut self, user_id: String, kicked_id: String, ctx: &mut Context<Self>) {
+        if self.creator_id.as_ref() != Some(&user_id) {
+            let room_name = self.name.clone();
+            self.notify_user_id(&user_id, ClientResponseMessage::NotAuthorized { room_name });
+            return;
+        }
+        if kicked_id == user_id || !self.user_map.contains_key(&kicked_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Creator tried to kick themselves or a user that is not in the room."
+            );
+            return;
+        }
+
+        let room_name = self.name.clone();
+        let msg = ClientResponseMessage::Kicked {
+            room_name: room_name.clone(),
+        };
+        self.notify_user_id(&kicked_id, msg);
+        let msg = RoomMessage::UserRemoved {
+            room_name,
+            user_id: kicked_id.clone(),
+        };
+        self.notify_manager(msg);
+        self.leave_room(kicked_id, ctx);
+    }
+
+    /// Tells the manager the room is closing and stops, or waits in the pool for a new room.
+    pub(super) fn close(&mut self, ctx: &mut Context<Self>) {
+        self.log_summary();
+        self.cancel_timer(ctx);
+        let msg = RoomMessage::RoomClosing {
+            room_name: self.name.clone(),
+        };
+        self.notify_manager(msg);
+        // with a pool, the manager decides whether this actor is kept for another room
+        if self.config.room_pool_size == 0 {
             ctx.stop();
+        } else {
+            self.go_idle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    fn kick(user_id: &str, kicked_id: &str) -> RoomMessage {
+        RoomMessage::Kick {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            kicked_id: kicked_id.to_string(),
         }
     }
+
+    /// A room created by alice, where bob is a co-host and carol a member.
+    async fn room_with_co_host() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.join("carol").await;
+        room.send(RoomMessage::AddCoHost {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            co_host_id: "bob".to_string(),
+        })
+        .await;
+        room
+    }
+
+    fn not_authorized(received: &[ClientResponseMessage]) -> bool {
+        received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::NotAuthorized { .. }))
+    }
+
+    #[actix_rt::test]
+    async fn creator_kicks_a_member() {
+        let room = room_with_co_host().await;
+        room.send(kick("alice", "carol")).await;
+
+        let kicked = room
+            .received("carol")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::Kicked { .. }));
+        assert!(kicked);
+        let members = room.run(|act, _| act.member_ids()).await;
+        assert_eq!(members, ["alice", "bob"]);
+    }
+
+    #[actix_rt::test]
+    async fn co_host_cannot_kick_the_creator() {
+        let room = room_with_co_host().await;
+        room.received("bob").await;
+        room.send(kick("bob", "alice")).await;
+
+        assert!(not_authorized(&room.received("bob").await));
+        let members = room.run(|act, _| act.member_ids()).await;
+        assert_eq!(members, ["alice", "bob", "carol"]);
+    }
+
+    #[actix_rt::test]
+    async fn co_host_cannot_kick_members() {
+        let room = room_with_co_host().await;
+        room.received("bob").await;
+        room.send(kick("bob", "carol")).await;
+
+        assert!(not_authorized(&room.received("bob").await));
+        let members = room.run(|act, _| act.member_ids()).await;
+        assert_eq!(members, ["alice", "bob", "carol"]);
+    }
 }
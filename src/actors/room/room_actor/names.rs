@@ -0,0 +1,135 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::UserData;
+
+impl RoomActor {
+    pub(super) fn set_unique_names(&mut self, user_id: String, unique: bool) {
+        if !self.check_host(&user_id) || self.unique_names == unique {
+            return;
+        }
+
+        self.unique_names = unique;
+        self.notify_users(ClientResponseMessage::UniqueNamesChanged {
+            room_name: self.name.clone(),
+            unique,
+        });
+    }
+
+    /// When the room requires unique names and another member already has this user's name,
+    /// appends a number to it. The adjusted name only applies inside this room.
+    pub(super) fn unique_name(&self, mut user: UserData) -> UserData {
+        if !self.unique_names {
+            return user;
+        }
+
+        let taken = |name: &str| {
+            self.user_map.values().any(|conn_info| {
+                conn_info.user.user_id != user.user_id && conn_info.user.name == name
+            })
+        };
+        if !taken(&user.name) {
+            return user;
+        }
+        let mut suffix = 2;
+        while taken(&format!("{} ({})", user.name, suffix)) {
+            suffix += 1;
+        }
+        user.name = format!("{} ({})", user.name, suffix);
+        user
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    async fn room_with(unique: bool) -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(RoomMessage::SetUniqueNames {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            unique,
+        })
+        .await;
+        room.received("alice").await;
+        room
+    }
+
+    async fn rename(room: &TestRoom, user_id: &str, name: &str) {
+        let mut user = test_util::user(user_id);
+        user.name = name.to_string();
+        room.send(RoomMessage::UserUpdated { user }).await;
+    }
+
+    async fn name_of(room: &TestRoom, user_id: &'static str) -> String {
+        room.run(move |act, _| act.user_map[user_id].user.name.clone())
+            .await
+    }
+
+    #[actix_rt::test]
+    async fn identical_names_are_disambiguated() {
+        let room = room_with(true).await;
+        rename(&room, "alice", "Muppet").await;
+        rename(&room, "bob", "Muppet").await;
+
+        assert_eq!(name_of(&room, "alice").await, "Muppet");
+        assert_eq!(name_of(&room, "bob").await, "Muppet (2)");
+        let broadcast = room.received("alice").await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::UserUpdated { user, .. } if user.name == "Muppet (2)"
+            )
+        });
+        assert!(broadcast);
+    }
+
+    #[actix_rt::test]
+    async fn suffixes_skip_the_taken_ones() {
+        let mut room = room_with(true).await;
+        room.join("carol").await;
+        rename(&room, "alice", "Muppet").await;
+        rename(&room, "bob", "Muppet (2)").await;
+        rename(&room, "carol", "Muppet").await;
+
+        assert_eq!(name_of(&room, "carol").await, "Muppet (3)");
+    }
+
+    #[actix_rt::test]
+    async fn identical_names_are_kept_when_the_option_is_off() {
+        let room = room_with(false).await;
+        rename(&room, "alice", "Muppet").await;
+        rename(&room, "bob", "Muppet").await;
+
+        assert_eq!(name_of(&room, "bob").await, "Muppet");
+    }
+
+    #[actix_rt::test]
+    async fn joining_with_a_taken_name_is_disambiguated() {
+        let mut room = room_with(true).await;
+        rename(&room, "alice", "carol").await;
+        room.join("carol").await;
+
+        assert_eq!(name_of(&room, "carol").await, "carol (2)");
+    }
+}
@@ -0,0 +1,62 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+
+impl RoomActor {
+    /// Who voted the smallest and who voted the largest size, both sorted. Only numeric
+    /// votes count, and there are no outliers if they are all the same.
+    pub(super) fn outliers(&self) -> Option<(Vec<String>, Vec<String>)> {
+        let sizes: Vec<(&String, u64)> = self
+            .vote_map
+            .iter()
+            .filter_map(|(user_id, size)| Some((user_id, size.as_ref()?.parse().ok()?)))
+            .collect();
+        let min = sizes.iter().map(|(_, size)| *size).min()?;
+        let max = sizes.iter().map(|(_, size)| *size).max()?;
+        if min == max {
+            return None;
+        }
+
+        let voted = |extreme: u64| {
+            let mut user_ids: Vec<String> = sizes
+                .iter()
+                .filter(|(_, size)| *size == extreme)
+                .map(|(user_id, _)| (*user_id).clone())
+                .collect();
+            user_ids.sort();
+            user_ids
+        };
+        Some((voted(min), voted(max)))
+    }
+
+    /// Tells the room who voted the extremes, unless the votes were revealed anonymously.
+    pub(super) fn send_outliers(&mut self) {
+        if self.anonymous_reveal && !self.votes_attributed {
+            return;
+        }
+        if let Some((lowest, highest)) = self.outliers() {
+            self.notify_users(ClientResponseMessage::Outliers {
+                room_name: self.name.clone(),
+                lowest,
+                highest,
+            });
+        }
+    }
+}
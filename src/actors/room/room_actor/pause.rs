@@ -0,0 +1,123 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+
+impl RoomActor {
+    /// Pauses or resumes the room. While paused no votes are accepted.
+    pub(super) fn set_paused(&mut self, user_id: String, paused: bool) {
+        if !self.check_host(&user_id) || self.paused == paused {
+            return;
+        }
+
+        self.paused = paused;
+        self.notify_users(ClientResponseMessage::PauseChanged {
+            room_name: self.name.clone(),
+            paused,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    fn pause(user_id: &str) -> RoomMessage {
+        RoomMessage::PauseRoom {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    fn resume(user_id: &str) -> RoomMessage {
+        RoomMessage::ResumeRoom {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    async fn vote_of(room: &TestRoom, user_id: &'static str) -> Option<String> {
+        room.run(move |act, _| act.vote_map.get(user_id).cloned().flatten())
+            .await
+    }
+
+    async fn paused(room: &TestRoom) -> bool {
+        room.run(|act, _| act.paused).await
+    }
+
+    #[actix_rt::test]
+    async fn voting_is_blocked_while_paused() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(pause("alice")).await;
+        room.received("bob").await;
+
+        room.vote("bob", "3").await;
+        assert_eq!(vote_of(&room, "bob").await, None);
+        let refused = room
+            .received("bob")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::RoomPaused { .. }));
+        assert!(refused);
+    }
+
+    #[actix_rt::test]
+    async fn voting_is_allowed_after_resume() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(pause("alice")).await;
+        room.send(resume("alice")).await;
+
+        room.vote("bob", "3").await;
+        assert_eq!(vote_of(&room, "bob").await, Some("3".to_string()));
+    }
+
+    #[actix_rt::test]
+    async fn pause_state_is_broadcast() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.received("bob").await;
+
+        room.send(pause("alice")).await;
+        let received = room.received("bob").await;
+        assert!(received.iter().any(|msg| matches!(
+            msg,
+            ClientResponseMessage::PauseChanged { paused: true, .. }
+        )));
+    }
+
+    #[actix_rt::test]
+    async fn only_hosts_pause_the_room() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+
+        room.send(pause("bob")).await;
+        assert!(!paused(&room).await);
+        room.send(pause("alice")).await;
+        assert!(paused(&room).await);
+        room.send(resume("bob")).await;
+        assert!(paused(&room).await);
+    }
+}
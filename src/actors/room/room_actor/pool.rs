@@ -0,0 +1,82 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::room::RoomActor;
+use crate::data::Scale;
+
+impl RoomActor {
+    /// Turns this actor into a brand new room. Nothing of the previous room is kept.
+    pub(super) fn recycle(
+        &mut self,
+        name: String,
+        password: Option<String>,
+        password_is_hash: bool,
+        custom_scale: Option<Scale>,
+    ) {
+        *self = RoomActor::new(
+            name,
+            password,
+            password_is_hash,
+            custom_scale,
+            self.room_manager.clone(),
+            self.config.clone(),
+        );
+    }
+
+    /// Leaves the actor without a room, waiting in the pool to be recycled.
+    pub(super) fn go_idle(&mut self) {
+        self.recycle(String::new(), None, false, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::RoomMessage;
+    use crate::actors::room::RoomActor;
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    #[actix_rt::test]
+    async fn recycled_room_carries_no_leftover_state() {
+        let mut room = TestRoom::start_with(Some("secret"), test_util::config());
+        room.join_with("alice", Some("secret"), false).await;
+        room.vote("alice", "3").await;
+        room.send(RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: Some("login page".to_string()),
+        })
+        .await;
+        room.vote("alice", "5").await;
+
+        let room_manager = room.manager.clone().recipient();
+        let (recycled, fresh, history, seq) = room
+            .run(move |act, _| {
+                act.recycle("other".to_string(), None, false, None);
+                let config = act.config.clone();
+                let fresh =
+                    RoomActor::new("other".to_string(), None, false, None, room_manager, config);
+                let recycled = serde_json::to_value(act.snapshot()).unwrap();
+                let fresh = serde_json::to_value(fresh.snapshot()).unwrap();
+                (recycled, fresh, act.history.len(), act.seq)
+            })
+            .await;
+        assert_eq!(recycled, fresh);
+        assert_eq!(history, 0);
+        assert_eq!(seq, 0);
+    }
+}
@@ -0,0 +1,104 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::room::RoomActor;
+use serde::Serialize;
+use tracing::{error, info};
+
+/// An entry of a session recording, one JSON line per request or broadcast.
+/// Connections and passwords are left out.
+#[derive(Serialize)]
+#[serde(tag = "kind")]
+enum Entry<'a> {
+    Request {
+        room_name: &'a str,
+        /// Since the room was created.
+        at_ms: u128,
+        message: &'a RoomMessage,
+    },
+    Broadcast {
+        room_name: &'a str,
+        at_ms: u128,
+        seq: u64,
+        message: &'a ClientResponseMessage,
+    },
+}
+
+impl RoomActor {
+    pub(super) fn record_request(&self, message: &RoomMessage) {
+        if self.config.record_sessions {
+            self.record(Entry::Request {
+                room_name: &self.name,
+                at_ms: self.created_at.elapsed().as_millis(),
+                message,
+            });
+        }
+    }
+
+    pub(super) fn record_broadcast(&self, seq: u64, message: &ClientResponseMessage) {
+        if self.config.record_sessions {
+            self.record(Entry::Broadcast {
+                room_name: &self.name,
+                at_ms: self.created_at.elapsed().as_millis(),
+                seq,
+                message,
+            });
+        }
+    }
+
+    fn record(&self, entry: Entry) {
+        match serde_json::to_string(&entry) {
+            Ok(entry) => info!("Record: {}", entry),
+            Err(err) => error!(error = %err, "Unable to record."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::test_util::{self, Log, TestRoom};
+    use crate::data::RoomSnapshot;
+
+    /// The state of the room, with the members in a fixed order.
+    fn final_state(mut snapshot: RoomSnapshot) -> serde_json::Value {
+        snapshot.users.sort_by(|a, b| a.user_id.cmp(&b.user_id));
+        serde_json::to_value(snapshot).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn replaying_a_recording_reproduces_the_room() {
+        let (log, _guard) = Log::capture();
+
+        let mut config = test_util::config();
+        config.record_sessions = true;
+        let mut recorded = TestRoom::start(config);
+        recorded.join("alice").await;
+        recorded.join("bob").await;
+        recorded.vote("alice", "3").await;
+        recorded.vote("bob", "5").await;
+        let expected = recorded.run(|act, _| act.snapshot()).await;
+        assert!(expected.voting_over);
+
+        let recording = log.text();
+        let mut replayed = TestRoom::start(test_util::config());
+        replayed.replay(&recording).await;
+        let actual = replayed.run(|act, _| act.snapshot()).await;
+        assert_eq!(final_state(actual), final_state(expected));
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::room_actor::scale;
+use crate::actors::room::RoomActor;
+use crate::data::{Reference, VoteSummary};
+use crate::redact;
+use tracing::debug;
+
+impl RoomActor {
+    /// Sets the item votes on the relative scale are compared to, or clears it.
+    pub(super) fn set_reference(&mut self, user_id: String, reference: Option<Reference>) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if let Some(reference) = &reference {
+            if reference.name.trim().is_empty() {
+                debug!(
+                    user_id = %redact::id(&user_id),
+                    "Host tried to set a reference without a name."
+                );
+                return;
+            }
+        }
+
+        self.reference = reference.clone();
+        self.notify_users(ClientResponseMessage::ReferenceChanged {
+            room_name: self.name.clone(),
+            reference,
+        });
+    }
+
+    /// The summary of the votes in the sizes of the reference. Only in relative mode, that is
+    /// with a reference set and the relative scale selected.
+    pub(super) fn implied_summary(&self) -> Option<VoteSummary> {
+        if self.selected_scale_name != scale::RELATIVE_SCALE {
+            return None;
+        }
+        let reference = self.reference.as_ref()?;
+        let implied = self
+            .vote_map
+            .values()
+            .filter_map(|size| size.as_ref()?.parse::<u64>().ok())
+            .map(|percent| reference.value.saturating_mul(percent) / 100);
+        VoteSummary::from_sizes(implied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::room::room_actor::scale::RELATIVE_SCALE;
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use crate::data::{Reference, VoteSummary};
+
+    async fn relative_room(scale_name: &str) -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(RoomMessage::ChangeScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            selected_scale_name: scale_name.to_string(),
+        })
+        .await;
+        room.send(RoomMessage::SetReference {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            reference: Some(Reference {
+                name: "login page".to_string(),
+                value: 8,
+            }),
+        })
+        .await;
+        room
+    }
+
+    async fn implied_summary(room: &TestRoom) -> Option<VoteSummary> {
+        room.received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::VoteResults {
+                    implied_summary, ..
+                } => Some(implied_summary),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn relative_votes_resolve_against_the_reference() {
+        let room = relative_room(RELATIVE_SCALE).await;
+        room.vote("alice", "50").await;
+        room.vote("bob", "200").await;
+
+        let implied = implied_summary(&room).await.unwrap();
+        assert_eq!(implied.min, 4);
+        assert_eq!(implied.max, 16);
+        assert_eq!(implied.average, 10.0);
+    }
+
+    #[actix_rt::test]
+    async fn abstentions_are_left_out_of_the_implied_sizes() {
+        let room = relative_room(RELATIVE_SCALE).await;
+        room.vote("alice", "100").await;
+        room.vote("bob", "NV").await;
+
+        let implied = implied_summary(&room).await.unwrap();
+        assert_eq!((implied.min, implied.max), (8, 8));
+        assert_eq!(implied.modes, ["8"]);
+    }
+
+    #[actix_rt::test]
+    async fn other_scales_have_no_implied_sizes() {
+        let room = relative_room("fibonacci").await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+
+        assert!(implied_summary(&room).await.is_none());
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::room::RoomActor;
+use tracing::info;
+
+impl RoomActor {
+    /// Asks the manager for the new name on behalf of a host. The room keeps its name until
+    /// the manager knows it by the new one.
+    pub(super) fn rename_room(&mut self, user_id: String, new_name: String) {
+        if !self.check_host(&user_id) || new_name == self.name {
+            return;
+        }
+
+        let msg = RoomMessage::RenameApproved {
+            room_name: self.name.clone(),
+            user_id,
+            new_name,
+        };
+        self.notify_manager(msg);
+    }
+
+    pub(super) fn set_name(&mut self, new_name: String) {
+        let old_name = std::mem::replace(&mut self.name, new_name.clone());
+        info!("Room renamed.");
+        self.notify_users(ClientResponseMessage::RoomRenamed { old_name, new_name });
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use actix::{Context, Recipient};
+use tracing::debug;
+
+/// How many broadcasts a room keeps for clients that missed them.
+const REPLAY_BUFFER_SIZE: usize = 50;
+
+impl RoomActor {
+    /// Numbers a broadcast and keeps it for replay.
+    pub(super) fn sequence(&mut self, msg: ClientResponseMessage) -> ClientResponseMessage {
+        self.seq += 1;
+        self.record_broadcast(self.seq, &msg);
+        self.recent_broadcasts.push_back((self.seq, msg.clone()));
+        while self.recent_broadcasts.len() > REPLAY_BUFFER_SIZE {
+            self.recent_broadcasts.pop_front();
+        }
+        ClientResponseMessage::Sequenced {
+            seq: self.seq,
+            message: Box::new(msg),
+        }
+    }
+
+    /// Sends the user the broadcasts sent after `since_seq`. When some of them are no longer
+    /// kept, the whole state of the room is sent instead.
+    pub(super) fn resume(&self, user_id: String, since_seq: u64) {
+        let oldest_kept = self
+            .recent_broadcasts
+            .front()
+            .map_or(self.seq + 1, |(seq, _)| *seq);
+        if since_seq + 1 < oldest_kept {
+            debug!("Broadcasts to replay are gone, sending the room state.");
+            self.notify_user_id(&user_id, self.room_joined_msg());
+            return;
+        }
+
+        for (seq, msg) in self.recent_broadcasts.iter() {
+            if *seq > since_seq {
+                let msg = ClientResponseMessage::Sequenced {
+                    seq: *seq,
+                    message: Box::new(msg.clone()),
+                };
+                self.notify_user_id(&user_id, msg);
+            }
+        }
+    }
+
+    /// Sends what the user missed to the connection that took the place of the dropped one,
+    /// if it said what it saw last.
+    pub(super) fn reconnected(
+        &mut self,
+        user_id: String,
+        since_seq: Option<u64>,
+        recipient: Recipient<ClientResponseMessage>,
+        ctx: &mut Context<Self>,
+    ) {
+        let user = match self.user_map.get_mut(&user_id) {
+            None => {
+                debug!(
+                    user_id = %redact::id(&user_id),
+                    "User reconnected to a room they is not in."
+                );
+                return;
+            }
+            Some(conn_info) => {
+                conn_info.recipient = recipient.clone();
+                conn_info.user.clone()
+            }
+        };
+
+        // sent before the user is back, so it comes ahead of the reconnect broadcast
+        let msg = ClientResponseMessage::Resumed {
+            room_name: self.name.clone(),
+            user,
+            is_host: self.host_ids.contains(&user_id),
+        };
+        self.notify_user(&user_id, &recipient, msg);
+        self.user_back(&user_id, ctx);
+        if let Some(since_seq) = since_seq {
+            self.resume(user_id, since_seq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TakeSequenced, TestRoom, ROOM};
+
+    /// Takes what the member received so far, keeping the sequence numbers.
+    async fn sequenced(room: &TestRoom, user_id: &str) -> Vec<(u64, ClientResponseMessage)> {
+        room.inbox(user_id)
+            .send(TakeSequenced)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ClientResponseMessage::Sequenced { seq, message } => Some((seq, *message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn resume_replays_exactly_the_missed_broadcasts() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.received("bob").await;
+        let seen = room.run(|act, _| act.seq).await;
+
+        // bob misses these two
+        for msg in [
+            RoomMessage::PauseRoom {
+                room_name: ROOM.to_string(),
+                user_id: "alice".to_string(),
+            },
+            RoomMessage::ResumeRoom {
+                room_name: ROOM.to_string(),
+                user_id: "alice".to_string(),
+            },
+        ] {
+            room.send(msg).await;
+        }
+        room.received("bob").await;
+
+        room.send(RoomMessage::Resume {
+            room_name: ROOM.to_string(),
+            user_id: "bob".to_string(),
+            since_seq: seen,
+        })
+        .await;
+        let replayed = sequenced(&room, "bob").await;
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[0].0, seen + 1);
+        assert!(matches!(
+            replayed[0].1,
+            ClientResponseMessage::PauseChanged { paused: true, .. }
+        ));
+        assert_eq!(replayed[1].0, seen + 2);
+        assert!(matches!(
+            replayed[1].1,
+            ClientResponseMessage::PauseChanged { paused: false, .. }
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::room::room_actor::scale;
+use crate::actors::room::RoomActor;
+use std::time::Instant;
+use tracing::info;
+
+impl RoomActor {
+    /// Gives the room a clean slate: votes, history, sub-polls and custom scales are dropped and the
+    /// default scale is selected. Members, hosts and the room settings are kept.
+    pub(super) fn reset_room(&mut self, user_id: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+
+        info!("Resetting room.");
+        self.vote_map.clear();
+        self.confidence_map.clear();
+        self.history.clear();
+        self.current_topic = None;
+        self.ticket_queue.clear();
+        self.estimates.clear();
+        self.round_started_at = Instant::now();
+        self.sub_polls.clear();
+        self.voting_over = false;
+        self.revealed_to_host = false;
+        self.votes_attributed = false;
+        self.paused = false;
+        self.last_randomized = None;
+        self.last_vote_status = None;
+        self.scale_values = RoomActor::scale_catalog();
+        self.selected_scale_name = scale::DEFAULT_SCALE.to_string();
+        self.pending_scale = None;
+        self.timer = None;
+
+        // one message with the whole state, so clients do not go through intermediate ones
+        let msg = self.room_joined_msg();
+        self.notify_users(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    fn reset(user_id: &str) -> RoomMessage {
+        RoomMessage::ResetRoom {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    /// A room with a finished round in its history and a vote in the current one.
+    async fn busy_room() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+        room.send(RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: Some("login page".to_string()),
+        })
+        .await;
+        room.vote("alice", "8").await;
+        room.received("bob").await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn reset_clears_votes_and_history_but_keeps_members() {
+        let room = busy_room().await;
+        room.send(reset("alice")).await;
+
+        let (votes, history, topic, members, hosts) = room
+            .run(|act, _| {
+                (
+                    act.vote_map.len(),
+                    act.history.len(),
+                    act.current_topic.clone(),
+                    act.user_map.len(),
+                    act.host_list(),
+                )
+            })
+            .await;
+        assert_eq!(votes, 0);
+        assert_eq!(history, 0);
+        assert_eq!(topic, None);
+        assert_eq!(members, 2);
+        assert_eq!(hosts, vec!["alice"]);
+
+        let received = room.received("bob").await;
+        assert_eq!(received.len(), 1);
+        assert!(matches!(
+            received[0],
+            ClientResponseMessage::RoomJoined { .. }
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn only_hosts_reset_the_room() {
+        let room = busy_room().await;
+        room.send(reset("bob")).await;
+
+        assert_eq!(room.run(|act, _| act.history.len()).await, 1);
+    }
+}
@@ -0,0 +1,296 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::{RevealPolicy, VoteSummary};
+use crate::redact;
+use tracing::debug;
+
+impl RoomActor {
+    /// Shows the results only to the hosts, so they can decide how to discuss them.
+    /// Voting is closed until the results are revealed to everyone or a new vote starts.
+    pub(super) fn reveal_to_host(&mut self, user_id: String) {
+        if !self.check_host(&user_id) || self.voting_over() {
+            return;
+        }
+
+        self.revealed_to_host = true;
+        let msg = ClientResponseMessage::VoteResults {
+            room_name: self.name.clone(),
+            votes: self.vote_map.clone(),
+            summary: VoteSummary::from_votes(self.vote_map.values()),
+            implied_summary: self.implied_summary(),
+            confidence: self.confidence(),
+        };
+        self.notify_hosts(msg);
+    }
+
+    /// Shows the results to everyone, regardless of how many have voted.
+    /// Depending on the room's policy, only hosts or any member may do it.
+    pub(super) fn reveal_to_all(&mut self, user_id: String) {
+        if !self.may_reveal(&user_id) || self.voting_over() {
+            return;
+        }
+
+        self.reveal();
+    }
+
+    /// Checks the user may reveal results to everyone, according to the room's policy.
+    pub(super) fn may_reveal(&self, user_id: &str) -> bool {
+        match self.reveal_policy {
+            RevealPolicy::Host => self.check_host(user_id),
+            RevealPolicy::Anyone if self.user_map.contains_key(user_id) => true,
+            RevealPolicy::Anyone => {
+                debug!(
+                    user_id = %redact::id(user_id),
+                    "User tried to reveal the votes of a room they is not in."
+                );
+                false
+            }
+        }
+    }
+
+    pub(super) fn set_anonymous_reveal(&mut self, user_id: String, anonymous: bool) {
+        if !self.check_host(&user_id) || self.anonymous_reveal == anonymous {
+            return;
+        }
+
+        self.anonymous_reveal = anonymous;
+        self.notify_users(ClientResponseMessage::AnonymousRevealChanged {
+            room_name: self.name.clone(),
+            anonymous,
+        });
+    }
+
+    pub(super) fn set_reveal_policy(&mut self, user_id: String, policy: RevealPolicy) {
+        if !self.check_host(&user_id) || self.reveal_policy == policy {
+            return;
+        }
+
+        self.reveal_policy = policy;
+        self.notify_users(ClientResponseMessage::RevealPolicyChanged {
+            room_name: self.name.clone(),
+            policy,
+        });
+    }
+
+    pub(super) fn set_reveal_on_complete(&mut self, user_id: String, reveal_on_complete: bool) {
+        if !self.check_host(&user_id) || self.reveal_on_complete == reveal_on_complete {
+            return;
+        }
+
+        self.reveal_on_complete = reveal_on_complete;
+        self.notify_users(ClientResponseMessage::RevealOnCompleteChanged {
+            room_name: self.name.clone(),
+            reveal_on_complete,
+        });
+        if !self.round_closed() {
+            self.send_vote_info();
+        }
+    }
+
+    /// Says who voted what in a round that was revealed anonymously.
+    /// Only the results of the current round can be attributed.
+    pub(super) fn attribute_votes(&mut self, user_id: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if !self.anonymous_reveal || !self.voting_over() {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to attribute votes of a round not revealed anonymously."
+            );
+            return;
+        }
+
+        self.votes_attributed = true;
+        self.notify_users(self.results_msg());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use crate::data::RevealPolicy;
+
+    fn results(received: &[ClientResponseMessage]) -> usize {
+        received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::VoteResults { .. }))
+            .count()
+    }
+
+    /// A room where the host voted and the guest did not, so the round is still open.
+    async fn room_with_a_vote() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.vote("alice", "3").await;
+        room.received("alice").await;
+        room.received("bob").await;
+        room
+    }
+
+    async fn reveal_to_host(room: &TestRoom) {
+        room.send(RoomMessage::RevealToHost {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+    }
+
+    async fn reveal_to_all(room: &TestRoom) {
+        room.send(RoomMessage::RevealToAll {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn only_the_host_sees_the_private_reveal() {
+        let room = room_with_a_vote().await;
+        reveal_to_host(&room).await;
+
+        assert_eq!(results(&room.received("alice").await), 1);
+        assert_eq!(results(&room.received("bob").await), 0);
+        assert!(room.run(|act, _| act.revealed_to_host).await);
+        assert!(!room.run(|act, _| act.voting_over()).await);
+    }
+
+    #[actix_rt::test]
+    async fn everyone_sees_the_public_reveal() {
+        let room = room_with_a_vote().await;
+        reveal_to_host(&room).await;
+        room.received("alice").await;
+
+        reveal_to_all(&room).await;
+        assert_eq!(results(&room.received("alice").await), 1);
+        assert_eq!(results(&room.received("bob").await), 1);
+        assert!(room.run(|act, _| act.voting_over()).await);
+    }
+
+    #[actix_rt::test]
+    async fn votes_are_closed_during_the_private_reveal() {
+        let room = room_with_a_vote().await;
+        reveal_to_host(&room).await;
+
+        room.vote("bob", "5").await;
+        let bob_vote = room
+            .run(|act, _| act.vote_map.get("bob").cloned().flatten())
+            .await;
+        assert_eq!(bob_vote, None);
+    }
+
+    fn anonymous_results(received: &[ClientResponseMessage]) -> usize {
+        received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::AnonymousVoteResults { .. }))
+            .count()
+    }
+
+    async fn attribute_votes(room: &TestRoom, user_id: &str) {
+        room.send(RoomMessage::AttributeVotes {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await;
+    }
+
+    /// A room revealing anonymously, where the host voted and the guest did not yet.
+    async fn anonymous_room() -> TestRoom {
+        let room = room_with_a_vote().await;
+        room.send(RoomMessage::SetAnonymousReveal {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            anonymous: true,
+        })
+        .await;
+        room.received("bob").await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn names_are_revealed_only_when_the_host_attributes_votes() {
+        let room = anonymous_room().await;
+        room.vote("bob", "5").await;
+        let received = room.received("bob").await;
+        assert_eq!(anonymous_results(&received), 1);
+        assert_eq!(results(&received), 0);
+
+        attribute_votes(&room, "alice").await;
+        assert_eq!(results(&room.received("bob").await), 1);
+    }
+
+    #[actix_rt::test]
+    async fn only_hosts_attribute_votes() {
+        let room = anonymous_room().await;
+        room.vote("bob", "5").await;
+        room.received("bob").await;
+
+        attribute_votes(&room, "bob").await;
+        assert_eq!(results(&room.received("bob").await), 0);
+    }
+
+    #[actix_rt::test]
+    async fn votes_of_an_open_round_are_not_attributed() {
+        let room = anonymous_room().await;
+        attribute_votes(&room, "alice").await;
+        assert_eq!(results(&room.received("bob").await), 0);
+    }
+
+    async fn bob_reveals(room: &TestRoom) {
+        room.send(RoomMessage::RevealToAll {
+            room_name: ROOM.to_string(),
+            user_id: "bob".to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn members_may_not_reveal_when_only_hosts_may() {
+        let room = room_with_a_vote().await;
+        bob_reveals(&room).await;
+
+        assert!(!room.run(|act, _| act.voting_over()).await);
+        let refused = room
+            .received("bob")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::NotAuthorized { .. }));
+        assert!(refused);
+    }
+
+    #[actix_rt::test]
+    async fn members_may_reveal_when_anyone_may() {
+        let room = room_with_a_vote().await;
+        room.send(RoomMessage::SetRevealPolicy {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            policy: RevealPolicy::Anyone,
+        })
+        .await;
+        room.received("bob").await;
+
+        bob_reveals(&room).await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+        assert_eq!(results(&room.received("bob").await), 1);
+    }
+}
@@ -0,0 +1,603 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::Scale;
+use crate::redact;
+use actix::{AsyncContext, Context};
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::debug;
+
+/// Scale selected when a room is created.
+pub(super) const DEFAULT_SCALE: &str = "fibonacci";
+/// Scale whose values are percentages of the reference of the room.
+pub(super) const RELATIVE_SCALE: &str = "relative";
+
+/// The scales every room has. This is the only place where the built-in scales are declared.
+pub(super) fn built_in_scales() -> Vec<Scale> {
+    let mut relative = Scale::new(
+        RELATIVE_SCALE,
+        "Relative",
+        &["25", "50", "100", "200", "400", "NV"],
+    );
+    relative.labels = [
+        ("25", "quarter"),
+        ("50", "half"),
+        ("100", "same"),
+        ("200", "double"),
+        ("400", "quadruple"),
+    ]
+    .iter()
+    .map(|(value, label)| (value.to_string(), label.to_string()))
+    .collect();
+    let scales = vec![
+        Scale::new(
+            "fibonacci",
+            "Fibonacci",
+            &["0", "1", "2", "3", "5", "8", "13", "21", "NV"],
+        ),
+        Scale::new("fistOfFive", "Fist of Five", &["1", "2", "3", "4", "5"]),
+        Scale::new("tshirt", "T-Shirt", &["XS", "S", "M", "L", "XL", "NV"]),
+        relative,
+    ];
+    for scale in scales.iter() {
+        scale.validate().expect("Built-in scale is invalid.");
+    }
+    scales
+}
+
+fn is_built_in(name: &str) -> bool {
+    built_in_scales().iter().any(|scale| scale.name == name)
+}
+
+impl RoomActor {
+    /// The scales every room starts with, by name.
+    pub fn scale_catalog() -> HashMap<String, Scale> {
+        built_in_scales()
+            .into_iter()
+            .map(|scale| (scale.name.clone(), scale))
+            .collect()
+    }
+
+    /// Checks a scale a room is to be created with, returning the reason when it cannot be used.
+    /// Numeric values may not be larger than the given maximum.
+    pub fn check_custom_scale(scale: &Scale, max_vote_value: u64) -> Result<(), String> {
+        scale.validate()?;
+        if scale.values.len() < 2 {
+            return Err("a scale needs at least two values".to_string());
+        }
+        // numbers too long for a u64 are too large as well
+        let too_large = scale.values.iter().find(|value| {
+            value.chars().all(|c| c.is_ascii_digit())
+                && !value
+                    .parse::<u64>()
+                    .is_ok_and(|size| size <= max_vote_value)
+        });
+        if let Some(value) = too_large {
+            return Err(format!("value {} is larger than {}", value, max_vote_value));
+        }
+        if is_built_in(&scale.name) {
+            return Err(format!("scale {} is built-in", scale.name));
+        }
+        Ok(())
+    }
+
+    /// Selects a scale once no other change comes within the debounce, so quick changes end
+    /// up in a single broadcast.
+    pub(super) fn change_scale(
+        &mut self,
+        user_id: String,
+        selected_scale_name: String,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User tried to change the scale of a room they is not in."
+            );
+            return;
+        }
+
+        let room_name = self.name.clone();
+        if self.paused {
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+
+        if !self.scale_values.contains_key(&selected_scale_name) {
+            self.scale_error(&user_id, format!("unknown scale {}", selected_scale_name));
+            return;
+        }
+
+        if let Some((_, handle)) = self.pending_scale.take() {
+            ctx.cancel_future(handle);
+        }
+        let debounce = self.config.scale_debounce;
+        if debounce.as_millis() == 0 {
+            self.select_scale(selected_scale_name);
+            return;
+        }
+        let handle = ctx.run_later(debounce, |act, _| {
+            // a change still pending when the room was paused is dropped
+            if let Some((selected_scale_name, _)) = act.pending_scale.take() {
+                if !act.paused {
+                    act.select_scale(selected_scale_name);
+                }
+            }
+        });
+        self.pending_scale = Some((selected_scale_name, handle));
+    }
+
+    fn select_scale(&mut self, selected_scale_name: String) {
+        // the scale may have been deleted while the change was pending
+        let values = match self.scale_values.get(&selected_scale_name) {
+            None => return,
+            Some(scale) => scale.values.clone(),
+        };
+        self.last_activity = Instant::now();
+        self.selected_scale_name = selected_scale_name.clone();
+        self.notify_users(ClientResponseMessage::ScaleChanged {
+            room_name: self.name.clone(),
+            selected_scale_name,
+            values: values.clone(),
+        });
+
+        // revealed results are left as they were voted
+        if self.round_closed() {
+            return;
+        }
+        let votes = self.vote_map.len();
+        self.vote_map
+            .retain(|_, size| size.as_ref().is_none_or(|size| values.contains(size)));
+        if self.vote_map.len() != votes {
+            self.send_vote_info();
+        }
+    }
+
+    /// Adds a custom scale to the room. Only hosts may do it, and not while the room is paused.
+    pub(super) fn create_scale(&mut self, user_id: String, scale: Scale) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if self.paused {
+            let room_name = self.name.clone();
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+
+        if let Err(reason) = RoomActor::check_custom_scale(&scale, self.config.max_vote_value) {
+            self.scale_error(&user_id, reason);
+            return;
+        }
+        if self.scale_values.contains_key(&scale.name) {
+            self.scale_error(&user_id, format!("scale {} already exists", scale.name));
+            return;
+        }
+        let custom_scales = self
+            .scale_values
+            .len()
+            .saturating_sub(built_in_scales().len());
+        if custom_scales >= self.config.max_custom_scales {
+            self.scale_error(&user_id, "too many scales".to_string());
+            return;
+        }
+
+        self.scale_values.insert(scale.name.clone(), scale);
+        self.notify_scales_updated();
+    }
+
+    /// Deletes a custom scale. Built-in scales and the selected or pending scale cannot be deleted.
+    pub(super) fn delete_scale(&mut self, user_id: String, name: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if self.paused {
+            let room_name = self.name.clone();
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+
+        if is_built_in(&name) {
+            self.scale_error(&user_id, format!("scale {} is built-in", name));
+            return;
+        }
+        if self.selected_scale_name == name {
+            self.scale_error(&user_id, format!("scale {} is selected", name));
+            return;
+        }
+        if matches!(&self.pending_scale, Some((pending, _)) if *pending == name) {
+            self.scale_error(&user_id, format!("scale {} is pending", name));
+            return;
+        }
+        if self.scale_values.remove(&name).is_none() {
+            self.scale_error(&user_id, format!("unknown scale {}", name));
+            return;
+        }
+
+        self.notify_scales_updated();
+    }
+
+    fn notify_scales_updated(&mut self) {
+        self.notify_users(ClientResponseMessage::ScalesUpdated {
+            room_name: self.name.clone(),
+            scale_values: self.scale_values.clone(),
+        });
+    }
+
+    fn scale_error(&self, user_id: &str, reason: String) {
+        let room_name = self.name.clone();
+        self.notify_user_id(
+            user_id,
+            ClientResponseMessage::ScaleError { room_name, reason },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::messages::RoomMessage;
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::time::Duration;
+
+    fn create(user_id: &str, name: &str) -> RoomMessage {
+        RoomMessage::CreateScale {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            scale: Scale::new(name, name, &["1", "2"]),
+        }
+    }
+
+    fn delete(user_id: &str, name: &str) -> RoomMessage {
+        RoomMessage::DeleteScale {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    async fn room_with_cap(max_custom_scales: usize) -> TestRoom {
+        let mut config = test_util::config();
+        config.max_custom_scales = max_custom_scales;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join("bob").await;
+        room
+    }
+
+    async fn has_scale(room: &TestRoom, name: &'static str) -> bool {
+        room.run(move |act, _| act.scale_values.contains_key(name))
+            .await
+    }
+
+    fn scale_error(received: &[ClientResponseMessage], expected: &str) -> bool {
+        received.iter().any(|msg| {
+            matches!(msg, ClientResponseMessage::ScaleError { reason, .. } if reason == expected)
+        })
+    }
+
+    #[actix_rt::test]
+    async fn scales_beyond_the_cap_are_refused() {
+        let room = room_with_cap(1).await;
+        room.send(create("alice", "first")).await;
+        room.send(create("alice", "second")).await;
+
+        assert!(has_scale(&room, "first").await);
+        assert!(!has_scale(&room, "second").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "too many scales"
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn deleting_a_scale_frees_its_slot() {
+        let room = room_with_cap(1).await;
+        room.send(create("alice", "first")).await;
+        room.send(delete("alice", "first")).await;
+        room.send(create("alice", "second")).await;
+
+        assert!(!has_scale(&room, "first").await);
+        assert!(has_scale(&room, "second").await);
+    }
+
+    #[actix_rt::test]
+    async fn deleting_a_scale_is_broadcast() {
+        let room = room_with_cap(1).await;
+        room.send(create("alice", "first")).await;
+        room.received("bob").await;
+
+        room.send(delete("alice", "first")).await;
+        let updated = room.received("bob").await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::ScalesUpdated { scale_values, .. }
+                    if !scale_values.contains_key("first")
+            )
+        });
+        assert!(updated);
+    }
+
+    #[actix_rt::test]
+    async fn selected_scale_cannot_be_deleted() {
+        let room = room_with_cap(1).await;
+        room.send(create("alice", "first")).await;
+        room.send(RoomMessage::ChangeScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            selected_scale_name: "first".to_string(),
+        })
+        .await;
+        room.send(delete("alice", "first")).await;
+
+        assert!(has_scale(&room, "first").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "scale first is selected"
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn built_in_scales_cannot_be_deleted() {
+        let room = room_with_cap(1).await;
+        room.send(delete("alice", "tshirt")).await;
+
+        assert!(has_scale(&room, "tshirt").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "scale tshirt is built-in"
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn only_hosts_create_scales() {
+        let room = room_with_cap(1).await;
+        room.send(create("bob", "first")).await;
+
+        assert!(!has_scale(&room, "first").await);
+        let not_authorized = room
+            .received("bob")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::NotAuthorized { .. }));
+        assert!(not_authorized);
+    }
+
+    #[actix_rt::test]
+    async fn scales_do_not_change_while_paused() {
+        let room = room_with_cap(2).await;
+        room.send(create("alice", "first")).await;
+        room.send(RoomMessage::PauseRoom {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room.send(create("alice", "second")).await;
+        room.send(delete("alice", "first")).await;
+
+        assert!(has_scale(&room, "first").await);
+        assert!(!has_scale(&room, "second").await);
+    }
+
+    #[actix_rt::test]
+    async fn scales_with_duplicate_values_are_refused() {
+        let room = room_with_cap(1).await;
+        room.send(RoomMessage::CreateScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            scale: Scale::new("dup", "Dup", &["1", "1"]),
+        })
+        .await;
+
+        assert!(!has_scale(&room, "dup").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "values are not unique"
+        ));
+    }
+
+    #[test]
+    fn built_in_scales_are_valid() {
+        for scale in built_in_scales() {
+            assert_eq!(scale.validate(), Ok(()), "{}", scale.name);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn scales_with_absurd_values_are_refused() {
+        let room = room_with_cap(1).await;
+        room.send(RoomMessage::CreateScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            scale: Scale::new("skew", "Skew", &["1", "999999999999"]),
+        })
+        .await;
+
+        assert!(!has_scale(&room, "skew").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "value 999999999999 is larger than 1000000"
+        ));
+    }
+
+    #[test]
+    fn numeric_values_are_bounded() {
+        let scale = Scale::new("big", "Big", &["1", "1000", "NV"]);
+        assert!(RoomActor::check_custom_scale(&scale, 1000).is_ok());
+        assert!(RoomActor::check_custom_scale(&scale, 999).is_err());
+    }
+
+    #[test]
+    fn numbers_too_long_for_a_size_are_rejected() {
+        let scale = Scale::new("huge", "Huge", &["1", "99999999999999999999999"]);
+        assert!(RoomActor::check_custom_scale(&scale, u64::MAX).is_err());
+    }
+
+    fn change(name: &str) -> RoomMessage {
+        RoomMessage::ChangeScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            selected_scale_name: name.to_string(),
+        }
+    }
+
+    fn scale_changes(received: &[ClientResponseMessage]) -> Vec<String> {
+        received
+            .iter()
+            .filter_map(|msg| match msg {
+                ClientResponseMessage::ScaleChanged {
+                    selected_scale_name,
+                    ..
+                } => Some(selected_scale_name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn rapid_scale_changes_are_broadcast_once() {
+        let mut config = test_util::config();
+        config.scale_debounce = Duration::from_millis(50);
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join("bob").await;
+        room.received("bob").await;
+
+        for name in ["tshirt", "fistOfFive", RELATIVE_SCALE] {
+            room.send(change(name)).await;
+        }
+        assert!(scale_changes(&room.received("bob").await).is_empty());
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        assert_eq!(scale_changes(&room.received("bob").await), [RELATIVE_SCALE]);
+        let selected = room.run(|act, _| act.selected_scale_name.clone()).await;
+        assert_eq!(selected, RELATIVE_SCALE);
+    }
+
+    async fn debounced_room() -> TestRoom {
+        let mut config = test_util::config();
+        config.scale_debounce = Duration::from_millis(50);
+        config.max_custom_scales = 1;
+        let mut room = TestRoom::start(config);
+        room.join("alice").await;
+        room.join("bob").await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn pending_scale_change_is_dropped_when_the_room_is_paused() {
+        let room = debounced_room().await;
+        room.received("bob").await;
+        room.send(change("tshirt")).await;
+        room.send(RoomMessage::PauseRoom {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        assert!(scale_changes(&room.received("bob").await).is_empty());
+        let selected = room.run(|act, _| act.selected_scale_name.clone()).await;
+        assert_ne!(selected, "tshirt");
+    }
+
+    #[actix_rt::test]
+    async fn pending_scale_cannot_be_deleted() {
+        let room = debounced_room().await;
+        room.send(create("alice", "first")).await;
+        room.send(change("first")).await;
+        room.send(delete("alice", "first")).await;
+
+        assert!(has_scale(&room, "first").await);
+        assert!(scale_error(
+            &room.received("alice").await,
+            "scale first is pending"
+        ));
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        let selected = room.run(|act, _| act.selected_scale_name.clone()).await;
+        assert_eq!(selected, "first");
+    }
+
+    #[actix_rt::test]
+    async fn tshirt_scale_can_be_selected() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.send(change("tshirt")).await;
+
+        let values = room
+            .received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::ScaleChanged {
+                    selected_scale_name,
+                    values,
+                    ..
+                } if selected_scale_name == "tshirt" => Some(values),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(values, ["XS", "S", "M", "L", "XL", "NV"]);
+        let display_name = room
+            .run(|act, _| act.scale_values["tshirt"].display_name.clone())
+            .await;
+        assert_eq!(display_name, "T-Shirt");
+    }
+
+    #[actix_rt::test]
+    async fn votes_off_the_new_scale_are_cleared() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.join("carol").await;
+        room.vote("alice", "13").await;
+        room.vote("bob", "3").await;
+        room.received("carol").await;
+        room.send(change("fistOfFive")).await;
+
+        let votes = room
+            .run(|act, _| act.vote_map.keys().cloned().collect::<Vec<_>>())
+            .await;
+        assert_eq!(votes, ["bob"]);
+        let status = room
+            .received("carol")
+            .await
+            .into_iter()
+            .rev()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::VoteStatus { votes, .. } => Some(votes),
+                _ => None,
+            })
+            .unwrap();
+        assert!(!status["alice"]);
+        assert!(status["bob"]);
+    }
+
+    #[actix_rt::test]
+    async fn revealed_votes_survive_a_scale_change() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.vote("alice", "13").await;
+        room.send(change("fistOfFive")).await;
+
+        let size = room.run(|act, _| act.vote_map["alice"].clone()).await;
+        assert_eq!(size.as_deref(), Some("13"));
+    }
+}
@@ -0,0 +1,77 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::{Round, VoteSummary};
+use crate::redact;
+use actix::{ActorContext, Context};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{error, info};
+
+/// What is left of a room when the server goes down.
+#[derive(Serialize)]
+struct Export {
+    room_name: String,
+    votes: HashMap<String, Option<String>>,
+    voting_over: bool,
+    summary: Option<VoteSummary>,
+    history: Vec<Round>,
+}
+
+/// The votes with the user ids as they should appear in the logs.
+fn redact_votes(votes: &HashMap<String, Option<String>>) -> HashMap<String, Option<String>> {
+    votes
+        .iter()
+        .map(|(user_id, size)| (redact::id(user_id), size.clone()))
+        .collect()
+}
+
+impl RoomActor {
+    /// Exports the history and the results of the current round, tells the users the server
+    /// is going down and stops the room.
+    pub(super) fn drain(&mut self, ctx: &mut Context<Self>) {
+        let history = self
+            .history
+            .iter()
+            .map(|round| Round {
+                votes: redact_votes(&round.votes),
+                summary: round.summary.clone(),
+                topic: round.topic.clone(),
+                duration_secs: round.duration_secs,
+                anonymous: round.anonymous,
+                anonymize_at: None,
+            })
+            .collect();
+        let export = Export {
+            room_name: redact::id(&self.name),
+            votes: redact_votes(&self.vote_map),
+            voting_over: self.voting_over(),
+            summary: VoteSummary::from_votes(self.vote_map.values()),
+            history,
+        };
+        match serde_json::to_string(&export) {
+            Ok(export) => info!("Export: {}", export),
+            Err(err) => error!(error = %err, "Unable to export room."),
+        }
+
+        self.notify_users(ClientResponseMessage::ServerShuttingDown);
+        ctx.stop();
+    }
+}
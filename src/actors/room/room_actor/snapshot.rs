@@ -0,0 +1,251 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::room_actor::scale::DEFAULT_SCALE;
+use crate::actors::room::RoomActor;
+use crate::data::{RoomSnapshot, Scale};
+use actix::Recipient;
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+impl RoomActor {
+    /// Captures the data of the room. Connections are not part of the snapshot.
+    pub(super) fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            name: self.name.clone(),
+            hashed_password: self.hashed_password.clone(),
+            users: self
+                .user_map
+                .values()
+                .map(|conn_info| conn_info.user.clone())
+                .collect(),
+            votes: self.vote_map.clone(),
+            confidence: self.confidence(),
+            host_ids: self.host_list(),
+            creator_id: self.creator_id.clone(),
+            paused: self.paused,
+            voting_over: self.voting_over,
+            revealed_to_host: self.revealed_to_host,
+            anonymous_reveal: self.anonymous_reveal,
+            votes_attributed: self.votes_attributed,
+            reveal_policy: self.reveal_policy,
+            reveal_on_complete: self.reveal_on_complete,
+            unique_names: self.unique_names,
+            auto_new_vote: self.auto_new_vote,
+            anonymize_history: self.anonymize_history,
+            abstentions_count: self.abstentions_count,
+            locked: self.locked,
+            passive_ids: self.passive_ids(),
+            topic: self.current_topic.clone(),
+            reference: self.reference.clone(),
+            scale_values: self.scale_values.clone(),
+            selected_scale_name: self.selected_scale_name.clone(),
+            last_randomized: self.last_randomized.clone(),
+        }
+    }
+
+    /// Replaces the data of the room with the snapshot. As connections cannot be restored,
+    /// users that are not currently in the room are left out, as are their votes. Scales are
+    /// checked as if they were created in the room, the built-in ones are always kept.
+    pub(super) fn restore_snapshot(&mut self, snapshot: RoomSnapshot) {
+        for user in snapshot.users {
+            if let Some(conn_info) = self.user_map.get_mut(&user.user_id) {
+                conn_info.active = !snapshot.passive_ids.contains(&user.user_id);
+                conn_info.user = user;
+            }
+        }
+        let user_map = &self.user_map;
+        self.vote_map = snapshot
+            .votes
+            .into_iter()
+            .filter(|(user_id, _)| user_map.get(user_id).is_some_and(|conn| conn.active))
+            .collect();
+        let vote_map = &self.vote_map;
+        self.confidence_map = snapshot
+            .confidence
+            .into_iter()
+            .filter(|(user_id, _)| vote_map.contains_key(user_id))
+            .collect();
+        let host_ids: HashSet<String> = snapshot
+            .host_ids
+            .into_iter()
+            .filter(|user_id| user_map.contains_key(user_id))
+            .collect();
+        if host_ids.is_empty() {
+            warn!("No host of the snapshot is in the room, keeping the current ones.");
+        } else {
+            self.host_ids = host_ids;
+        }
+
+        self.creator_id = snapshot.creator_id;
+        self.hashed_password = snapshot.hashed_password;
+        self.paused = snapshot.paused;
+        self.voting_over = snapshot.voting_over;
+        self.revealed_to_host = snapshot.revealed_to_host;
+        self.anonymous_reveal = snapshot.anonymous_reveal;
+        self.votes_attributed = snapshot.votes_attributed;
+        self.reveal_policy = snapshot.reveal_policy;
+        self.reveal_on_complete = snapshot.reveal_on_complete;
+        self.unique_names = snapshot.unique_names;
+        self.auto_new_vote = snapshot.auto_new_vote;
+        self.anonymize_history = snapshot.anonymize_history;
+        self.reference = snapshot.reference;
+        self.current_topic = snapshot.topic;
+        self.abstentions_count = snapshot.abstentions_count;
+        self.locked = snapshot.locked;
+        self.scale_values = self.restored_scales(snapshot.scale_values);
+        if self
+            .scale_values
+            .contains_key(&snapshot.selected_scale_name)
+        {
+            self.selected_scale_name = snapshot.selected_scale_name;
+        } else if !self.scale_values.contains_key(&self.selected_scale_name) {
+            self.selected_scale_name = DEFAULT_SCALE.to_string();
+        }
+        self.last_randomized = snapshot.last_randomized;
+        self.last_vote_status = None;
+    }
+
+    /// The built-in scales, with the custom scales of the snapshot that could be created in
+    /// the room. Built-in scales of the snapshot are ignored, so their values cannot change.
+    fn restored_scales(&self, snapshot_scales: HashMap<String, Scale>) -> HashMap<String, Scale> {
+        let mut scale_values = RoomActor::scale_catalog();
+        let built_in = scale_values.len();
+        for (name, scale) in snapshot_scales {
+            if scale_values.contains_key(&name) {
+                continue;
+            }
+            if scale_values.len() - built_in >= self.config.max_custom_scales {
+                warn!("Snapshot has too many scales, leaving the rest out.");
+                break;
+            }
+            let checked = if scale.name == name {
+                RoomActor::check_custom_scale(&scale, self.config.max_vote_value)
+            } else {
+                Err(format!("scale {} is filed as {}", scale.name, name))
+            };
+            match checked {
+                Ok(()) => {
+                    scale_values.insert(name, scale);
+                }
+                Err(reason) => warn!(%reason, "Snapshot scale is invalid, leaving it out."),
+            }
+        }
+        scale_values
+    }
+
+    pub(super) fn send_snapshot(&self, recipient: Recipient<ClientResponseMessage>) {
+        let snapshot = self.snapshot();
+        if let Err(err) = recipient.do_send(ClientResponseMessage::RoomSnapshot { snapshot }) {
+            warn!(error = %err, "Unable to send snapshot.");
+        }
+    }
+
+    pub(super) fn restore(
+        &mut self,
+        snapshot: RoomSnapshot,
+        recipient: Recipient<ClientResponseMessage>,
+    ) {
+        self.restore_snapshot(snapshot);
+        self.notify_users(self.room_joined_msg());
+
+        let room_name = self.name.clone();
+        if let Err(err) = recipient.do_send(ClientResponseMessage::RoomRestored { room_name }) {
+            warn!(error = %err, "Unable to confirm restore.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::test_util::{self, TestRoom};
+    use crate::data::{RoomSnapshot, Scale};
+
+    async fn room_with_votes() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.vote("alice", "3").await;
+        room
+    }
+
+    async fn restore(room: &TestRoom, snapshot: RoomSnapshot) -> RoomSnapshot {
+        room.run(move |act, _| {
+            act.restore_snapshot(snapshot);
+            act.snapshot()
+        })
+        .await
+    }
+
+    fn as_json(snapshot: &RoomSnapshot) -> serde_json::Value {
+        serde_json::to_value(snapshot).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn restoring_a_snapshot_brings_the_room_back() {
+        let room = room_with_votes().await;
+        let snapshot = room.run(|act, _| act.snapshot()).await;
+
+        room.run(|act, _| act.start_round()).await;
+        let restored = restore(&room, snapshot.clone()).await;
+        assert_eq!(as_json(&restored), as_json(&snapshot));
+    }
+
+    #[actix_rt::test]
+    async fn invalid_scales_are_left_out() {
+        let room = room_with_votes().await;
+        let mut snapshot = room.run(|act, _| act.snapshot()).await;
+        let invalid = Scale::new("huge", "Huge", &["1", "99999999999999999999999"]);
+        snapshot.scale_values.insert(invalid.name.clone(), invalid);
+        snapshot.selected_scale_name = "huge".to_string();
+
+        let restored = restore(&room, snapshot).await;
+        assert!(!restored.scale_values.contains_key("huge"));
+        assert_eq!(restored.selected_scale_name, "fibonacci");
+    }
+
+    #[actix_rt::test]
+    async fn built_in_scales_are_kept() {
+        let room = room_with_votes().await;
+        let mut snapshot = room.run(|act, _| act.snapshot()).await;
+        snapshot.scale_values.clear();
+        let tshirt = Scale::new("tshirt", "T-Shirt", &["S", "M"]);
+        snapshot.scale_values.insert(tshirt.name.clone(), tshirt);
+
+        let restored = restore(&room, snapshot).await;
+        assert!(restored.scale_values.contains_key("fibonacci"));
+        assert_eq!(restored.scale_values["tshirt"].values.len(), 6);
+    }
+
+    #[actix_rt::test]
+    async fn confidence_of_users_not_in_the_room_is_dropped() {
+        let room = room_with_votes().await;
+        let mut snapshot = room.run(|act, _| act.snapshot()).await;
+        snapshot
+            .votes
+            .insert("carol".to_string(), Some("5".to_string()));
+        snapshot.confidence.insert("carol".to_string(), 4);
+
+        room.run(move |act, _| act.restore_snapshot(snapshot)).await;
+        let kept = room
+            .run(|act, _| act.confidence_map.contains_key("carol"))
+            .await;
+        assert!(!kept);
+    }
+}
@@ -0,0 +1,240 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::VoteSummary;
+use crate::redact;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// How many sub-polls a room may have at once.
+const MAX_SUB_POLLS: usize = 20;
+
+/// An item estimated in parallel with the main round. It has its own votes and is revealed
+/// on its own, either by reaching the quorum or by a manual reveal.
+#[derive(Default)]
+pub(super) struct SubPoll {
+    votes: HashMap<String, Option<String>>,
+    revealed: bool,
+}
+
+impl RoomActor {
+    pub(super) fn create_sub_poll(&mut self, user_id: String, poll_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User tried to create a sub-poll in a room they is not in."
+            );
+            return;
+        }
+
+        if poll_id.trim().is_empty() {
+            self.sub_poll_error(&user_id, poll_id, "id is empty");
+        } else if self.sub_polls.contains_key(&poll_id) {
+            self.sub_poll_error(&user_id, poll_id, "sub-poll already exists");
+        } else if self.sub_polls.len() >= MAX_SUB_POLLS {
+            self.sub_poll_error(&user_id, poll_id, "too many sub-polls");
+        } else {
+            self.sub_polls.insert(poll_id.clone(), SubPoll::default());
+            self.notify_users(ClientResponseMessage::SubPollCreated {
+                room_name: self.name.clone(),
+                poll_id,
+            });
+        }
+    }
+
+    pub(super) fn sub_poll_vote(&mut self, user_id: String, poll_id: String, size: String) {
+        let room_name = self.name.clone();
+        if self.paused {
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+        if !self.is_active(&user_id) {
+            self.notify_user_id(&user_id, ClientResponseMessage::NotAuthorized { room_name });
+            return;
+        }
+        if !self.on_selected_scale(&size) {
+            let msg = ClientResponseMessage::InvalidVote { room_name, size };
+            self.notify_user_id(&user_id, msg);
+            return;
+        }
+
+        match self.sub_polls.get_mut(&poll_id) {
+            None => self.sub_poll_error(&user_id, poll_id, "unknown sub-poll"),
+            Some(poll) if poll.revealed => {
+                self.notify_user_id(&user_id, ClientResponseMessage::VotingOver)
+            }
+            Some(poll) => {
+                poll.votes.insert(user_id, Some(size));
+                self.send_sub_poll_info(poll_id);
+            }
+        }
+    }
+
+    /// Reveals a sub-poll regardless of how many have voted. Follows the room's reveal policy.
+    pub(super) fn reveal_sub_poll(&mut self, user_id: String, poll_id: String) {
+        if !self.may_reveal(&user_id) {
+            return;
+        }
+
+        match self.sub_polls.get_mut(&poll_id) {
+            None => self.sub_poll_error(&user_id, poll_id, "unknown sub-poll"),
+            Some(poll) if poll.revealed => {}
+            Some(poll) => {
+                poll.revealed = true;
+                self.send_sub_poll_info(poll_id);
+            }
+        }
+    }
+
+    /// Sends the results of the sub-poll if it is over, or who has voted otherwise.
+    fn send_sub_poll_info(&mut self, poll_id: String) {
+        let active = self.member_counts().active;
+        let quorum_percent = self.config.quorum_percent;
+        let room_name = self.name.clone();
+        let poll = match self.sub_polls.get_mut(&poll_id) {
+            None => return,
+            Some(poll) => poll,
+        };
+
+        poll.revealed = poll.revealed || poll.votes.len() * 100 >= active * quorum_percent;
+        let msg = if poll.revealed {
+            ClientResponseMessage::SubPollResults {
+                room_name,
+                poll_id,
+                votes: poll.votes.clone(),
+                summary: VoteSummary::from_votes(poll.votes.values()),
+            }
+        } else {
+            let votes = poll
+                .votes
+                .keys()
+                .map(|user_id| (user_id.clone(), true))
+                .collect();
+            ClientResponseMessage::SubPollStatus {
+                room_name,
+                poll_id,
+                votes,
+            }
+        };
+        self.notify_users(msg);
+    }
+
+    fn sub_poll_error(&self, user_id: &str, poll_id: String, reason: &str) {
+        let msg = ClientResponseMessage::SubPollError {
+            room_name: self.name.clone(),
+            poll_id,
+            reason: reason.to_string(),
+        };
+        self.notify_user_id(user_id, msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::collections::HashMap;
+
+    fn sub_poll_vote(user_id: &str, poll_id: &str, size: &str) -> RoomMessage {
+        RoomMessage::SubPollVote {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            poll_id: poll_id.to_string(),
+            size: size.to_string(),
+        }
+    }
+
+    /// The results of each sub-poll revealed in what the member received.
+    fn revealed(
+        received: &[ClientResponseMessage],
+    ) -> HashMap<String, HashMap<String, Option<String>>> {
+        received
+            .iter()
+            .filter_map(|msg| match msg {
+                ClientResponseMessage::SubPollResults { poll_id, votes, .. } => {
+                    Some((poll_id.clone(), votes.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A room with two sub-polls, "login" and "signup".
+    async fn room_with_sub_polls() -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        for poll_id in ["login", "signup"] {
+            room.send(RoomMessage::CreateSubPoll {
+                room_name: ROOM.to_string(),
+                user_id: "alice".to_string(),
+                poll_id: poll_id.to_string(),
+            })
+            .await;
+        }
+        room.received("bob").await;
+        room
+    }
+
+    #[actix_rt::test]
+    async fn sub_polls_reveal_separately() {
+        let room = room_with_sub_polls().await;
+        room.send(sub_poll_vote("alice", "login", "3")).await;
+        room.send(sub_poll_vote("bob", "login", "5")).await;
+        room.send(sub_poll_vote("alice", "signup", "8")).await;
+
+        let results = revealed(&room.received("bob").await);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results["login"]["alice"].as_deref(), Some("3"));
+        assert_eq!(results["login"]["bob"].as_deref(), Some("5"));
+
+        room.send(RoomMessage::RevealSubPoll {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            poll_id: "signup".to_string(),
+        })
+        .await;
+        let results = revealed(&room.received("bob").await);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results["signup"]["alice"].as_deref(), Some("8"));
+        assert!(!results["signup"].contains_key("bob"));
+    }
+
+    #[actix_rt::test]
+    async fn sub_poll_votes_leave_the_main_round_alone() {
+        let room = room_with_sub_polls().await;
+        room.send(sub_poll_vote("alice", "login", "3")).await;
+
+        assert!(room.run(|act, _| act.vote_map.is_empty()).await);
+    }
+
+    #[actix_rt::test]
+    async fn sizes_off_the_scale_are_refused() {
+        let room = room_with_sub_polls().await;
+        room.send(sub_poll_vote("alice", "login", "4")).await;
+
+        let refused = room
+            .received("alice")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::InvalidVote { .. }));
+        assert!(refused);
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::data::{RoundOutcome, SessionSummary};
+use crate::redact;
+use tracing::{debug, error, info};
+
+impl RoomActor {
+    /// The session so far, built from the finished rounds.
+    fn session_summary(&self) -> SessionSummary {
+        let mut participants: Vec<String> = self.participants.values().cloned().collect();
+        participants.sort();
+        let rounds = self
+            .history
+            .iter()
+            .map(|round| RoundOutcome {
+                topic: round.topic.clone(),
+                summary: round.summary.clone(),
+                consensus: round.consensus(),
+            })
+            .collect();
+        SessionSummary {
+            room_name: self.name.clone(),
+            participants,
+            duration_secs: self.created_at.elapsed().as_secs(),
+            rounds,
+        }
+    }
+
+    /// Sends the summary of the session to every member of the room.
+    pub(super) fn get_session_summary(&mut self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User not in room asked for the session summary."
+            );
+            return;
+        }
+        let summary = self.session_summary();
+        self.notify_users(ClientResponseMessage::SessionSummary { summary });
+    }
+
+    /// Logs the summary of the session, if enabled, as the room closes.
+    pub(super) fn log_summary(&self) {
+        if !self.config.log_summaries {
+            return;
+        }
+        let mut summary = self.session_summary();
+        summary.room_name = redact::id(&summary.room_name);
+        if self.config.redact_logs {
+            summary.participants.clear();
+        }
+        match serde_json::to_string(&summary) {
+            Ok(summary) => info!("Summary: {}", summary),
+            Err(err) => error!(error = %err, "Unable to summarize room."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use crate::data::SessionSummary;
+
+    fn new_vote(topic: Option<&str>) -> RoomMessage {
+        RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: topic.map(str::to_string),
+        }
+    }
+
+    async fn summary(room: &TestRoom) -> SessionSummary {
+        room.received("alice").await;
+        room.send(RoomMessage::GetSessionSummary {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room.received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::SessionSummary { summary } => Some(summary),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn two_topic_session_is_summarized() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.join("carol").await;
+        room.send(RoomMessage::LeaveRoom {
+            room_name: ROOM.to_string(),
+            user_id: "carol".to_string(),
+            recipient: None,
+        })
+        .await;
+
+        room.send(new_vote(Some("login page"))).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "3").await;
+        room.send(new_vote(Some("signup page"))).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "8").await;
+        room.send(new_vote(None)).await;
+
+        let summary = summary(&room).await;
+        assert_eq!(summary.room_name, ROOM);
+        assert_eq!(summary.participants, ["alice", "bob", "carol"]);
+        let topics: Vec<_> = summary
+            .rounds
+            .iter()
+            .map(|round| round.topic.as_deref())
+            .collect();
+        assert_eq!(topics, [Some("login page"), Some("signup page")]);
+        let consensus: Vec<_> = summary.rounds.iter().map(|round| round.consensus).collect();
+        assert_eq!(consensus, [true, false]);
+    }
+
+    #[actix_rt::test]
+    async fn summary_is_only_for_members() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.send(RoomMessage::GetSessionSummary {
+            room_name: ROOM.to_string(),
+            user_id: "mallory".to_string(),
+        })
+        .await;
+
+        let sent = room
+            .received("alice")
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::SessionSummary { .. }));
+        assert!(!sent);
+    }
+}
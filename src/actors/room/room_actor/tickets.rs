@@ -0,0 +1,168 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::room_actor::topic::MAX_TOPIC_LENGTH;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use tracing::debug;
+
+/// Most tickets a room may have queued.
+const MAX_TICKETS: usize = 500;
+
+impl RoomActor {
+    /// Replaces the queue of tickets to estimate. Blank tickets are skipped.
+    pub(super) fn load_tickets(&mut self, user_id: String, tickets: Vec<String>) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if tickets.len() > MAX_TICKETS
+            || tickets
+                .iter()
+                .any(|ticket| ticket.chars().count() > MAX_TOPIC_LENGTH)
+        {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to load too many or too long tickets."
+            );
+            return;
+        }
+
+        self.ticket_queue = tickets
+            .into_iter()
+            .map(|ticket| ticket.trim().to_string())
+            .filter(|ticket| !ticket.is_empty())
+            .collect();
+        self.notify_users(ClientResponseMessage::TicketsLoaded {
+            room_name: self.name.clone(),
+            remaining: self.ticket_queue.len(),
+        });
+    }
+
+    /// Starts a round about the next ticket of the queue. With no ticket left, the room is
+    /// told the queue is finished and stays as it is.
+    pub(super) fn next_ticket(&mut self, user_id: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        let room_name = self.name.clone();
+        if self.paused {
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+
+        let current = match self.ticket_queue.pop_front() {
+            None => {
+                self.notify_users(ClientResponseMessage::QueueFinished { room_name });
+                return;
+            }
+            Some(ticket) => ticket,
+        };
+        self.start_round();
+        self.start_ticket(current);
+    }
+
+    /// Moves the room on after a reveal: a new round starts about the next ticket of the
+    /// queue, or without a topic once the queue is empty.
+    pub(super) fn auto_advance(&mut self) {
+        self.start_round();
+        match self.ticket_queue.pop_front() {
+            None => self.change_topic(None),
+            Some(ticket) => self.start_ticket(ticket),
+        }
+    }
+
+    fn start_ticket(&mut self, current: String) {
+        self.change_topic(Some(current.clone()));
+        self.notify_users(ClientResponseMessage::TicketAdvanced {
+            room_name: self.name.clone(),
+            current,
+            remaining: self.ticket_queue.len(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::RoomMessage;
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    async fn room_with_tickets(tickets: &[&str]) -> TestRoom {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        room.send(RoomMessage::SetAutoNewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            auto_new_vote: true,
+        })
+        .await;
+        room.send(RoomMessage::LoadTickets {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            tickets: tickets.iter().map(|ticket| ticket.to_string()).collect(),
+        })
+        .await;
+        room.send(RoomMessage::NextTicket {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+        room
+    }
+
+    async fn vote_round(room: &TestRoom) {
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+    }
+
+    #[actix_rt::test]
+    async fn auto_new_vote_walks_through_the_queue() {
+        let room = room_with_tickets(&["first", "second"]).await;
+        assert_eq!(
+            room.run(|act, _| act.current_topic.clone())
+                .await
+                .as_deref(),
+            Some("first")
+        );
+
+        vote_round(&room).await;
+        assert_eq!(
+            room.run(|act, _| act.current_topic.clone())
+                .await
+                .as_deref(),
+            Some("second")
+        );
+        assert!(room.run(|act, _| act.vote_map.is_empty()).await);
+
+        vote_round(&room).await;
+        assert_eq!(room.run(|act, _| act.current_topic.clone()).await, None);
+        let topics: Vec<Option<String>> = room
+            .run(|act, _| {
+                act.history
+                    .iter()
+                    .map(|round| round.topic.clone())
+                    .collect()
+            })
+            .await;
+        assert_eq!(
+            topics,
+            vec![Some("first".to_string()), Some("second".to_string())]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use actix::{AsyncContext, Context, SpawnHandle};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Longest countdown a room may run.
+const MAX_TIMER_SECS: u64 = 60 * 60;
+const TICK: Duration = Duration::from_secs(1);
+
+/// A countdown shared by the room, ticking every second.
+pub(super) struct Timer {
+    /// Tells this countdown from the ones it replaced.
+    started_at: Instant,
+    remaining: u64,
+    reveal_on_timeout: bool,
+    handle: SpawnHandle,
+}
+
+impl RoomActor {
+    /// Starts a countdown for the room, replacing the one running.
+    pub(super) fn start_timer(
+        &mut self,
+        user_id: String,
+        seconds: u64,
+        reveal_on_timeout: bool,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if seconds == 0 || seconds > MAX_TIMER_SECS {
+            debug!(
+                user_id = %redact::id(&user_id),
+                seconds,
+                "Host tried to start a timer that is too long or too short."
+            );
+            return;
+        }
+
+        self.cancel_timer(ctx);
+        self.notify_users(ClientResponseMessage::TimerTick {
+            room_name: self.name.clone(),
+            remaining: seconds,
+        });
+        let timer = Timer {
+            started_at: Instant::now(),
+            remaining: seconds,
+            reveal_on_timeout,
+            handle: SpawnHandle::default(),
+        };
+        self.schedule_tick(timer, ctx);
+    }
+
+    pub(super) fn cancel_timer(&mut self, ctx: &mut Context<Self>) {
+        if let Some(timer) = self.timer.take() {
+            ctx.cancel_future(timer.handle);
+        }
+    }
+
+    fn schedule_tick(&mut self, mut timer: Timer, ctx: &mut Context<Self>) {
+        let started_at = timer.started_at;
+        timer.handle = ctx.run_later(TICK, move |act, ctx| act.tick(started_at, ctx));
+        self.timer = Some(timer);
+    }
+
+    /// Counts a second down. Where no context is at hand, as when a round starts, the timer
+    /// is dropped without being cancelled, so a tick of a timer that is gone does nothing.
+    fn tick(&mut self, started_at: Instant, ctx: &mut Context<Self>) {
+        let mut timer = match self.timer.take() {
+            Some(timer) if timer.started_at == started_at => timer,
+            timer => {
+                self.timer = timer;
+                return;
+            }
+        };
+        timer.remaining -= 1;
+        let room_name = self.name.clone();
+        if timer.remaining == 0 {
+            self.notify_users(ClientResponseMessage::TimerExpired { room_name });
+            if timer.reveal_on_timeout {
+                self.reveal_on_timeout();
+            }
+        } else {
+            self.notify_users(ClientResponseMessage::TimerTick {
+                room_name,
+                remaining: timer.remaining,
+            });
+            self.schedule_tick(timer, ctx);
+        }
+    }
+
+    /// Ends the round as if every active member that has not voted abstained. A round where
+    /// nobody voted is revealed with no votes.
+    fn reveal_on_timeout(&mut self) {
+        if self.round_closed() {
+            return;
+        }
+
+        let abstaining: Vec<String> = self
+            .user_map
+            .iter()
+            .filter(|(user_id, conn_info)| {
+                conn_info.active && !self.vote_map.contains_key(*user_id)
+            })
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        for user_id in abstaining {
+            self.vote_map.insert(user_id, None);
+        }
+        self.reveal();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::collections::HashMap;
+
+    async fn start_timer(room: &TestRoom, reveal_on_timeout: bool) {
+        room.send(RoomMessage::StartTimer {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            seconds: 1,
+            reveal_on_timeout,
+        })
+        .await;
+    }
+
+    /// Runs the last tick of the timer right away, instead of waiting for it.
+    async fn expire(room: &TestRoom) {
+        room.run(|act, ctx| {
+            let started_at = act.timer.as_ref().unwrap().started_at;
+            act.tick(started_at, ctx);
+        })
+        .await;
+    }
+
+    fn results(received: &[ClientResponseMessage]) -> Option<HashMap<String, Option<String>>> {
+        received.iter().find_map(|msg| match msg {
+            ClientResponseMessage::VoteResults { votes, .. } => Some(votes.clone()),
+            _ => None,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn expiry_reveals_with_the_silent_abstaining() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        start_timer(&room, true).await;
+        room.vote("alice", "3").await;
+        expire(&room).await;
+
+        let votes = results(&room.received("bob").await).unwrap();
+        assert_eq!(votes["alice"].as_deref(), Some("3"));
+        assert_eq!(votes["bob"], None);
+    }
+
+    #[actix_rt::test]
+    async fn expiry_of_a_silent_round_reveals_no_votes() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join_with("alice", None, true).await;
+        start_timer(&room, true).await;
+        expire(&room).await;
+
+        let votes = results(&room.received("alice").await).unwrap();
+        assert!(votes.is_empty());
+        assert!(room.run(|act, _| act.voting_over()).await);
+    }
+
+    #[actix_rt::test]
+    async fn votes_after_expiry_are_refused() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        start_timer(&room, true).await;
+        expire(&room).await;
+        room.received("alice").await;
+
+        room.vote("bob", "5").await;
+        let received = room.received("bob").await;
+        assert!(received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::VotingOver)));
+        assert!(results(&room.received("alice").await).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn expiry_without_reveal_leaves_the_round_open() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.join("bob").await;
+        start_timer(&room, false).await;
+        expire(&room).await;
+
+        let received = room.received("alice").await;
+        assert!(received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::TimerExpired { .. })));
+        assert!(results(&received).is_none());
+        assert!(!room.run(|act, _| act.voting_over()).await);
+    }
+}
@@ -0,0 +1,77 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::messages::ClientResponseMessage;
+use crate::actors::room::RoomActor;
+use crate::redact;
+use tracing::debug;
+
+/// Longest topic a round may have, in characters.
+pub(super) const MAX_TOPIC_LENGTH: usize = 200;
+
+impl RoomActor {
+    /// Sets what the current round is about. A blank topic clears it.
+    pub(super) fn set_topic(&mut self, user_id: String, topic: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User tried to set the topic of a room they is not in."
+            );
+            return;
+        }
+        if topic.chars().count() > MAX_TOPIC_LENGTH {
+            debug!(user_id = %redact::id(&user_id), "User tried to set a topic that is too long.");
+            return;
+        }
+
+        let topic = Some(topic.trim().to_string()).filter(|topic| !topic.is_empty());
+        self.change_topic(topic);
+    }
+
+    pub(super) fn change_topic(&mut self, topic: Option<String>) {
+        if self.current_topic == topic {
+            return;
+        }
+        self.current_topic = topic.clone();
+        self.notify_users(ClientResponseMessage::TopicChanged {
+            room_name: self.name.clone(),
+            topic,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{GetRoomSummary, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+
+    #[actix_rt::test]
+    async fn room_summary_has_the_topic() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        room.send(RoomMessage::SetTopic {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: "login page".to_string(),
+        })
+        .await;
+
+        let summary = room.addr.send(GetRoomSummary).await.unwrap().unwrap();
+        assert_eq!(summary.topic.as_deref(), Some("login page"));
+    }
+}
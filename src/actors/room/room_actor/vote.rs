@@ -16,71 +16,771 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::actors::lifecycle::{self, Event};
 use crate::actors::messages::ClientResponseMessage;
 use crate::actors::room::RoomActor;
+use crate::data::VoteSummary;
+use crate::redact;
 use std::collections::HashMap;
+use std::time::Instant;
+use tracing::debug;
+
+/// Range of the confidence a voter may give their vote.
+const MIN_CONFIDENCE: u8 = 1;
+const MAX_CONFIDENCE: u8 = 5;
 
 impl RoomActor {
-    pub(super) fn vote(&mut self, user_id: String, size: u64) {
-        if self.voting_over() {
-            match self.user_map.get(&user_id) {
-                None => println!("RoomActor: User tried to cast vote in a room he is not in."),
-                Some(user) => {
-                    let msg = ClientResponseMessage::VotingOver;
-                    self.notify_user(&user.user.user_id, &user.recipient, msg);
-                }
-            }
-        } else {
-            match self.user_map.get(&user_id) {
-                None => println!("RoomActor: User tried to cast vote in a room he is not in."),
-                Some(user) => {
-                    let room_name = self.name.clone();
-                    let msg = ClientResponseMessage::OwnVote { room_name, size };
-                    self.notify_user(&user.user.user_id, &user.recipient, msg);
-                }
+    /// Records a vote, which must be one of the values of the selected scale. The confidence,
+    /// if given, must be within its range.
+    pub(super) fn vote(&mut self, user_id: String, size: String, confidence: Option<u8>) {
+        if !self.on_selected_scale(&size) {
+            let room_name = self.name.clone();
+            let msg = ClientResponseMessage::InvalidVote { room_name, size };
+            self.notify_user_id(&user_id, msg);
+            return;
+        }
+        if let Some(confidence) = confidence {
+            if !(MIN_CONFIDENCE..=MAX_CONFIDENCE).contains(&confidence) {
+                let room_name = self.name.clone();
+                let msg = ClientResponseMessage::InvalidConfidence {
+                    room_name,
+                    confidence,
+                };
+                self.notify_user_id(&user_id, msg);
+                return;
             }
+        }
+        self.cast_vote(user_id, Some(size), confidence);
+    }
 
-            let already_voted = self.vote_map.contains_key(&user_id);
-            self.vote_map.insert(user_id, size);
+    /// The size is one of the values of the selected scale.
+    pub(super) fn on_selected_scale(&self, size: &str) -> bool {
+        self.scale_values
+            .get(&self.selected_scale_name)
+            .is_some_and(|scale| scale.values.iter().any(|value| value == size))
+    }
 
-            if !already_voted {
-                self.send_vote_info();
-            }
+    /// Records an explicit abstention. It counts as a decision, but carries no size.
+    pub(super) fn abstain(&mut self, user_id: String) {
+        self.cast_vote(user_id, None, None);
+    }
+
+    fn cast_vote(&mut self, user_id: String, size: Option<String>, confidence: Option<u8>) {
+        let room_name = self.name.clone();
+        if self.paused {
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
+            return;
+        }
+        if !self.is_active(&user_id) {
+            self.notify_user_id(&user_id, ClientResponseMessage::NotAuthorized { room_name });
+            return;
+        }
+        // late votes must never change revealed results, only a new vote reopens the round
+        if self.round_closed() {
+            self.notify_user_id(&user_id, ClientResponseMessage::VotingOver);
+            return;
+        }
+
+        self.notify_user_id(
+            &user_id,
+            ClientResponseMessage::OwnVote {
+                room_name,
+                size: size.clone(),
+            },
+        );
+        self.last_activity = Instant::now();
+        match confidence {
+            None => self.confidence_map.remove(&user_id),
+            Some(confidence) => self.confidence_map.insert(user_id.clone(), confidence),
+        };
+        self.vote_map.insert(user_id, size);
+        self.send_vote_info();
+    }
+
+    /// Reveals the round once the quorum is reached, if the room reveals on its own, or says
+    /// who has voted otherwise. Nothing is sent once the results are out, so members coming
+    /// and going cannot reopen a revealed round.
+    pub(super) fn send_vote_info(&mut self) {
+        if self.voting_over {
+            return;
+        }
+        if self.reveal_on_complete && !self.revealed_to_host && self.quorum_reached() {
+            self.reveal();
+            return;
         }
+
+        let votes = self.vote_status();
+        // clients already have this status, no need to send it again
+        if self.last_vote_status.as_ref() == Some(&votes) {
+            return;
+        }
+        self.last_vote_status = Some(votes.clone());
+        let room_name = self.name.clone();
+        let msg = ClientResponseMessage::VoteStatus { room_name, votes };
+        self.notify_users(msg);
+    }
+
+    /// Ends the round and sends the results to everyone. This is the only place a round
+    /// is revealed.
+    pub(super) fn reveal(&mut self) {
+        self.voting_over = true;
+        lifecycle::emit(Event::Reveal, Some(&self.name), None);
+        self.last_vote_status = None;
+        self.notify_users(self.results_msg());
+        self.send_outliers();
+        self.record_estimate();
+        if self.auto_new_vote {
+            self.auto_advance();
+        }
+    }
+
+    /// Sends the requester what was last broadcast about the round: the results if they are
+    /// public, or who has voted otherwise.
+    pub(super) fn get_vote_status(&self, user_id: String) {
+        if !self.user_map.contains_key(&user_id) {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User asked for the vote status of a room they is not in."
+            );
+            return;
+        }
+
+        let msg = if self.voting_over() {
+            self.results_msg()
+        } else {
+            let room_name = self.name.clone();
+            let votes = self.vote_status();
+            ClientResponseMessage::VoteStatus { room_name, votes }
+        };
+        self.notify_user_id(&user_id, msg);
     }
 
-    pub(super) fn send_vote_info(&self) {
+    /// The results of the round, anonymous if the room reveals them that way.
+    pub(super) fn results_msg(&self) -> ClientResponseMessage {
         let room_name = self.name.clone();
-        if self.voting_over() {
-            let votes = self.vote_map.clone();
-            let msg = ClientResponseMessage::VoteResults { room_name, votes };
-            self.notify_users(msg);
+        // a round revealed before anyone voted still gets results, just empty ones
+        let summary = VoteSummary::from_votes(self.vote_map.values());
+        let implied_summary = self.implied_summary();
+        let confidence = self.confidence();
+        if self.anonymous_reveal && !self.votes_attributed {
+            let mut votes: Vec<Option<String>> = self.vote_map.values().cloned().collect();
+            votes.sort();
+            let mut confidence: Vec<u8> = confidence.into_values().collect();
+            confidence.sort();
+            ClientResponseMessage::AnonymousVoteResults {
+                room_name,
+                votes,
+                summary,
+                implied_summary,
+                confidence,
+            }
         } else {
-            let mut votes = HashMap::new();
-            for user_id in self.user_map.keys() {
-                let has_voted = self.vote_map.contains_key(user_id);
-                votes.insert(user_id.to_owned(), has_voted);
+            let votes = self.vote_map.clone();
+            ClientResponseMessage::VoteResults {
+                room_name,
+                votes,
+                summary,
+                implied_summary,
+                confidence,
             }
-            let msg = ClientResponseMessage::VoteStatus { room_name, votes };
-            self.notify_users(msg);
         }
     }
 
-    pub(super) fn new_vote(&mut self, user_id: String) {
+    /// The confidence of the votes that are still in the round.
+    pub(super) fn confidence(&self) -> HashMap<String, u8> {
+        self.confidence_map
+            .iter()
+            .filter(|(user_id, _)| self.vote_map.contains_key(*user_id))
+            .map(|(user_id, confidence)| (user_id.clone(), *confidence))
+            .collect()
+    }
+
+    /// Whether each active user has voted.
+    fn vote_status(&self) -> HashMap<String, bool> {
+        self.user_map
+            .iter()
+            .filter(|(_, conn_info)| conn_info.active)
+            .map(|(user_id, _)| (user_id.clone(), self.vote_map.contains_key(user_id)))
+            .collect()
+    }
+
+    pub(super) fn new_vote(&mut self, user_id: String, topic: Option<String>) {
         if !self.user_map.contains_key(&user_id) {
-            println!("RoomActor: User tried to request new vote in a room they is not in.");
+            debug!(
+                user_id = %redact::id(&user_id),
+                "User tried to request new vote in a room they is not in."
+            );
+            return;
+        }
+
+        if self.paused {
+            let room_name = self.name.clone();
+            self.notify_user_id(&user_id, ClientResponseMessage::RoomPaused { room_name });
             return;
         }
 
+        self.start_round();
+        if let Some(topic) = topic {
+            self.set_topic(user_id, topic);
+        }
+    }
+
+    /// Keeps the finished round in the history and starts a new one.
+    pub(super) fn start_round(&mut self) {
+        self.archive_round();
+        self.round_started_at = Instant::now();
+        self.timer = None;
         self.voting_over = false;
+        self.revealed_to_host = false;
+        self.votes_attributed = false;
+        self.last_randomized = None;
+        self.last_vote_status = None;
         self.vote_map.clear();
+        self.confidence_map.clear();
 
         self.notify_users(ClientResponseMessage::NewVote {
             room_name: self.name.clone(),
         });
     }
 
-    fn voting_over(&self) -> bool {
-        self.vote_map.len() == self.user_map.len()
+    pub(super) fn set_auto_new_vote(&mut self, user_id: String, auto_new_vote: bool) {
+        if !self.check_host(&user_id) || self.auto_new_vote == auto_new_vote {
+            return;
+        }
+
+        self.auto_new_vote = auto_new_vote;
+        self.notify_users(ClientResponseMessage::AutoNewVoteChanged {
+            room_name: self.name.clone(),
+            auto_new_vote,
+        });
+    }
+
+    pub(super) fn set_abstentions_count(&mut self, user_id: String, abstentions_count: bool) {
+        if !self.check_host(&user_id) || self.abstentions_count == abstentions_count {
+            return;
+        }
+        // changing it now could take back results already revealed
+        if self.round_closed() {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to change the abstention policy of a closed round."
+            );
+            return;
+        }
+
+        self.abstentions_count = abstentions_count;
+        self.notify_users(ClientResponseMessage::AbstentionsCountChanged {
+            room_name: self.name.clone(),
+            abstentions_count,
+        });
+        self.send_vote_info();
+    }
+
+    /// Reopens a revealed round only for those who did not vote the most voted size.
+    /// Ties are settled in favor of the size that comes first in the scale.
+    pub(super) fn revote_dissenters(&mut self, user_id: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if !self.voting_over() {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to revote dissenters before the results were revealed."
+            );
+            return;
+        }
+
+        let mode = match self.mode() {
+            None => return,
+            Some(mode) => mode,
+        };
+        let mut dissenters: Vec<String> = self
+            .vote_map
+            .iter()
+            .filter(|(_, size)| size.as_ref() != Some(&mode))
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        dissenters.sort();
+        self.revote(dissenters);
+    }
+
+    /// Reopens a revealed round only for those who voted the smallest or the largest size.
+    pub(super) fn revote_outliers(&mut self, user_id: String) {
+        if !self.check_host(&user_id) {
+            return;
+        }
+        if !self.voting_over() {
+            debug!(
+                user_id = %redact::id(&user_id),
+                "Host tried to revote outliers before the results were revealed."
+            );
+            return;
+        }
+
+        if let Some((mut lowest, highest)) = self.outliers() {
+            lowest.extend(highest);
+            lowest.sort();
+            self.revote(lowest);
+        }
+    }
+
+    /// Drops the votes of the users and reopens the round for them. The other votes are kept.
+    fn revote(&mut self, user_ids: Vec<String>) {
+        if user_ids.is_empty() {
+            return;
+        }
+
+        for user_id in user_ids.iter() {
+            self.vote_map.remove(user_id);
+        }
+        self.voting_over = false;
+        self.revealed_to_host = false;
+        self.votes_attributed = false;
+        self.last_vote_status = None;
+
+        self.notify_users(ClientResponseMessage::RevoteRequested {
+            room_name: self.name.clone(),
+            user_ids,
+        });
+        self.send_vote_info();
+    }
+
+    /// The most voted size. Abstentions are not counted.
+    pub(super) fn mode(&self) -> Option<String> {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for size in self.vote_map.values().flatten() {
+            *counts.entry(size).or_insert(0) += 1;
+        }
+        let position = |size: &String| {
+            self.scale_values
+                .get(&self.selected_scale_name)
+                .and_then(|scale| scale.values.iter().position(|value| value == size))
+        };
+        counts
+            .into_iter()
+            .max_by(|(a_size, a_count), (b_size, b_count)| {
+                a_count
+                    .cmp(b_count)
+                    .then(position(b_size).cmp(&position(a_size)))
+            })
+            .map(|(size, _)| size.clone())
+    }
+
+    /// Whether the results were revealed, to everyone or just to the hosts. No vote may
+    /// change a closed round.
+    pub(super) fn round_closed(&self) -> bool {
+        self.voting_over() || self.revealed_to_host
+    }
+
+    /// Whether the results are public. While only the host can see the results, or when the
+    /// room waits for a reveal, the round does not end even if everyone has voted.
+    pub(super) fn voting_over(&self) -> bool {
+        self.voting_over
+    }
+
+    /// Whether enough active users have voted for the round to end. A room where no one is
+    /// active never reaches it. Every check on how many have voted must go through here.
+    pub(super) fn quorum_reached(&self) -> bool {
+        let active = self.member_counts().active;
+        if active == 0 {
+            return false;
+        }
+        let votes = if self.abstentions_count {
+            self.vote_map.len()
+        } else {
+            self.vote_map.values().filter(|size| size.is_some()).count()
+        };
+        votes * 100 >= active * self.config.quorum_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+    use crate::actors::test_util::{self, TestRoom, ROOM};
+    use std::collections::HashMap;
+
+    fn results(received: &[ClientResponseMessage]) -> usize {
+        received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::VoteResults { .. }))
+            .count()
+    }
+
+    async fn room_with(users: &[&str], quorum_percent: usize) -> TestRoom {
+        let mut config = test_util::config();
+        config.quorum_percent = quorum_percent;
+        let mut room = TestRoom::start(config);
+        for user_id in users {
+            room.join(user_id).await;
+        }
+        room
+    }
+
+    #[actix_rt::test]
+    async fn reaching_the_quorum_ends_the_round() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        assert!(!room.run(|act, _| act.voting_over()).await);
+
+        room.vote("bob", "5").await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+        assert_eq!(results(&room.received("alice").await), 1);
+    }
+
+    #[actix_rt::test]
+    async fn votes_after_a_quorum_reveal_are_refused() {
+        let room = room_with(&["alice", "bob"], 50).await;
+        room.vote("alice", "3").await;
+        assert_eq!(results(&room.received("alice").await), 1);
+        room.received("bob").await;
+
+        room.vote("bob", "5").await;
+        let received = room.received("bob").await;
+        assert!(received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::VotingOver)));
+        assert_eq!(results(&received), 0);
+        assert_eq!(results(&room.received("alice").await), 0);
+    }
+
+    #[actix_rt::test]
+    async fn joining_after_a_quorum_reveal_does_not_reopen_the_round() {
+        let mut room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+        room.received("alice").await;
+
+        room.join("carol").await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+        assert_eq!(results(&room.received("alice").await), 0);
+    }
+
+    async fn abstain(room: &TestRoom, user_id: &str) {
+        room.send(RoomMessage::Abstain {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn explicit_abstention_counts_toward_the_quorum() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        abstain(&room, "bob").await;
+
+        assert!(room.run(|act, _| act.voting_over()).await);
+        let summary = room
+            .received("alice")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::VoteResults { votes, summary, .. } => Some((votes, summary)),
+                _ => None,
+            });
+        let (votes, summary) = summary.unwrap();
+        assert_eq!(votes["bob"], None);
+        assert_eq!(summary.unwrap().average, 3.0);
+    }
+
+    #[actix_rt::test]
+    async fn silent_member_is_not_decided() {
+        let room = room_with(&["alice", "bob", "carol"], 100).await;
+        abstain(&room, "alice").await;
+        room.vote("bob", "3").await;
+
+        assert!(!room.run(|act, _| act.quorum_reached()).await);
+        let status = room.run(|act, _| act.vote_status()).await;
+        assert!(status["alice"]);
+        assert!(status["bob"]);
+        assert!(!status["carol"]);
+    }
+
+    async fn quorum_reached(room: &TestRoom) -> bool {
+        room.run(|act, _| act.quorum_reached()).await
+    }
+
+    #[actix_rt::test]
+    async fn room_without_active_members_has_no_quorum() {
+        let mut room = TestRoom::start(test_util::config());
+        room.join_with("alice", None, true).await;
+        assert!(!quorum_reached(&room).await);
+    }
+
+    #[actix_rt::test]
+    async fn passive_members_are_left_out_of_the_quorum() {
+        let mut room = room_with(&["alice"], 100).await;
+        room.join_with("bob", None, true).await;
+        room.vote("alice", "3").await;
+        assert!(quorum_reached(&room).await);
+    }
+
+    #[actix_rt::test]
+    async fn round_completes_once_every_active_member_voted() {
+        let mut room = room_with(&["alice", "bob"], 100).await;
+        room.join_with("carol", None, true).await;
+        room.vote("alice", "3").await;
+        assert!(!room.run(|act, _| act.voting_over()).await);
+
+        room.vote("bob", "5").await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+        assert_eq!(results(&room.received("carol").await), 1);
+    }
+
+    #[actix_rt::test]
+    async fn quorum_honors_the_threshold() {
+        let room = room_with(&["alice", "bob", "carol"], 50).await;
+        room.vote("alice", "3").await;
+        assert!(!quorum_reached(&room).await);
+        room.vote("bob", "5").await;
+        assert!(quorum_reached(&room).await);
+    }
+
+    #[actix_rt::test]
+    async fn abstentions_may_be_left_out_of_the_quorum() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.run(|act, _| act.abstentions_count = false).await;
+        room.vote("alice", "3").await;
+        abstain(&room, "bob").await;
+        assert!(!quorum_reached(&room).await);
+    }
+
+    async fn set_abstentions_count(room: &TestRoom, abstentions_count: bool) {
+        room.send(RoomMessage::SetAbstentionsCount {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            abstentions_count,
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn all_abstaining_reaches_the_quorum_when_abstentions_count() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        set_abstentions_count(&room, true).await;
+        abstain(&room, "alice").await;
+        abstain(&room, "bob").await;
+        assert!(quorum_reached(&room).await);
+        assert_eq!(results(&room.received("alice").await), 1);
+    }
+
+    #[actix_rt::test]
+    async fn all_abstaining_falls_short_when_abstentions_do_not_count() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        set_abstentions_count(&room, false).await;
+        abstain(&room, "alice").await;
+        abstain(&room, "bob").await;
+        assert!(!quorum_reached(&room).await);
+
+        let received = room.received("bob").await;
+        assert_eq!(results(&received), 0);
+        assert!(received.iter().any(|msg| matches!(
+            msg,
+            ClientResponseMessage::AbstentionsCountChanged {
+                abstentions_count: false,
+                ..
+            }
+        )));
+    }
+
+    fn statuses(received: &[ClientResponseMessage]) -> usize {
+        received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::VoteStatus { .. }))
+            .count()
+    }
+
+    fn new_vote() -> RoomMessage {
+        RoomMessage::NewVote {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            topic: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn redundant_vote_status_is_not_sent_again() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        assert_eq!(statuses(&room.received("bob").await), 1);
+
+        room.vote("alice", "5").await;
+        assert_eq!(statuses(&room.received("bob").await), 0);
+    }
+
+    #[actix_rt::test]
+    async fn new_votes_are_always_sent() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        room.send(new_vote()).await;
+        room.send(new_vote()).await;
+        let received = room.received("bob").await;
+        let new_votes = received
+            .iter()
+            .filter(|msg| matches!(msg, ClientResponseMessage::NewVote { .. }))
+            .count();
+        assert_eq!(new_votes, 2);
+
+        // the same status as before the new vote is news in the new round
+        room.vote("alice", "3").await;
+        assert_eq!(statuses(&room.received("bob").await), 1);
+    }
+
+    #[actix_rt::test]
+    async fn forced_reveal_of_a_silent_round_yields_empty_results() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.send(RoomMessage::RevealToAll {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+
+        let results = room
+            .received("bob")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::VoteResults {
+                    votes,
+                    summary,
+                    implied_summary,
+                    ..
+                } => Some((votes, summary, implied_summary)),
+                _ => None,
+            });
+        let (votes, summary, implied_summary) = results.unwrap();
+        assert!(votes.is_empty());
+        assert!(summary.is_none());
+        assert!(implied_summary.is_none());
+    }
+
+    async fn revote_dissenters(room: &TestRoom) {
+        room.send(RoomMessage::RevoteDissenters {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn only_dissenters_are_asked_to_revote() {
+        let room = room_with(&["alice", "bob", "carol"], 100).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "3").await;
+        room.vote("carol", "5").await;
+        room.received("bob").await;
+
+        revote_dissenters(&room).await;
+        let asked = room
+            .received("bob")
+            .await
+            .into_iter()
+            .find_map(|msg| match msg {
+                ClientResponseMessage::RevoteRequested { user_ids, .. } => Some(user_ids),
+                _ => None,
+            });
+        assert_eq!(asked, Some(vec!["carol".to_string()]));
+        let votes = room.run(|act, _| act.vote_map.clone()).await;
+        assert_eq!(votes.len(), 2);
+        assert_eq!(votes["alice"], Some("3".to_string()));
+        assert_eq!(votes["bob"], Some("3".to_string()));
+        assert!(!room.run(|act, _| act.voting_over()).await);
+
+        room.vote("carol", "3").await;
+        assert!(room.run(|act, _| act.voting_over()).await);
+    }
+
+    #[actix_rt::test]
+    async fn open_rounds_are_not_revoted() {
+        let room = room_with(&["alice", "bob", "carol"], 100).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+        revote_dissenters(&room).await;
+
+        let votes = room.run(|act, _| act.vote_map.clone()).await;
+        assert_eq!(votes["bob"], Some("5".to_string()));
+    }
+
+    fn status(received: &[ClientResponseMessage]) -> Option<HashMap<String, bool>> {
+        received.iter().find_map(|msg| match msg {
+            ClientResponseMessage::VoteStatus { votes, .. } => Some(votes.clone()),
+            _ => None,
+        })
+    }
+
+    async fn get_vote_status(room: &TestRoom, user_id: &str) {
+        room.send(RoomMessage::GetVoteStatus {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+        })
+        .await;
+    }
+
+    #[actix_rt::test]
+    async fn status_on_demand_matches_the_last_broadcast() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        let broadcast = status(&room.received("bob").await).unwrap();
+        room.received("alice").await;
+
+        get_vote_status(&room, "bob").await;
+        assert_eq!(status(&room.received("bob").await), Some(broadcast));
+        assert!(room.received("alice").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn status_on_demand_of_a_revealed_round_is_the_results() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.vote("alice", "3").await;
+        room.vote("bob", "5").await;
+        room.received("bob").await;
+
+        get_vote_status(&room, "bob").await;
+        assert_eq!(results(&room.received("bob").await), 1);
+    }
+
+    fn invalid_vote(received: &[ClientResponseMessage], expected: &str) -> bool {
+        received.iter().any(|msg| {
+            matches!(msg, ClientResponseMessage::InvalidVote { size, .. } if size == expected)
+        })
+    }
+
+    #[actix_rt::test]
+    async fn vote_on_the_scale_is_counted() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.received("bob").await;
+        room.vote("alice", "13").await;
+
+        assert_eq!(statuses(&room.received("bob").await), 1);
+        let size = room.run(|act, _| act.vote_map["alice"].clone()).await;
+        assert_eq!(size.as_deref(), Some("13"));
+    }
+
+    #[actix_rt::test]
+    async fn vote_off_the_scale_is_refused() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.received("bob").await;
+        room.vote("alice", "9999").await;
+
+        assert!(invalid_vote(&room.received("alice").await, "9999"));
+        assert_eq!(statuses(&room.received("bob").await), 0);
+        assert!(room.run(|act, _| act.vote_map.is_empty()).await);
+    }
+
+    #[actix_rt::test]
+    async fn value_of_the_previous_scale_is_refused() {
+        let room = room_with(&["alice", "bob"], 100).await;
+        room.send(RoomMessage::ChangeScale {
+            room_name: ROOM.to_string(),
+            user_id: "alice".to_string(),
+            selected_scale_name: "fistOfFive".to_string(),
+        })
+        .await;
+        room.received("alice").await;
+        room.vote("alice", "13").await;
+
+        assert!(invalid_vote(&room.received("alice").await, "13"));
+        assert!(room.run(|act, _| act.vote_map.is_empty()).await);
     }
 }
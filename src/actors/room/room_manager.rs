@@ -16,35 +16,161 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::actors::messages::{ClientResponseMessage, RoomMessage};
+use crate::actors::messages::{
+    ClientMessage, ClientResponseMessage, GetMemberCounts, GetRoomSummaries, GetRoomSummary,
+    Recycle, Retire, RoomMessage, Shutdown,
+};
 use crate::actors::room::RoomActor;
-use crate::data::UserData;
+use crate::config::Config;
+use crate::data::{MemberCounts, RoomSummary, Scale, UserData};
+use crate::redact;
 use actix::prelude::*;
 use actix::Actor;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How long connections have to answer a diagnostic ping before being counted as unresponsive
+const PING_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
 
 /// Room manager. This is an actor that knows about all the created rooms and where each user is.
 pub struct RoomManagerActor {
     rooms: HashMap<String, Addr<RoomActor>>,
-    user_room_map: HashMap<String, String>,
+    /// Rooms each user is in. Users in no room have no entry.
+    user_room_map: HashMap<String, HashSet<String>>,
     room_name_validator: Regex,
+    config: Config,
+    clients: HashMap<String, Client>,
+    ping_check: Option<PingCheck>,
+    /// Room actors without a room, waiting to be reused.
+    idle_rooms: Vec<Addr<RoomActor>>,
+    /// IP address each open room was created from.
+    room_creators: HashMap<String, IpAddr>,
+    /// How many open rooms were created from each IP address.
+    rooms_per_ip: HashMap<IpAddr, usize>,
+    /// Open rooms that are listed for everyone.
+    public_rooms: HashSet<String>,
+}
+
+/// A connected client, whether or not it is in a room.
+struct Client {
+    /// Secret a new connection must present to take back this user id.
+    resume_token: String,
+    recipient: Recipient<ClientMessage>,
+    responder: Recipient<ClientResponseMessage>,
+}
+
+/// What a room is created with.
+#[derive(Default)]
+struct NewRoom {
+    password: Option<String>,
+    password_is_hash: bool,
+    custom_scale: Option<Scale>,
+    /// Listed for everyone.
+    public: bool,
+}
+
+/// A diagnostic ping of every connection, waiting for answers.
+struct PingCheck {
+    admin: Recipient<ClientResponseMessage>,
+    pinged: HashSet<String>,
+    responsive: HashSet<String>,
 }
 
 impl Actor for RoomManagerActor {
     type Context = Context<Self>;
+
+    /// Fills the pool of idle rooms, so the first rooms are not started on demand.
+    fn started(&mut self, ctx: &mut Self::Context) {
+        while self.idle_rooms.len() < self.config.room_pool_size {
+            let room_actor = self.start_room(String::new(), NewRoom::default(), ctx);
+            self.idle_rooms.push(room_actor);
+        }
+    }
 }
 
 impl RoomManagerActor {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         Self {
             rooms: HashMap::new(),
             user_room_map: HashMap::new(),
             room_name_validator: Regex::new(r"^[-_a-zA-Z]{1,50}$").unwrap(),
+            config,
+            clients: HashMap::new(),
+            ping_check: None,
+            idle_rooms: Vec::new(),
+            room_creators: HashMap::new(),
+            rooms_per_ip: HashMap::new(),
+            public_rooms: HashSet::new(),
         }
     }
 }
 
+impl Handler<Shutdown> for RoomManagerActor {
+    type Result = ResponseFuture<Result<(), ()>>;
+
+    /// Drains every room, resolving once all of them have exported their results. Clients
+    /// outside a room are told right away, rooms tell their own members.
+    fn handle(&mut self, _: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        for (user_id, client) in self.clients.iter() {
+            if !self.user_room_map.contains_key(user_id) {
+                let _ = client
+                    .responder
+                    .do_send(ClientResponseMessage::ServerShuttingDown);
+            }
+        }
+        info!(rooms = self.rooms.len(), "Draining rooms.");
+        let drains: Vec<_> = self
+            .rooms
+            .values()
+            .map(|room| room.send(Shutdown))
+            .collect();
+        Box::pin(async move {
+            futures::future::join_all(drains).await;
+            Ok(())
+        })
+    }
+}
+
+impl Handler<GetMemberCounts> for RoomManagerActor {
+    type Result = ResponseFuture<Result<MemberCounts, ()>>;
+
+    /// Asks the room for its counts. Unknown rooms are an error.
+    fn handle(&mut self, msg: GetMemberCounts, _: &mut Context<Self>) -> Self::Result {
+        let counts = self.rooms.get(&msg.room_name).map(|room| room.send(msg));
+        Box::pin(async move {
+            match counts {
+                None => Err(()),
+                Some(counts) => counts.await.unwrap_or(Err(())),
+            }
+        })
+    }
+}
+
+impl Handler<GetRoomSummaries> for RoomManagerActor {
+    type Result = ResponseFuture<Result<Vec<RoomSummary>, ()>>;
+
+    /// Asks every room for its summary. Rooms that do not answer are left out.
+    fn handle(&mut self, _: GetRoomSummaries, _: &mut Context<Self>) -> Self::Result {
+        let summaries: Vec<_> = self
+            .rooms
+            .values()
+            .map(|room| room.send(GetRoomSummary))
+            .collect();
+        Box::pin(async move {
+            let mut rooms: Vec<RoomSummary> = futures::future::join_all(summaries)
+                .await
+                .into_iter()
+                .filter_map(|summary| summary.ok().and_then(|summary| summary.ok()))
+                .collect();
+            rooms.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(rooms)
+        })
+    }
+}
+
 impl Handler<RoomMessage> for RoomManagerActor {
     type Result = ();
 
@@ -56,140 +182,826 @@ impl Handler<RoomMessage> for RoomManagerActor {
                 ref user,
                 ref password_is_hash,
                 ref recipient,
+                ip,
+                ref custom_scale,
+                public,
+                ..
             } => {
                 self.join_room(
                     room_name.to_owned(),
                     password.to_owned(),
-                    password_is_hash.clone(),
+                    *password_is_hash,
                     user.user_id.to_owned(),
                     recipient.clone(),
+                    ip,
+                    custom_scale.clone(),
+                    public,
                     msg,
                     ctx,
                 );
             }
             RoomMessage::UserUpdated { user } => self.user_updated(user),
-            RoomMessage::LeaveRoom { user_id, room_name } => self.leave_room(user_id, room_name),
+            RoomMessage::LeaveRoom {
+                user_id,
+                room_name,
+                recipient,
+            } => self.leave_room(user_id, room_name, recipient),
             RoomMessage::UserLeft { user_id } => self.user_left(user_id),
-            RoomMessage::Vote { ref room_name, .. } => self.forward(room_name.clone(), msg),
-            RoomMessage::NewVote { ref room_name, .. } => self.forward(room_name.clone(), msg),
+            RoomMessage::UserRemoved { room_name, user_id } => {
+                self.remove_from_room(&user_id, &room_name)
+            }
+            RoomMessage::UserDropped { user_id } => self.user_dropped(user_id),
             RoomMessage::RoomClosing { room_name } => self.room_closing(room_name),
-            RoomMessage::Randomize { ref room_name } => self.forward(room_name.clone(), msg),
-            _ => {}
+            RoomMessage::Resume {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::UpdateActive {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetAllActive {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::Vote {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::Abstain {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::NewVote {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetTopic {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::LoadTickets {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::NextTicket {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetEstimate {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::ExportEstimates {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::StartTimer {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RevoteDissenters {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RevoteOutliers {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetVoteStatus {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetHistory {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetUsers {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetSessionSummary {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetRoomStatistics {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::ResetRoom {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::CreateSubPoll {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SubPollVote {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RevealSubPoll {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::Randomize {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::GetLastRandomize {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::PauseRoom {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::ResumeRoom {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::ChangeScale {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::CreateScale {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::DeleteScale {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RevealToHost {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RevealToAll {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetAnonymousReveal {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetRevealPolicy {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetRevealOnComplete {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetLocked {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RenameRoom {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetReference {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetUniqueNames {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetAutoNewVote {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetAnonymizeHistory {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::SetAbstentionsCount {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::AttributeVotes {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::AddCoHost {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::RemoveCoHost {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::TransferHost {
+                ref room_name,
+                ref user_id,
+                ..
+            }
+            | RoomMessage::Kick {
+                ref room_name,
+                ref user_id,
+                ..
+            } => {
+                let (room_name, user_id) = (room_name.clone(), user_id.clone());
+                self.forward_from_member(&user_id, room_name, msg);
+            }
+            RoomMessage::ClientConnected {
+                user_id,
+                resume_token,
+                recipient,
+                responder,
+            } => {
+                let client = Client {
+                    resume_token,
+                    recipient,
+                    responder,
+                };
+                self.clients.insert(user_id, client);
+            }
+            RoomMessage::ListScales { recipient } => self.list_scales(recipient),
+            RoomMessage::ListRooms { recipient } => self.list_rooms(recipient),
+            RoomMessage::Reclaim {
+                room_name,
+                user_id,
+                resume_token,
+                new_user_id,
+                since_seq,
+            } => self.reclaim(room_name, user_id, resume_token, new_user_id, since_seq),
+            RoomMessage::RenameApproved {
+                room_name,
+                user_id,
+                new_name,
+            } => self.rename_room(room_name, user_id, new_name),
+            RoomMessage::Reconnected { .. } | RoomMessage::SetName { .. } => {
+                warn!("Reconnects are sent to rooms, not to the manager.")
+            }
+            RoomMessage::PingAll { secret, recipient } => self.ping_all(secret, recipient, ctx),
+            RoomMessage::PingReport { user_id } => self.ping_report(user_id),
+            RoomMessage::SnapshotRoom {
+                ref room_name,
+                ref secret,
+                ref recipient,
+            }
+            | RoomMessage::RestoreRoom {
+                ref room_name,
+                ref secret,
+                ref recipient,
+                ..
+            } => {
+                if self.is_admin(secret, recipient) {
+                    self.forward(room_name.clone(), msg);
+                }
+            }
         };
     }
 }
 
 impl RoomManagerActor {
+    #[allow(clippy::too_many_arguments)]
     fn join_room(
         &mut self,
         room_name: String,
-        password: String,
+        password: Option<String>,
         password_is_hash: bool,
         user_id: String,
         recipient: Recipient<ClientResponseMessage>,
+        ip: Option<IpAddr>,
+        custom_scale: Option<Scale>,
+        public: bool,
         msg: RoomMessage,
         ctx: &mut Context<Self>,
     ) {
         if self.room_name_validator.is_match(&room_name) {
             if !self.rooms.contains_key(&room_name) {
-                self.create_room(room_name.clone(), password, password_is_hash, ctx);
+                if !self.can_create_room(ip) {
+                    self.notify_user(
+                        &user_id,
+                        &recipient,
+                        ClientResponseMessage::ServerAtCapacity,
+                    );
+                    return;
+                }
+                let max_vote_value = self.config.max_vote_value;
+                let checked = custom_scale
+                    .as_ref()
+                    .map(|scale| RoomActor::check_custom_scale(scale, max_vote_value));
+                if let Some(Err(reason)) = checked {
+                    let msg = ClientResponseMessage::InvalidScale { room_name, reason };
+                    self.notify_user(&user_id, &recipient, msg);
+                    return;
+                }
+                let room = NewRoom {
+                    password,
+                    password_is_hash,
+                    custom_scale,
+                    public,
+                };
+                self.create_room(room_name.clone(), room, ip, ctx);
             }
-            self.do_join_room(room_name, user_id, recipient, msg);
+            self.do_join_room(room_name, user_id, msg);
         } else {
             self.notify_user(&user_id, &recipient, ClientResponseMessage::InvalidRoomName);
         }
     }
 
+    /// Moves a room to a new name, as long as the name is valid and free.
+    fn rename_room(&mut self, room_name: String, user_id: String, new_name: String) {
+        let reply = if !self.room_name_validator.is_match(&new_name) {
+            Some(ClientResponseMessage::InvalidRoomName)
+        } else if self.rooms.contains_key(&new_name) {
+            Some(ClientResponseMessage::RoomNameTaken {
+                room_name: new_name.clone(),
+            })
+        } else {
+            None
+        };
+        if let Some(reply) = reply {
+            if let Some(client) = self.clients.get(&user_id) {
+                if let Err(err) = client.responder.do_send(reply) {
+                    warn!(error = %err, "Unable to notify user.");
+                }
+            }
+            return;
+        }
+
+        let room = match self.rooms.remove(&room_name) {
+            None => return,
+            Some(room) => room,
+        };
+        room.do_send(RoomMessage::SetName {
+            new_name: new_name.clone(),
+        });
+        self.rooms.insert(new_name.clone(), room);
+        for rooms in self.user_room_map.values_mut() {
+            if rooms.remove(&room_name) {
+                rooms.insert(new_name.clone());
+            }
+        }
+        if self.public_rooms.remove(&room_name) {
+            self.public_rooms.insert(new_name.clone());
+        }
+        if let Some(ip) = self.room_creators.remove(&room_name) {
+            self.room_creators.insert(new_name, ip);
+        }
+    }
+
     fn create_room(
         &mut self,
         room_name: String,
-        password: String,
-        password_is_hash: bool,
+        room: NewRoom,
+        ip: Option<IpAddr>,
         ctx: &mut Context<Self>,
     ) {
-        let room_manager = ctx.address().recipient();
-        let room_actor =
-            RoomActor::new(room_name.clone(), password, password_is_hash, room_manager).start();
+        if let Some(ip) = ip {
+            *self.rooms_per_ip.entry(ip).or_insert(0) += 1;
+            self.room_creators.insert(room_name.clone(), ip);
+        }
+        if room.public {
+            self.public_rooms.insert(room_name.clone());
+        }
+
+        let room_actor = match self.idle_rooms.pop() {
+            None => self.start_room(room_name.clone(), room, ctx),
+            Some(room_actor) => {
+                room_actor.do_send(Recycle {
+                    room_name: room_name.clone(),
+                    password: room.password,
+                    password_is_hash: room.password_is_hash,
+                    custom_scale: room.custom_scale,
+                });
+                room_actor
+            }
+        };
         self.rooms.insert(room_name, room_actor);
     }
 
-    fn do_join_room(
-        &mut self,
+    fn start_room(
+        &self,
         room_name: String,
+        room: NewRoom,
+        ctx: &mut Context<Self>,
+    ) -> Addr<RoomActor> {
+        let room_manager = ctx.address().recipient();
+        let config = self.config.clone();
+        RoomActor::new(
+            room_name,
+            room.password,
+            room.password_is_hash,
+            room.custom_scale,
+            room_manager,
+            config,
+        )
+        .start()
+    }
+
+    fn can_create_room(&self, ip: Option<IpAddr>) -> bool {
+        let limit = self.config.max_rooms_per_ip;
+        match ip.and_then(|ip| self.rooms_per_ip.get(&ip)) {
+            None => true,
+            Some(rooms) => limit == 0 || *rooms < limit,
+        }
+    }
+
+    fn do_join_room(&mut self, room_name: String, user_id: String, msg: RoomMessage) {
+        // a user already in the room is told so by the room, and keeps their place
+        let room = self.rooms.get(&room_name).unwrap();
+        room.do_send(msg);
+        self.user_room_map
+            .entry(user_id)
+            .or_default()
+            .insert(room_name);
+    }
+
+    fn is_in_room(&self, user_id: &str, room_name: &str) -> bool {
+        self.user_room_map
+            .get(user_id)
+            .is_some_and(|rooms| rooms.contains(room_name))
+    }
+
+    /// Forgets that the user is in the room. A dropped client is forgotten once it is in no
+    /// room, as no one can take its place anymore.
+    fn remove_from_room(&mut self, user_id: &str, room_name: &str) {
+        let in_no_room = match self.user_room_map.get_mut(user_id) {
+            None => return,
+            Some(rooms) => {
+                rooms.remove(room_name);
+                rooms.is_empty()
+            }
+        };
+        if !in_no_room {
+            return;
+        }
+        self.user_room_map.remove(user_id);
+        let dropped = self
+            .clients
+            .get(user_id)
+            .is_some_and(|client| !client.recipient.connected());
+        if dropped {
+            self.clients.remove(user_id);
+        }
+    }
+
+    /// Takes the user out of the room, confirming it with LeftRoom, or NotInRoom when the
+    /// user is not in that room.
+    fn leave_room(
+        &mut self,
         user_id: String,
-        recipient: Recipient<ClientResponseMessage>,
-        msg: RoomMessage,
+        room_name: String,
+        recipient: Option<Recipient<ClientResponseMessage>>,
     ) {
-        match self.user_room_map.get(&user_id) {
-            None => {
-                let room = self.rooms.get(&room_name).unwrap();
-                room.do_send(msg);
-                self.user_room_map.insert(user_id, room_name);
-            }
-            Some(_) => {
-                println!("RoomManager: User trying to join a second room.");
-                self.notify_user(
-                    &user_id,
-                    &recipient,
-                    ClientResponseMessage::CannotJoinMultipleRooms,
+        let reply = match self.user_room_map.get(&user_id) {
+            Some(rooms) if rooms.contains(&room_name) => {
+                self.remove_from_room(&user_id, &room_name);
+                match self.rooms.get(&room_name) {
+                    None => {
+                        debug!(
+                            user_id = %redact::id(&user_id),
+                            room_name = %redact::id(&room_name),
+                            "User tried to exit a room which does not exist."
+                        );
+                        ClientResponseMessage::NotInRoom { room_name }
+                    }
+                    Some(room) => {
+                        room.do_send(RoomMessage::LeaveRoom {
+                            user_id,
+                            room_name: room_name.clone(),
+                            recipient: None,
+                        });
+                        ClientResponseMessage::LeftRoom { room_name }
+                    }
+                }
+            }
+            _ => {
+                debug!(
+                    user_id = %redact::id(&user_id),
+                    room_name = %redact::id(&room_name),
+                    "User tried to exit a room which they is not into."
                 );
+                ClientResponseMessage::NotInRoom { room_name }
+            }
+        };
+
+        if let Some(recipient) = recipient {
+            if let Err(err) = recipient.do_send(reply) {
+                warn!(error = %err, "Unable to notify user.");
             }
         }
     }
 
-    fn leave_room(&mut self, user_id: String, room_name: String) {
-        match self.user_room_map.get_mut(&user_id) {
-            None => println!(
-                "RoomManager: {} tried to exit {} which they is not into.",
-                &user_id, &room_name
-            ),
-            Some(_) => {
-                let _ = self.user_room_map.remove(&user_id);
+    fn user_left(&mut self, user_id: String) {
+        self.clients.remove(&user_id);
+        let room_names = self.user_room_map.get(&user_id).cloned();
+        match room_names {
+            None => {
+                debug!(
+                    user_id = %redact::id(&user_id),
+                    "User left, but no record of his rooms exists."
+                )
+            }
+            Some(room_names) => {
+                for room_name in room_names {
+                    self.leave_room(user_id.clone(), room_name, None);
+                }
             }
+        }
+    }
+
+    /// Gives a new connection the id of a dropped one, in every room it was in. Only the
+    /// given room replays what was missed, the others are resumed one by one afterwards. The
+    /// new connection keeps its own id when the token does not match, when the dropped one is
+    /// still connected or when the new one already joined a room.
+    fn reclaim(
+        &mut self,
+        room_name: String,
+        user_id: String,
+        resume_token: String,
+        new_user_id: String,
+        since_seq: u64,
+    ) {
+        let reclaimable = match self.clients.get(&user_id) {
+            None => false,
+            Some(client) => {
+                client.resume_token == resume_token
+                    && !client.recipient.connected()
+                    && self.is_in_room(&user_id, &room_name)
+                    && !self.user_room_map.contains_key(&new_user_id)
+            }
+        };
+        let new_client = match self.clients.remove(&new_user_id) {
+            None => return,
+            Some(new_client) => new_client,
         };
+        if !reclaimable {
+            debug!(
+                user_id = %redact::id(&new_user_id),
+                taken_id = %redact::id(&user_id),
+                room_name = %redact::id(&room_name),
+                "User could not take back an id."
+            );
+            let msg = ClientResponseMessage::ResumeFailed { room_name, user_id };
+            if let Err(err) = new_client.responder.do_send(msg) {
+                warn!(error = %err, "Unable to notify user.");
+            }
+            self.clients.insert(new_user_id, new_client);
+            return;
+        }
 
-        match self.rooms.get(&room_name) {
-            None => println!(
-                "RoomManager: {} tried to exit {} which does not exist",
-                &user_id, &room_name
-            ),
-            Some(room) => room.do_send(RoomMessage::LeaveRoom { user_id, room_name }),
+        let room_names = self
+            .user_room_map
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default();
+        for other_room in room_names
+            .iter()
+            .filter(|other_room| **other_room != room_name)
+        {
+            let msg = RoomMessage::Reconnected {
+                user_id: user_id.clone(),
+                since_seq: None,
+                recipient: new_client.responder.clone(),
+            };
+            self.notify_room(other_room, msg);
         }
+        let msg = RoomMessage::Reconnected {
+            user_id: user_id.clone(),
+            since_seq: Some(since_seq),
+            recipient: new_client.responder.clone(),
+        };
+        self.clients.insert(user_id, new_client);
+        self.notify_room(&room_name, msg);
     }
 
-    fn user_left(&mut self, user_id: String) {
-        let room_name = self.user_room_map.remove(&user_id);
-        match room_name {
-            None => println!("RoomManager: User left, but no record of his rooms exists."),
-            Some(room) => self.leave_room(user_id.clone(), room),
+    /// Lets the rooms keep the user for the grace period, so they may resume on a new
+    /// connection. They leave right away when there is no grace period or no room.
+    fn user_dropped(&mut self, user_id: String) {
+        let room_names = self.user_room_map.get(&user_id).cloned();
+        match room_names {
+            Some(room_names) if self.config.disconnect_grace > Duration::from_secs(0) => {
+                for room_name in room_names {
+                    let user_id = user_id.clone();
+                    self.notify_room(&room_name, RoomMessage::UserDropped { user_id });
+                }
+            }
+            _ => self.user_left(user_id),
         }
     }
 
     fn user_updated(&mut self, user: UserData) {
-        let room_name = self.user_room_map.get(&user.user_id);
-        match room_name {
-            None => println!(
-                "RoomManager: User tried to update his info, but no record of his rooms exists."
-            ),
-            Some(room) => self.notify_room(room, RoomMessage::UserUpdated { user }),
+        let room_names = match self.user_room_map.get(&user.user_id) {
+            None => {
+                debug!(
+                    user_id = %redact::id(&user.user_id),
+                    "User tried to update his info, but no record of his rooms exists."
+                );
+                return;
+            }
+            Some(room_names) => room_names.clone(),
+        };
+        for room_name in room_names {
+            let user = user.clone();
+            self.notify_room(&room_name, RoomMessage::UserUpdated { user });
+        }
+    }
+
+    fn list_scales(&self, recipient: Recipient<ClientResponseMessage>) {
+        let scales = RoomActor::scale_catalog();
+        if let Err(err) = recipient.do_send(ClientResponseMessage::ScaleCatalog { scales }) {
+            warn!(error = %err, "Unable to send the scale catalog.");
+        }
+    }
+
+    /// Asks every public room for its summary, and sends the list once they all answered.
+    /// Rooms that do not answer are left out.
+    fn list_rooms(&self, recipient: Recipient<ClientResponseMessage>) {
+        let summaries: Vec<_> = self
+            .public_rooms
+            .iter()
+            .filter_map(|room_name| self.rooms.get(room_name))
+            .map(|room| room.send(GetRoomSummary))
+            .collect();
+        actix_rt::spawn(async move {
+            let mut rooms: Vec<RoomSummary> = futures::future::join_all(summaries)
+                .await
+                .into_iter()
+                .filter_map(|summary| summary.ok().and_then(|summary| summary.ok()))
+                .collect();
+            rooms.sort_by(|a, b| a.name.cmp(&b.name));
+            if let Err(err) = recipient.do_send(ClientResponseMessage::RoomList { rooms }) {
+                warn!(error = %err, "Unable to send the room list.");
+            }
+        });
+    }
+
+    /// Forwards a message from a user to a room, as long as the user is in that room.
+    fn forward_from_member(&mut self, user_id: &str, room_name: String, msg: RoomMessage) {
+        if self.is_in_room(user_id, &room_name) {
+            self.forward(room_name, msg);
+            return;
+        }
+        debug!(
+            user_id = %redact::id(user_id),
+            "User tried to send a message to a room they are not in."
+        );
+        if let Some(client) = self.clients.get(user_id) {
+            let msg = ClientResponseMessage::NotInRoom { room_name };
+            if let Err(err) = client.responder.do_send(msg) {
+                warn!(error = %err, "Unable to notify user.");
+            }
         }
     }
 
     fn forward(&mut self, room_name: String, msg: RoomMessage) {
         match self.rooms.get(&room_name) {
-            None => println!("RoomManager: User tried to send a message to an unknown room."),
+            None => {
+                debug!(
+                    room_name = %redact::id(&room_name),
+                    "User tried to send a message to an unknown room."
+                )
+            }
             Some(room) => room.do_send(msg),
         }
     }
 
+    fn ping_all(
+        &mut self,
+        secret: String,
+        admin: Recipient<ClientResponseMessage>,
+        ctx: &mut Context<Self>,
+    ) {
+        if !self.is_admin(&secret, &admin) {
+            return;
+        }
+        if self.ping_check.is_some() {
+            let msg = "A ping check is already running.".to_string();
+            let _ = admin.do_send(ClientResponseMessage::Error { msg });
+            return;
+        }
+
+        // a connection that is gone and in no room can never be taken back
+        let user_room_map = &self.user_room_map;
+        self.clients.retain(|user_id, client| {
+            client.recipient.connected() || user_room_map.contains_key(user_id)
+        });
+        let mut pinged = HashSet::new();
+        for (user_id, client) in self.clients.iter() {
+            if client.recipient.do_send(ClientMessage::PingCheck).is_ok() {
+                pinged.insert(user_id.clone());
+            }
+        }
+        self.ping_check = Some(PingCheck {
+            admin,
+            pinged,
+            responsive: HashSet::new(),
+        });
+        ctx.run_later(PING_CHECK_TIMEOUT, |act, _ctx| act.ping_check_done());
+    }
+
+    /// Checks the admin secret, warning the user if it does not match.
+    fn is_admin(&self, secret: &str, recipient: &Recipient<ClientResponseMessage>) -> bool {
+        if self.config.admin_secret.as_deref() == Some(secret) {
+            return true;
+        }
+        let _ = recipient.do_send(ClientResponseMessage::NotAdmin);
+        false
+    }
+
+    fn ping_report(&mut self, user_id: String) {
+        if let Some(check) = self.ping_check.as_mut() {
+            if check.pinged.contains(&user_id) {
+                check.responsive.insert(user_id);
+            }
+        }
+    }
+
+    fn ping_check_done(&mut self) {
+        if let Some(check) = self.ping_check.take() {
+            let responsive = check.responsive.len();
+            let msg = ClientResponseMessage::PingResults {
+                responsive,
+                unresponsive: check.pinged.len() - responsive,
+            };
+            if let Err(err) = check.admin.do_send(msg) {
+                warn!(error = %err, "Unable to send ping results.");
+            }
+        }
+    }
+
+    /// Forgets a room that became empty or idle. With a pool, its actor is kept idle for reuse,
+    /// unless the pool is full.
     fn room_closing(&mut self, room_name: String) {
-        self.rooms.remove(&room_name);
+        self.public_rooms.remove(&room_name);
+        // a room closed while idle still has members
+        for rooms in self.user_room_map.values_mut() {
+            rooms.remove(&room_name);
+        }
+        self.user_room_map.retain(|_, rooms| !rooms.is_empty());
+        if let Some(room_actor) = self.rooms.remove(&room_name) {
+            if self.idle_rooms.len() < self.config.room_pool_size {
+                self.idle_rooms.push(room_actor);
+            } else if self.config.room_pool_size > 0 {
+                room_actor.do_send(Retire);
+            }
+        }
+
+        if let Some(ip) = self.room_creators.remove(&room_name) {
+            if let Some(rooms) = self.rooms_per_ip.get_mut(&ip) {
+                *rooms -= 1;
+                if *rooms == 0 {
+                    self.rooms_per_ip.remove(&ip);
+                }
+            }
+        }
     }
 
     fn notify_room(&self, room_name: &String, msg: RoomMessage) {
         let room = self.rooms.get(room_name);
         match room {
-            None => println!("RoomManager: Unable to find room to send message to"),
+            None => warn!("Unable to find room to send message to"),
             Some(room) => room.do_send(msg),
         }
     }
@@ -201,8 +1013,594 @@ impl RoomManagerActor {
         msg: ClientResponseMessage,
     ) {
         if let Err(err) = recipient.do_send(msg) {
-            println!("RoomManager: Unable to reach ClientActor.\nError: {}", err);
+            warn!(user_id = %redact::id(user_id), error = %err, "Unable to reach ClientActor.");
             self.user_left(user_id.to_owned());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::test_util::{self, hang_up, take, Inbox, Log, PingChecks, REDACTION};
+    use actix_web_actors::ws;
+    use futures::SinkExt;
+
+    const SETTLE: Duration = Duration::from_millis(20);
+
+    fn manager() -> Addr<RoomManagerActor> {
+        let mut config = test_util::config();
+        config.disconnect_grace = Duration::from_secs(30);
+        RoomManagerActor::new(config).start()
+    }
+
+    /// Registers a connection with the manager, as a ClientActor does when it starts.
+    async fn connect(manager: &Addr<RoomManagerActor>, user_id: &str) -> Addr<Inbox> {
+        let inbox = Inbox::default().start();
+        let msg = RoomMessage::ClientConnected {
+            user_id: user_id.to_string(),
+            resume_token: format!("{}-token", user_id),
+            recipient: inbox.clone().recipient(),
+            responder: inbox.clone().recipient(),
+        };
+        manager.send(msg).await.unwrap();
+        inbox
+    }
+
+    async fn join(manager: &Addr<RoomManagerActor>, user_id: &str, inbox: &Addr<Inbox>) {
+        let msg = RoomMessage::JoinRoom {
+            room_name: test_util::ROOM.to_string(),
+            password: None,
+            password_is_hash: false,
+            user: test_util::user(user_id),
+            recipient: inbox.clone().recipient(),
+            ip: None,
+            custom_scale: None,
+            public: false,
+            spectator: false,
+        };
+        manager.send(msg).await.unwrap();
+    }
+
+    fn reclaim(user_id: &str, resume_token: &str, new_user_id: &str) -> RoomMessage {
+        RoomMessage::Reclaim {
+            room_name: test_util::ROOM.to_string(),
+            user_id: user_id.to_string(),
+            resume_token: resume_token.to_string(),
+            new_user_id: new_user_id.to_string(),
+            since_seq: 0,
+        }
+    }
+
+    /// Alice and Bob in a room, with Alice's connection dropped.
+    async fn room_with_dropped_user(manager: &Addr<RoomManagerActor>) -> Addr<Inbox> {
+        let alice = connect(manager, "alice").await;
+        join(manager, "alice", &alice).await;
+        let bob = connect(manager, "bob").await;
+        join(manager, "bob", &bob).await;
+        actix_rt::time::delay_for(SETTLE).await;
+
+        hang_up(&alice).await;
+        let msg = RoomMessage::UserDropped {
+            user_id: "alice".to_string(),
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        bob
+    }
+
+    #[actix_rt::test]
+    async fn new_connection_resumes_a_dropped_user() {
+        let manager = manager();
+        let bob = room_with_dropped_user(&manager).await;
+        take(&bob).await;
+
+        let carol = connect(&manager, "carol").await;
+        manager
+            .send(reclaim("alice", "alice-token", "carol"))
+            .await
+            .unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+
+        let resumed = take(&carol).await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::Resumed { ref user, .. } if user.user_id == "alice"
+            )
+        });
+        assert!(resumed);
+        let reconnected = take(&bob).await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::UserReconnected { ref user_id, .. } if user_id == "alice"
+            )
+        });
+        assert!(reconnected);
+    }
+
+    #[actix_rt::test]
+    async fn wrong_token_keeps_the_new_id() {
+        let manager = manager();
+        room_with_dropped_user(&manager).await;
+
+        let carol = connect(&manager, "carol").await;
+        manager
+            .send(reclaim("alice", "not-the-token", "carol"))
+            .await
+            .unwrap();
+
+        let received = take(&carol).await;
+        assert!(received.iter().any(|msg| matches!(
+            msg,
+            ClientResponseMessage::ResumeFailed { user_id, .. } if user_id == "alice"
+        )));
+        assert!(!received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::Resumed { .. })));
+    }
+
+    fn vote(user_id: &str, size: &str) -> RoomMessage {
+        RoomMessage::Vote {
+            room_name: test_util::ROOM.to_string(),
+            user_id: user_id.to_string(),
+            size: size.to_string(),
+            confidence: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn unreachable_member_is_removed_and_the_round_completes() {
+        let manager = manager();
+        let mut inboxes = Vec::new();
+        for user_id in ["alice", "bob", "carol"].iter() {
+            let inbox = connect(&manager, user_id).await;
+            join(&manager, user_id, &inbox).await;
+            inboxes.push(inbox);
+        }
+        actix_rt::time::delay_for(SETTLE).await;
+        manager.send(vote("alice", "3")).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        take(&inboxes[0]).await;
+
+        // carol is gone without the manager being told, so only a failed send finds out
+        hang_up(&inboxes[2]).await;
+        manager.send(vote("bob", "5")).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+
+        let received = take(&inboxes[0]).await;
+        let left = received.iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::UserLeft { user_id, .. } if user_id == "carol"
+            )
+        });
+        assert!(left);
+        let revealed = received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::VoteResults { .. }));
+        assert!(revealed);
+    }
+
+    #[actix_rt::test]
+    async fn ping_check_tallies_responsive_and_unresponsive_clients() {
+        let mut config = test_util::config();
+        config.admin_secret = Some("secret".to_string());
+        let manager = RoomManagerActor::new(config).start();
+        let alice = connect(&manager, "alice").await;
+        let bob = connect(&manager, "bob").await;
+        let admin = Inbox::default().start();
+
+        let msg = RoomMessage::PingAll {
+            secret: "secret".to_string(),
+            recipient: admin.clone().recipient(),
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        assert_eq!(alice.send(PingChecks).await.unwrap(), 1);
+        assert_eq!(bob.send(PingChecks).await.unwrap(), 1);
+
+        // only alice answers the ping
+        let msg = RoomMessage::PingReport {
+            user_id: "alice".to_string(),
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(PING_CHECK_TIMEOUT + SETTLE).await;
+
+        let results = take(&admin).await.into_iter().find_map(|msg| match msg {
+            ClientResponseMessage::PingResults {
+                responsive,
+                unresponsive,
+            } => Some((responsive, unresponsive)),
+            _ => None,
+        });
+        assert_eq!(results, Some((1, 1)));
+    }
+
+    #[actix_rt::test]
+    async fn ping_check_needs_the_admin_secret() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        let admin = Inbox::default().start();
+
+        let msg = RoomMessage::PingAll {
+            secret: "guess".to_string(),
+            recipient: admin.clone().recipient(),
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+
+        assert_eq!(alice.send(PingChecks).await.unwrap(), 0);
+        let refused = take(&admin)
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::NotAdmin));
+        assert!(refused);
+    }
+
+    async fn join_from(
+        manager: &Addr<RoomManagerActor>,
+        user_id: &str,
+        room_name: &str,
+        ip: &str,
+        inbox: &Addr<Inbox>,
+    ) {
+        let msg = RoomMessage::JoinRoom {
+            room_name: room_name.to_string(),
+            password: None,
+            password_is_hash: false,
+            user: test_util::user(user_id),
+            recipient: inbox.clone().recipient(),
+            ip: Some(ip.parse().unwrap()),
+            custom_scale: None,
+            public: false,
+            spectator: false,
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+    }
+
+    fn at_capacity(received: &[ClientResponseMessage]) -> bool {
+        received
+            .iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::ServerAtCapacity))
+    }
+
+    fn joined(received: &[ClientResponseMessage], room: &str) -> bool {
+        received.iter().any(|msg| {
+            matches!(msg, ClientResponseMessage::RoomJoined { room_name, .. } if room_name == room)
+        })
+    }
+
+    fn limited_manager() -> Addr<RoomManagerActor> {
+        let mut config = test_util::config();
+        config.max_rooms_per_ip = 1;
+        RoomManagerActor::new(config).start()
+    }
+
+    #[actix_rt::test]
+    async fn ip_past_its_room_limit_is_blocked() {
+        let manager = limited_manager();
+        let alice = connect(&manager, "alice").await;
+        let bob = connect(&manager, "bob").await;
+        join_from(&manager, "alice", "one", "10.0.0.1", &alice).await;
+        assert!(joined(&take(&alice).await, "one"));
+
+        join_from(&manager, "alice", "two", "10.0.0.1", &alice).await;
+        assert!(at_capacity(&take(&alice).await));
+
+        join_from(&manager, "bob", "two", "10.0.0.2", &bob).await;
+        let received = take(&bob).await;
+        assert!(!at_capacity(&received));
+        assert!(joined(&received, "two"));
+    }
+
+    #[actix_rt::test]
+    async fn zero_rooms_per_ip_is_unlimited() {
+        let mut config = test_util::config();
+        config.max_rooms_per_ip = 0;
+        let manager = RoomManagerActor::new(config).start();
+        let alice = connect(&manager, "alice").await;
+        for room in &["one", "two", "three"] {
+            join_from(&manager, "alice", room, "10.0.0.1", &alice).await;
+            assert!(joined(&take(&alice).await, room));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn closed_rooms_free_the_limit_of_their_ip() {
+        let manager = limited_manager();
+        let alice = connect(&manager, "alice").await;
+        join_from(&manager, "alice", "one", "10.0.0.1", &alice).await;
+        let msg = RoomMessage::LeaveRoom {
+            room_name: "one".to_string(),
+            user_id: "alice".to_string(),
+            recipient: None,
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        take(&alice).await;
+
+        join_from(&manager, "alice", "two", "10.0.0.1", &alice).await;
+        assert!(joined(&take(&alice).await, "two"));
+    }
+
+    #[actix_rt::test]
+    async fn shutdown_exports_the_results_of_active_rooms() {
+        let _redaction = REDACTION.lock().await;
+        let (log, _guard) = Log::capture();
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        join(&manager, "alice", &alice).await;
+        let bob = connect(&manager, "bob").await;
+        join(&manager, "bob", &bob).await;
+        manager.send(vote("alice", "3")).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        take(&bob).await;
+
+        manager.send(Shutdown).await.unwrap().unwrap();
+        let text = log.text();
+        let export = text
+            .lines()
+            .find_map(|line| line.split_once("Export: "))
+            .map(|(_, export)| serde_json::from_str::<serde_json::Value>(export).unwrap())
+            .unwrap();
+        assert_eq!(export["room_name"], test_util::ROOM);
+        assert_eq!(export["votes"]["alice"], "3");
+        assert!(export["votes"]["bob"].is_null());
+
+        actix_rt::time::delay_for(SETTLE).await;
+        let told = take(&bob)
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::ServerShuttingDown));
+        assert!(told);
+    }
+
+    fn not_in_room(received: &[ClientResponseMessage], room: &str) -> bool {
+        received.iter().any(|msg| {
+            matches!(msg, ClientResponseMessage::NotInRoom { room_name } if room_name == room)
+        })
+    }
+
+    #[actix_rt::test]
+    async fn messages_for_a_room_that_does_not_exist_are_rejected() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        let room = || "nowhere".to_string();
+        let user = || "alice".to_string();
+        let msgs = vec![
+            RoomMessage::Vote {
+                room_name: room(),
+                user_id: user(),
+                size: "3".to_string(),
+                confidence: None,
+            },
+            RoomMessage::NewVote {
+                room_name: room(),
+                user_id: user(),
+                topic: None,
+            },
+            RoomMessage::Randomize {
+                room_name: room(),
+                user_id: user(),
+            },
+        ];
+
+        for msg in msgs {
+            manager.send(msg).await.unwrap();
+            actix_rt::time::delay_for(SETTLE).await;
+            assert!(not_in_room(&take(&alice).await, "nowhere"));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn votes_for_a_room_the_user_is_not_in_are_rejected() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        join(&manager, "alice", &alice).await;
+        let mallory = connect(&manager, "mallory").await;
+        actix_rt::time::delay_for(SETTLE).await;
+        take(&alice).await;
+
+        manager.send(vote("mallory", "3")).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+
+        assert!(not_in_room(&take(&mallory).await, test_util::ROOM));
+        let status = take(&alice)
+            .await
+            .into_iter()
+            .any(|msg| matches!(msg, ClientResponseMessage::VoteStatus { .. }));
+        assert!(!status);
+    }
+
+    fn leave(room_name: &str, inbox: &Addr<Inbox>) -> RoomMessage {
+        RoomMessage::LeaveRoom {
+            room_name: room_name.to_string(),
+            user_id: "alice".to_string(),
+            recipient: Some(inbox.clone().recipient()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn leaving_a_room_is_acknowledged() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        join(&manager, "alice", &alice).await;
+        actix_rt::time::delay_for(SETTLE).await;
+
+        manager.send(leave(test_util::ROOM, &alice)).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        let left = take(&alice).await.into_iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::LeftRoom { room_name } if room_name == test_util::ROOM
+            )
+        });
+        assert!(left);
+    }
+
+    #[actix_rt::test]
+    async fn leaving_a_bogus_room_is_an_error() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        join(&manager, "alice", &alice).await;
+        actix_rt::time::delay_for(SETTLE).await;
+
+        manager.send(leave("bogus", &alice)).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        assert!(not_in_room(&take(&alice).await, "bogus"));
+    }
+
+    #[actix_rt::test]
+    async fn pooled_room_is_handed_out_empty() {
+        let mut config = test_util::config();
+        config.room_pool_size = 1;
+        let manager = RoomManagerActor::new(config).start();
+        let alice = connect(&manager, "alice").await;
+        let bob = connect(&manager, "bob").await;
+        join_from(&manager, "alice", "one", "10.0.0.1", &alice).await;
+        manager
+            .send(RoomMessage::Vote {
+                room_name: "one".to_string(),
+                user_id: "alice".to_string(),
+                size: "3".to_string(),
+                confidence: None,
+            })
+            .await
+            .unwrap();
+        manager.send(leave("one", &alice)).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+        take(&alice).await;
+
+        join_from(&manager, "bob", "two", "10.0.0.2", &bob).await;
+        actix_rt::time::delay_for(SETTLE).await;
+        let room_joined = take(&bob).await.into_iter().find_map(|msg| match msg {
+            ClientResponseMessage::RoomJoined {
+                users, votes_cast, ..
+            } => Some((users, votes_cast)),
+            _ => None,
+        });
+        let (users, votes_cast) = room_joined.expect("bob should have joined");
+        let ids: Vec<_> = users.iter().map(|user| user.user_id.as_str()).collect();
+        assert_eq!(ids, ["bob"]);
+        assert_eq!(votes_cast, 0);
+        assert!(take(&alice).await.is_empty());
+    }
+
+    async fn join_with_scale(
+        manager: &Addr<RoomManagerActor>,
+        user_id: &str,
+        inbox: &Addr<Inbox>,
+        custom_scale: Scale,
+    ) {
+        let msg = RoomMessage::JoinRoom {
+            room_name: test_util::ROOM.to_string(),
+            password: None,
+            password_is_hash: false,
+            user: test_util::user(user_id),
+            recipient: inbox.clone().recipient(),
+            ip: None,
+            custom_scale: Some(custom_scale),
+            public: false,
+            spectator: false,
+        };
+        manager.send(msg).await.unwrap();
+        actix_rt::time::delay_for(SETTLE).await;
+    }
+
+    /// The scales of the room and the selected one, as the member was told when joining.
+    fn joined_scales(received: &[ClientResponseMessage]) -> Option<(Vec<String>, String)> {
+        received.iter().find_map(|msg| match msg {
+            ClientResponseMessage::RoomJoined {
+                scale_values,
+                selected_scale_name,
+                ..
+            } => {
+                let mut names: Vec<String> = scale_values.keys().cloned().collect();
+                names.sort();
+                Some((names, selected_scale_name.clone()))
+            }
+            _ => None,
+        })
+    }
+
+    #[actix_rt::test]
+    async fn creator_seeds_the_room_with_a_custom_scale() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        let hours = Scale::new("hours", "Hours", &["1", "2", "4", "8"]);
+        join_with_scale(&manager, "alice", &alice, hours).await;
+
+        let (names, selected) = joined_scales(&take(&alice).await).unwrap();
+        assert!(names.contains(&"hours".to_string()));
+        assert_eq!(selected, "hours");
+    }
+
+    #[actix_rt::test]
+    async fn custom_scale_of_a_later_joiner_is_ignored() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        let bob = connect(&manager, "bob").await;
+        let hours = Scale::new("hours", "Hours", &["1", "2", "4", "8"]);
+        join_with_scale(&manager, "alice", &alice, hours).await;
+        let days = Scale::new("days", "Days", &["1", "2", "3"]);
+        join_with_scale(&manager, "bob", &bob, days).await;
+
+        let (names, selected) = joined_scales(&take(&bob).await).unwrap();
+        assert!(!names.contains(&"days".to_string()));
+        assert_eq!(selected, "hours");
+    }
+
+    #[actix_rt::test]
+    async fn invalid_custom_scale_does_not_create_the_room() {
+        let manager = manager();
+        let alice = connect(&manager, "alice").await;
+        let single = Scale::new("single", "Single", &["1"]);
+        join_with_scale(&manager, "alice", &alice, single).await;
+
+        let received = take(&alice).await;
+        let refused = received.iter().any(|msg| {
+            matches!(
+                msg,
+                ClientResponseMessage::InvalidScale { reason, .. }
+                    if reason == "a scale needs at least two values"
+            )
+        });
+        assert!(refused);
+        assert!(joined_scales(&received).is_none());
+    }
+
+    async fn client_count(manager: &Addr<RoomManagerActor>) -> usize {
+        test_util::run_in_manager(manager, |act, _| act.clients.len()).await
+    }
+
+    #[actix_rt::test]
+    async fn connection_gone_without_a_close_frame_is_forgotten() {
+        let config = test_util::config();
+        let manager = RoomManagerActor::new(config.clone()).start();
+        let mut srv = test_util::server_with(manager.clone(), config);
+        let mut client = srv.ws().await.unwrap();
+        test_util::register(&mut client).await;
+        assert_eq!(client_count(&manager).await, 1);
+
+        drop(client);
+        actix_rt::time::delay_for(SETTLE * 5).await;
+        assert_eq!(client_count(&manager).await, 0);
+    }
+
+    #[actix_rt::test]
+    async fn connection_closed_by_the_server_is_forgotten() {
+        let config = test_util::config();
+        let manager = RoomManagerActor::new(config.clone()).start();
+        let mut srv = test_util::server_with(manager.clone(), config);
+        let mut client = srv.ws().await.unwrap();
+        test_util::register(&mut client).await;
+
+        // larger than a frame may be, so the server closes the connection
+        let frame = ws::Message::Text("x".repeat(128 * 1024));
+        client.send(frame).await.unwrap();
+        assert!(test_util::close_reason(&mut client).await.is_some());
+        actix_rt::time::delay_for(SETTLE).await;
+        assert_eq!(client_count(&manager).await, 0);
+    }
+}
@@ -0,0 +1,527 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Stand-ins for the actors around a room, so tests can see what a room sends, a server to
+//! connect websocket clients to, and a log tests can read back.
+
+use crate::actors::messages::{ClientMessage, ClientResponseMessage, RoomMessage};
+use crate::actors::room::{RoomActor, RoomManagerActor};
+use crate::config::Config;
+use crate::data::UserData;
+use actix::prelude::*;
+use actix_web::{test, web, App};
+use actix_web_actors::ws;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::subscriber::DefaultGuard;
+
+/// Name of the room tests run in.
+pub const ROOM: &str = "room";
+
+/// The configuration tests start from: no debounce, no pool and no idle timeout, so rooms
+/// act right away and only stop when tests want them to.
+pub fn config() -> Config {
+    let mut config = Config::from_env();
+    config.scale_debounce = Duration::from_millis(0);
+    config.room_pool_size = 0;
+    config.room_idle_timeout = Duration::from_secs(0);
+    config.max_room_size = 0;
+    config.randomize_cooldown = Duration::from_secs(0);
+    config.record_sessions = false;
+    config
+}
+
+pub fn user(user_id: &str) -> UserData {
+    UserData::new(user_id.to_string(), user_id.to_string(), "mp".to_string())
+}
+
+/// Stands in for a ClientActor, keeping what it is sent.
+#[derive(Default)]
+pub struct Inbox {
+    received: Vec<ClientResponseMessage>,
+    ping_checks: usize,
+}
+
+impl Actor for Inbox {
+    type Context = Context<Self>;
+}
+
+impl Handler<ClientResponseMessage> for Inbox {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientResponseMessage, _: &mut Context<Self>) {
+        self.received.push(msg);
+    }
+}
+
+impl Handler<ClientMessage> for Inbox {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
+        match msg {
+            ClientMessage::PingCheck => self.ping_checks += 1,
+        }
+    }
+}
+
+/// Takes what an inbox received so far, with broadcasts still wrapped in their sequence
+/// numbers.
+pub struct TakeSequenced;
+
+impl Message for TakeSequenced {
+    type Result = Vec<ClientResponseMessage>;
+}
+
+impl Handler<TakeSequenced> for Inbox {
+    type Result = MessageResult<TakeSequenced>;
+
+    fn handle(&mut self, _: TakeSequenced, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(std::mem::take(&mut self.received))
+    }
+}
+
+/// How many diagnostic pings an inbox got.
+pub struct PingChecks;
+
+impl Message for PingChecks {
+    type Result = usize;
+}
+
+impl Handler<PingChecks> for Inbox {
+    type Result = usize;
+
+    fn handle(&mut self, _: PingChecks, _: &mut Context<Self>) -> usize {
+        self.ping_checks
+    }
+}
+
+/// Stops an inbox, as a connection that went away.
+pub struct Hangup;
+
+impl Message for Hangup {
+    type Result = ();
+}
+
+impl Handler<Hangup> for Inbox {
+    type Result = ();
+
+    fn handle(&mut self, _: Hangup, ctx: &mut Context<Self>) {
+        ctx.stop();
+    }
+}
+
+/// Takes what an inbox received so far, without the sequence numbers.
+pub async fn take(inbox: &Addr<Inbox>) -> Vec<ClientResponseMessage> {
+    inbox
+        .send(TakeSequenced)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(unsequenced)
+        .collect()
+}
+
+pub fn unsequenced(msg: ClientResponseMessage) -> ClientResponseMessage {
+    match msg {
+        ClientResponseMessage::Sequenced { message, .. } => *message,
+        msg => msg,
+    }
+}
+
+/// Stops the inbox and waits until messages can no longer reach it.
+pub async fn hang_up(inbox: &Addr<Inbox>) {
+    inbox.do_send(Hangup);
+    while inbox.connected() {
+        actix_rt::time::delay_for(Duration::from_millis(1)).await;
+    }
+}
+
+/// Stands in for the RoomManagerActor, keeping what rooms tell it.
+#[derive(Default)]
+pub struct Manager {
+    received: Vec<RoomMessage>,
+}
+
+impl Actor for Manager {
+    type Context = Context<Self>;
+}
+
+impl Handler<RoomMessage> for Manager {
+    type Result = ();
+
+    fn handle(&mut self, msg: RoomMessage, _: &mut Context<Self>) {
+        self.received.push(msg);
+    }
+}
+
+pub struct TakeRoomMessages;
+
+impl Message for TakeRoomMessages {
+    type Result = Vec<RoomMessage>;
+}
+
+impl Handler<TakeRoomMessages> for Manager {
+    type Result = MessageResult<TakeRoomMessages>;
+
+    fn handle(&mut self, _: TakeRoomMessages, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(std::mem::take(&mut self.received))
+    }
+}
+
+type RunFn<A, R> = dyn FnOnce(&mut A, &mut Context<A>) -> R + Send;
+
+/// Runs a closure inside a room or the manager, with its context.
+pub struct Run<A: Actor<Context = Context<A>>, R>(Box<RunFn<A, R>>);
+
+impl<A: Actor<Context = Context<A>>, R: 'static> Message for Run<A, R> {
+    type Result = R;
+}
+
+impl<R: 'static> Handler<Run<RoomActor, R>> for RoomActor {
+    type Result = MessageResult<Run<RoomActor, R>>;
+
+    fn handle(&mut self, msg: Run<RoomActor, R>, ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult((msg.0)(self, ctx))
+    }
+}
+
+impl<R: 'static> Handler<Run<RoomManagerActor, R>> for RoomManagerActor {
+    type Result = MessageResult<Run<RoomManagerActor, R>>;
+
+    fn handle(&mut self, msg: Run<RoomManagerActor, R>, ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult((msg.0)(self, ctx))
+    }
+}
+
+/// Runs a closure inside the manager, with its context.
+pub async fn run_in_manager<R, F>(room_manager: &Addr<RoomManagerActor>, f: F) -> R
+where
+    R: Send + 'static,
+    F: FnOnce(&mut RoomManagerActor, &mut Context<RoomManagerActor>) -> R + Send + 'static,
+{
+    room_manager.send(Run(Box::new(f))).await.unwrap()
+}
+
+/// A room running on its own, with an inbox for each member and a stand-in manager.
+pub struct TestRoom {
+    pub addr: Addr<RoomActor>,
+    pub manager: Addr<Manager>,
+    inboxes: HashMap<String, Addr<Inbox>>,
+}
+
+impl TestRoom {
+    pub fn start(config: Config) -> TestRoom {
+        TestRoom::start_with(None, config)
+    }
+
+    pub fn start_with(password: Option<&str>, config: Config) -> TestRoom {
+        let manager = Manager::default().start();
+        let password = password.map(|password| password.to_string());
+        let room = RoomActor::new(
+            ROOM.to_string(),
+            password,
+            false,
+            None,
+            manager.clone().recipient(),
+            config,
+        );
+        TestRoom {
+            addr: room.start(),
+            manager,
+            inboxes: HashMap::new(),
+        }
+    }
+
+    /// Joins the room as an active member. The first one to join is the host.
+    pub async fn join(&mut self, user_id: &str) -> Addr<Inbox> {
+        self.join_with(user_id, None, false).await
+    }
+
+    pub async fn join_with(
+        &mut self,
+        user_id: &str,
+        password: Option<&str>,
+        spectator: bool,
+    ) -> Addr<Inbox> {
+        let inbox = self.new_inbox(user_id);
+        let msg = RoomMessage::JoinRoom {
+            room_name: ROOM.to_string(),
+            password: password.map(|password| password.to_string()),
+            password_is_hash: false,
+            user: user(user_id),
+            recipient: inbox.clone().recipient(),
+            ip: None,
+            custom_scale: None,
+            public: false,
+            spectator,
+        };
+        self.send(msg).await;
+        inbox
+    }
+
+    pub async fn vote(&self, user_id: &str, size: &str) {
+        self.send(RoomMessage::Vote {
+            room_name: ROOM.to_string(),
+            user_id: user_id.to_string(),
+            size: size.to_string(),
+            confidence: None,
+        })
+        .await;
+    }
+
+    /// Sends the room the requests of a session recording, the `Record:` lines of a log.
+    /// Members get an inbox when they join or reconnect, as connections are not recorded.
+    pub async fn replay(&mut self, recording: &str) {
+        for line in recording.lines() {
+            let entry = match line.split_once("Record: ") {
+                None => continue,
+                Some((_, entry)) => entry,
+            };
+            let mut entry: Value = serde_json::from_str(entry).unwrap();
+            if entry["kind"] != "Request" {
+                continue;
+            }
+            let mut msg: RoomMessage = serde_json::from_value(entry["message"].take()).unwrap();
+            match msg {
+                RoomMessage::JoinRoom {
+                    ref user,
+                    ref mut recipient,
+                    ..
+                } => *recipient = self.new_inbox(&user.user_id).recipient(),
+                RoomMessage::Reconnected {
+                    ref user_id,
+                    ref mut recipient,
+                    ..
+                } => *recipient = self.new_inbox(user_id).recipient(),
+                _ => {}
+            }
+            self.send(msg).await;
+        }
+    }
+
+    fn new_inbox(&mut self, user_id: &str) -> Addr<Inbox> {
+        let inbox = Inbox::default().start();
+        self.inboxes.insert(user_id.to_string(), inbox.clone());
+        inbox
+    }
+
+    pub async fn send(&self, msg: RoomMessage) {
+        self.addr.send(msg).await.unwrap();
+    }
+
+    pub async fn run<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut RoomActor, &mut Context<RoomActor>) -> R + Send + 'static,
+    {
+        self.addr.send(Run(Box::new(f))).await.unwrap()
+    }
+
+    pub fn inbox(&self, user_id: &str) -> &Addr<Inbox> {
+        &self.inboxes[user_id]
+    }
+
+    /// Takes what the member received so far.
+    pub async fn received(&self, user_id: &str) -> Vec<ClientResponseMessage> {
+        take(self.inbox(user_id)).await
+    }
+
+    /// Takes what the room told the manager so far.
+    pub async fn manager_received(&self) -> Vec<RoomMessage> {
+        self.manager.send(TakeRoomMessages).await.unwrap()
+    }
+}
+
+/// Joins the room of the tests through the manager, as a member with an inbox of its own.
+pub async fn join_through(
+    room_manager: &Addr<RoomManagerActor>,
+    user_id: &str,
+    spectator: bool,
+) -> Addr<Inbox> {
+    let inbox = Inbox::default().start();
+    let msg = RoomMessage::JoinRoom {
+        room_name: ROOM.to_string(),
+        password: None,
+        password_is_hash: false,
+        user: user(user_id),
+        recipient: inbox.clone().recipient(),
+        ip: None,
+        custom_scale: None,
+        public: false,
+        spectator,
+    };
+    room_manager.send(msg).await.unwrap();
+    inbox
+}
+
+/// How long a websocket client waits for the server before a test fails.
+const WS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server with the websocket route and a room manager of its own.
+pub fn server(config: Config) -> test::TestServer {
+    let room_manager = RoomManagerActor::new(config.clone()).start();
+    server_with(room_manager, config)
+}
+
+/// A server whose connections go to the given manager, so tests can look inside it.
+pub fn server_with(room_manager: Addr<RoomManagerActor>, config: Config) -> test::TestServer {
+    test::start(move || {
+        App::new()
+            .data(room_manager.clone())
+            .data(config.clone())
+            .service(web::resource("/").route(web::get().to(crate::ws_index)))
+    })
+}
+
+/// The client side of a websocket connection.
+pub trait WsClient:
+    Sink<ws::Message, Error = ws::ProtocolError>
+    + Stream<Item = Result<ws::Frame, ws::ProtocolError>>
+    + Unpin
+{
+}
+
+impl<T> WsClient for T where
+    T: Sink<ws::Message, Error = ws::ProtocolError>
+        + Stream<Item = Result<ws::Frame, ws::ProtocolError>>
+        + Unpin
+{
+}
+
+pub async fn send_json(client: &mut impl WsClient, msg: Value) {
+    client
+        .send(ws::Message::Text(msg.to_string()))
+        .await
+        .unwrap();
+}
+
+/// The next frame that is not a ping, None once the connection is gone.
+pub async fn next_frame(client: &mut impl WsClient) -> Option<ws::Frame> {
+    loop {
+        let frame = actix_rt::time::timeout(WS_TIMEOUT, client.next())
+            .await
+            .expect("The server did not answer in time.");
+        match frame {
+            Some(Ok(ws::Frame::Ping(_))) => continue,
+            Some(Ok(frame)) => return Some(frame),
+            _ => return None,
+        }
+    }
+}
+
+/// Reads until a message of the type comes, skipping the others.
+pub async fn next_of_type(client: &mut impl WsClient, msg_type: &str) -> Value {
+    loop {
+        match next_frame(client).await {
+            Some(ws::Frame::Text(text)) => {
+                let msg: Value = serde_json::from_slice(&text).unwrap();
+                if msg["type"] == msg_type {
+                    return msg;
+                }
+            }
+            Some(ws::Frame::Close(reason)) => panic!("Closed while waiting: {:?}", reason),
+            Some(_) => {}
+            None => panic!("Connection gone while waiting for {}.", msg_type),
+        }
+    }
+}
+
+/// Reads until the server closes the connection, skipping the messages before.
+pub async fn close_reason(client: &mut impl WsClient) -> Option<ws::CloseReason> {
+    loop {
+        match next_frame(client).await {
+            Some(ws::Frame::Close(reason)) => return reason,
+            Some(_) => {}
+            None => panic!("Connection gone without a close frame."),
+        }
+    }
+}
+
+/// Asks the server who the client is, returning its user id.
+pub async fn register(client: &mut impl WsClient) -> String {
+    send_json(client, serde_json::json!({ "type": "Register" })).await;
+    let own_data = next_of_type(client, "OwnData").await;
+    own_data["data"]["user"]["user_id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+pub async fn join_room(client: &mut impl WsClient, room_name: &str) {
+    let msg = serde_json::json!({ "type": "JoinRoom", "data": { "room_name": room_name } });
+    send_json(client, msg).await;
+}
+
+/// Connects a client speaking MessagePack.
+pub async fn msgpack_client(srv: &test::TestServer) -> impl WsClient {
+    let (_, client) = awc::Client::new()
+        .ws(srv.url("/"))
+        .protocols([crate::actors::MSGPACK_PROTOCOL])
+        .connect()
+        .await
+        .unwrap();
+    client
+}
+
+/// The next MessagePack message, read as JSON.
+pub async fn next_msgpack(client: &mut impl WsClient) -> Value {
+    match next_frame(client).await {
+        Some(ws::Frame::Binary(bin)) => rmp_serde::from_slice(&bin).unwrap(),
+        frame => panic!("Expected a binary frame, got {:?}.", frame),
+    }
+}
+
+/// Held by tests that read identifiers back from the logs, as whether they are redacted is
+/// set for the whole process.
+pub static REDACTION: futures::lock::Mutex<()> = futures::lock::Mutex::new(());
+
+/// Keeps what is logged, so tests can read it back.
+#[derive(Clone, Default)]
+pub struct Log(Arc<Mutex<Vec<u8>>>);
+
+impl Log {
+    /// Captures what the current thread logs until the guard is dropped.
+    pub fn capture() -> (Log, DefaultGuard) {
+        let log = Log::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        (log, tracing::subscriber::set_default(subscriber))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl io::Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::data::GRAVATAR_STYLES;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+/// How messages are handed over to the client actors.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Delivery {
+    /// Fails fast when the client mailbox is full, dropping the message.
+    Immediate,
+    /// Queues the message even if the client mailbox is full.
+    Queued,
+}
+
+/// Server configuration, read from the environment on startup.
+#[derive(Clone)]
+pub struct Config {
+    pub delivery: Delivery,
+    /// Secret required by admin commands. Admin commands are disabled when not set.
+    pub admin_secret: Option<String>,
+    /// Name users have until they set one.
+    pub default_name: String,
+    /// Gravatar default image style users have until they pick one.
+    pub gravatar_style: String,
+    /// How many scales a room may have besides the built-in ones.
+    pub max_custom_scales: usize,
+    /// Percentage of the room that has to vote for the round to end.
+    pub quorum_percent: usize,
+    /// How often heartbeat pings are sent. Always shorter than the client timeout.
+    pub heartbeat_interval: Duration,
+    /// How long a client may go without answering before a still there prompt.
+    pub client_timeout: Duration,
+    /// How long a room host may go without answering before a still there prompt.
+    /// Never shorter than the timeout of other users.
+    pub host_timeout: Duration,
+    /// How long a client has to answer the still there prompt before being disconnected.
+    pub still_there_timeout: Duration,
+    /// Largest numeric value a scale may have, so sizes can be summed without overflowing.
+    pub max_vote_value: u64,
+    /// How many open rooms may have been created from the same IP address. 0 means no limit.
+    pub max_rooms_per_ip: usize,
+    /// Room names and user ids are hashed in the logs.
+    pub redact_logs: bool,
+    /// Shortest time between two randomizes of a room.
+    pub randomize_cooldown: Duration,
+    /// How many times a connection may try to join a room in a minute.
+    pub max_joins_per_minute: usize,
+    /// How long names stay on the rounds of rooms that anonymize their history. Zero strips
+    /// them as soon as the round ends.
+    pub history_anonymize_delay: Duration,
+    /// How many idle room actors are kept to be reused by new rooms. 0 disables the pool.
+    pub room_pool_size: usize,
+    /// The summary of a session is logged when its room closes.
+    pub log_summaries: bool,
+    /// Broadcasts to rooms larger than this are sent this many members at a time, so the room
+    /// keeps answering in between. 0 sends every broadcast at once.
+    pub broadcast_chunk_size: usize,
+    /// How long a room waits for more scale changes before selecting the last one.
+    pub scale_debounce: Duration,
+    /// Every request and broadcast of a room is logged, for post-mortems. Never together
+    /// with redacted logs, as recordings hold names and ids.
+    pub record_sessions: bool,
+    /// How many finished rounds a room keeps. The oldest are dropped beyond it.
+    pub max_rounds: usize,
+    /// How long a room keeps a user whose connection dropped, waiting for them to resume.
+    pub disconnect_grace: Duration,
+    /// How long a room may go without votes, joins or scale changes before it is closed.
+    /// Zero keeps idle rooms open.
+    pub room_idle_timeout: Duration,
+    /// How many users a room may have, passive ones included. 0 means no limit.
+    pub max_room_size: usize,
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let (heartbeat_interval, client_timeout) = heartbeat_from_env();
+        Config {
+            delivery: delivery_from_env(),
+            admin_secret: env::var("SIZEMATTERS_ADMIN_SECRET").ok(),
+            default_name: env::var("SIZEMATTERS_DEFAULT_NAME")
+                .ok()
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or_else(|| "Shirtless Muppet".to_string()),
+            gravatar_style: gravatar_style_from_env(),
+            max_custom_scales: parse_env("SIZEMATTERS_MAX_CUSTOM_SCALES", 10),
+            quorum_percent: parse_env("SIZEMATTERS_QUORUM_PERCENT", 100).clamp(1, 100),
+            heartbeat_interval,
+            client_timeout,
+            host_timeout: Duration::from_secs(parse_env("SIZEMATTERS_HOST_TIMEOUT", 10)),
+            still_there_timeout: Duration::from_secs(parse_env(
+                "SIZEMATTERS_STILL_THERE_TIMEOUT",
+                10,
+            )),
+            max_vote_value: parse_env("SIZEMATTERS_MAX_VOTE_VALUE", 1_000_000),
+            max_rooms_per_ip: parse_env("SIZEMATTERS_MAX_ROOMS_PER_IP", 0),
+            max_rounds: parse_env("SIZEMATTERS_MAX_ROUNDS", 100),
+            history_anonymize_delay: Duration::from_secs(parse_env(
+                "SIZEMATTERS_HISTORY_ANONYMIZE_SECONDS",
+                300,
+            )),
+            room_pool_size: parse_env("SIZEMATTERS_ROOM_POOL_SIZE", 0),
+            max_joins_per_minute: parse_env("SIZEMATTERS_MAX_JOINS_PER_MINUTE", 10),
+            randomize_cooldown: Duration::from_secs(parse_env("SIZEMATTERS_RANDOMIZE_COOLDOWN", 2)),
+            redact_logs: parse_env("SIZEMATTERS_REDACT_LOGS", false),
+            broadcast_chunk_size: parse_env("SIZEMATTERS_BROADCAST_CHUNK_SIZE", 100),
+            scale_debounce: Duration::from_millis(parse_env("SIZEMATTERS_SCALE_DEBOUNCE_MS", 500)),
+            record_sessions: record_sessions_from_env(),
+            log_summaries: parse_env("SIZEMATTERS_LOG_SUMMARIES", false),
+            disconnect_grace: Duration::from_secs(parse_env("SIZEMATTERS_DISCONNECT_GRACE", 30)),
+            max_room_size: parse_env("SIZEMATTERS_MAX_ROOM_SIZE", 0),
+            room_idle_timeout: Duration::from_secs(
+                60 * parse_env("SIZEMATTERS_ROOM_IDLE_MINUTES", 120),
+            ),
+        }
+    }
+}
+
+/// Reads a variable from the environment, using the default if it is not set or invalid.
+fn parse_env<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Err(_) => default,
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!(value = %value, "Invalid {}, using the default.", name);
+            default
+        }),
+    }
+}
+
+fn heartbeat_from_env() -> (Duration, Duration) {
+    let interval = Duration::from_secs(parse_env("SIZEMATTERS_HEARTBEAT_INTERVAL", 5));
+    let timeout = Duration::from_secs(parse_env("SIZEMATTERS_CLIENT_TIMEOUT", 10));
+    checked_heartbeat(interval, timeout)
+}
+
+/// The heartbeat interval and the client timeout. A timeout that is not longer than the
+/// interval would prompt clients before they had a chance to answer, so both fall back to
+/// the defaults then.
+fn checked_heartbeat(interval: Duration, timeout: Duration) -> (Duration, Duration) {
+    let defaults = (Duration::from_secs(5), Duration::from_secs(10));
+    if interval.is_zero() || timeout <= interval {
+        warn!(
+            "SIZEMATTERS_CLIENT_TIMEOUT must be longer than a non-zero \
+             SIZEMATTERS_HEARTBEAT_INTERVAL, using the defaults."
+        );
+        return defaults;
+    }
+    (interval, timeout)
+}
+
+fn record_sessions_from_env() -> bool {
+    let record_sessions = parse_env("SIZEMATTERS_RECORD_SESSIONS", false);
+    if record_sessions && parse_env("SIZEMATTERS_REDACT_LOGS", false) {
+        warn!("Sessions are not recorded while logs are redacted.");
+        return false;
+    }
+    record_sessions
+}
+
+fn delivery_from_env() -> Delivery {
+    match env::var("SIZEMATTERS_DELIVERY") {
+        Err(_) => Delivery::Queued,
+        Ok(value) => match value.as_str() {
+            "immediate" => Delivery::Immediate,
+            "queued" => Delivery::Queued,
+            _ => {
+                warn!(value = %value, "Unknown SIZEMATTERS_DELIVERY, using 'queued'.");
+                Delivery::Queued
+            }
+        },
+    }
+}
+
+fn gravatar_style_from_env() -> String {
+    match env::var("SIZEMATTERS_GRAVATAR_STYLE") {
+        Err(_) => "identicon".to_string(),
+        Ok(value) if GRAVATAR_STYLES.contains(&value.as_str()) => value,
+        Ok(value) => {
+            warn!(value = %value, "Unknown SIZEMATTERS_GRAVATAR_STYLE, using 'identicon'.");
+            "identicon".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_longer_than_the_interval_is_kept() {
+        let interval = Duration::from_secs(20);
+        let timeout = Duration::from_secs(60);
+        assert_eq!(checked_heartbeat(interval, timeout), (interval, timeout));
+    }
+
+    #[test]
+    fn timeout_not_longer_than_the_interval_falls_back_to_the_defaults() {
+        let defaults = (Duration::from_secs(5), Duration::from_secs(10));
+        let interval = Duration::from_secs(30);
+        assert_eq!(checked_heartbeat(interval, interval), defaults);
+        assert_eq!(
+            checked_heartbeat(interval, Duration::from_secs(10)),
+            defaults
+        );
+        assert_eq!(checked_heartbeat(Duration::ZERO, interval), defaults);
+    }
+}
@@ -18,27 +18,444 @@
 
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
+use std::time::Instant;
+
+/// Colors given to users that did not pick one.
+const DEFAULT_COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+
+/// Default image styles Gravatar supports, for users without a Gravatar.
+pub const GRAVATAR_STYLES: [&str; 7] = [
+    "mp",
+    "identicon",
+    "monsterid",
+    "wavatar",
+    "retro",
+    "robohash",
+    "blank",
+];
 
 #[derive(Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
 pub struct UserData {
     pub user_id: String,
     pub name: String,
     pub gravatar_id: String,
+    /// Default image style clients should ask Gravatar for.
+    pub gravatar_style: String,
+    pub color: String,
 }
 
 impl UserData {
-    pub fn new(user_id: String) -> UserData {
-        let name = "Shirtless Muppet".to_string();
-        let gravatar_id = format!("{:x}", md5::compute(user_id.clone()));
+    pub fn new(user_id: String, name: String, gravatar_style: String) -> UserData {
+        let digest = md5::compute(user_id.clone());
+        let gravatar_id = format!("{:x}", digest);
+        // derived from the id, so the same user always gets the same color
+        let color = DEFAULT_COLORS[digest[0] as usize % DEFAULT_COLORS.len()].to_string();
         UserData {
             user_id,
             name,
             gravatar_id,
+            gravatar_style,
+            color,
         }
     }
 
     pub fn set_avatar(&mut self, avatar: &str) {
         self.gravatar_id = format!("{:x}", md5::compute(avatar));
     }
+
+    /// Sets the Gravatar default image style if it is one Gravatar supports.
+    pub fn set_gravatar_style(&mut self, style: &str) -> bool {
+        let valid = GRAVATAR_STYLES.contains(&style);
+        if valid {
+            self.gravatar_style = style.to_string();
+        }
+        valid
+    }
+
+    /// Sets the color if it is a hex color in the `#rrggbb` format.
+    pub fn set_color(&mut self, color: &str) -> bool {
+        let valid = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if valid {
+            self.color = color.to_lowercase();
+        }
+        valid
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scale {
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    pub values: Vec<String>,
+    /// Display labels for some of the values. Votes still use the values.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+impl Scale {
+    pub fn new(name: &str, display_name: &str, values: &[&str]) -> Scale {
+        Scale {
+            name: name.to_string(),
+            display_name: display_name.to_string(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Checks that the scale can be used for voting, returning the reason when it cannot.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+        if self.display_name.trim().is_empty() {
+            return Err("displayName is empty".to_string());
+        }
+        if self.values.is_empty() {
+            return Err("values are empty".to_string());
+        }
+        if self.values.iter().any(|value| value.trim().is_empty()) {
+            return Err("a value is empty".to_string());
+        }
+        let unique: HashSet<&String> = self.values.iter().collect();
+        if unique.len() != self.values.len() {
+            return Err("values are not unique".to_string());
+        }
+        if let Some(value) = self.labels.keys().find(|value| !unique.contains(value)) {
+            return Err(format!("label for unknown value {}", value));
+        }
+        Ok(())
+    }
+}
+
+/// Summary of the sizes voted in a round. Abstentions are not counted.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct VoteSummary {
+    pub min: u64,
+    pub max: u64,
+    pub average: f64,
+    /// The most voted sizes, smallest first. There is more than one when they tie.
+    pub modes: Vec<String>,
+}
+
+impl VoteSummary {
+    /// Summary of the numeric votes. Abstentions and values that are not numbers, like the
+    /// sizes of a T-shirt scale, are not counted.
+    pub fn from_votes<'a, I: IntoIterator<Item = &'a Option<String>>>(votes: I) -> Option<Self> {
+        let sizes = votes
+            .into_iter()
+            .filter_map(|size| size.as_ref()?.parse().ok());
+        VoteSummary::from_sizes(sizes)
+    }
+
+    /// Returns None when no size was voted, so an empty round never divides by zero.
+    pub fn from_sizes<I: IntoIterator<Item = u64>>(sizes: I) -> Option<Self> {
+        let sizes: Vec<u64> = sizes.into_iter().collect();
+        let min = *sizes.iter().min()?;
+        let max = *sizes.iter().max()?;
+        // summed wider than the sizes, so large sizes cannot overflow
+        let sum: u128 = sizes.iter().map(|&size| u128::from(size)).sum();
+        let average = sum as f64 / sizes.len() as f64;
+        let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+        for size in sizes.iter() {
+            *counts.entry(*size).or_insert(0) += 1;
+        }
+        let most = counts.values().copied().max().unwrap_or(0);
+        let modes = counts
+            .into_iter()
+            .filter(|(_, count)| *count == most)
+            .map(|(size, _)| size.to_string())
+            .collect();
+        Some(VoteSummary {
+            min,
+            max,
+            average,
+            modes,
+        })
+    }
+}
+
+/// A finished round, as it was revealed.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Round {
+    pub votes: HashMap<String, Option<String>>,
+    pub summary: Option<VoteSummary>,
+    pub topic: Option<String>,
+    /// From the start of the round until the next one started.
+    pub duration_secs: u64,
+    /// The votes were revealed without saying who voted what.
+    pub anonymous: bool,
+    /// When the names are to be stripped from the round.
+    #[serde(skip)]
+    pub anonymize_at: Option<Instant>,
+}
+
+impl Round {
+    /// Forgets who voted what. The sizes are kept, numbered instead of named.
+    pub fn strip_names(&mut self) {
+        let mut sizes: Vec<Option<String>> = self.votes.drain().map(|(_, size)| size).collect();
+        sizes.sort();
+        self.votes = sizes
+            .into_iter()
+            .enumerate()
+            .map(|(position, size)| ((position + 1).to_string(), size))
+            .collect();
+        self.anonymous = true;
+        self.anonymize_at = None;
+    }
+
+    /// Everyone who voted a size voted the same one.
+    pub fn consensus(&self) -> bool {
+        let mut sizes = self.votes.values().flatten();
+        match sizes.next() {
+            None => false,
+            Some(first) => sizes.all(|size| size == first),
+        }
+    }
+}
+
+/// How productive a session has been so far.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoomStatistics {
+    pub rounds: usize,
+    /// Sum of the average size of every round where a size was voted.
+    pub total_points: f64,
+    /// None when no size was voted in any round.
+    pub average_points: Option<f64>,
+    /// None when no round has finished.
+    pub average_round_secs: Option<f64>,
+}
+
+/// How a finished round ended.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoundOutcome {
+    pub topic: Option<String>,
+    pub summary: Option<VoteSummary>,
+    pub consensus: bool,
+}
+
+/// What happened in a room, from its creation until now.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SessionSummary {
+    pub room_name: String,
+    /// Names of everyone who joined the room, sorted.
+    pub participants: Vec<String>,
+    pub duration_secs: u64,
+    pub rounds: Vec<RoundOutcome>,
+}
+
+/// A member of a room, with their role in it.
+#[derive(Serialize, Clone)]
+pub struct Member {
+    pub user: UserData,
+    pub active: bool,
+    pub host: bool,
+}
+
+/// How many members of a room vote and how many just follow it.
+#[derive(Serialize, Clone)]
+pub struct MemberCounts {
+    pub active: usize,
+    pub passive: usize,
+}
+
+/// The state of a room, for operators and for the list of public rooms. Passwords and
+/// votes are left out.
+#[derive(Serialize, Clone)]
+pub struct RoomSummary {
+    pub name: String,
+    pub members: MemberCounts,
+    pub selected_scale_name: String,
+    /// The results of the current round have not been revealed to everyone yet.
+    pub round_in_progress: bool,
+    /// Joining the room takes a password.
+    pub password_protected: bool,
+    /// What the current round is about.
+    pub topic: Option<String>,
+}
+
+/// An item of known size that votes on the relative scale are compared to.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub value: u64,
+}
+
+/// Who may reveal the results to everyone.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RevealPolicy {
+    Host,
+    Anyone,
+}
+
+/// The data of a room, without its connections.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoomSnapshot {
+    pub name: String,
+    /// None for a room anyone may join.
+    pub hashed_password: Option<String>,
+    pub users: Vec<UserData>,
+    pub votes: HashMap<String, Option<String>>,
+    pub confidence: HashMap<String, u8>,
+    pub host_ids: Vec<String>,
+    /// Missing from snapshots taken before only the creator could kick.
+    #[serde(default)]
+    pub creator_id: Option<String>,
+    pub paused: bool,
+    pub voting_over: bool,
+    pub revealed_to_host: bool,
+    pub anonymous_reveal: bool,
+    pub votes_attributed: bool,
+    pub reveal_policy: RevealPolicy,
+    pub reveal_on_complete: bool,
+    pub unique_names: bool,
+    pub auto_new_vote: bool,
+    /// Missing from snapshots taken before rooms could anonymize their history.
+    #[serde(default)]
+    pub anonymize_history: bool,
+    pub abstentions_count: bool,
+    pub locked: bool,
+    pub passive_ids: Vec<String>,
+    pub topic: Option<String>,
+    pub reference: Option<Reference>,
+    pub scale_values: HashMap<String, Scale>,
+    pub selected_scale_name: String,
+    pub last_randomized: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_of_large_sizes_does_not_overflow() {
+        let summary = VoteSummary::from_sizes(vec![u64::MAX, u64::MAX]).unwrap();
+        assert_eq!(summary.max, u64::MAX);
+        assert_eq!(summary.average, u64::MAX as f64);
+    }
+
+    #[test]
+    fn clear_mode_is_the_only_one() {
+        let summary = VoteSummary::from_sizes(vec![3, 5, 5, 8]).unwrap();
+        assert_eq!(summary.modes, vec!["5"]);
+    }
+
+    #[test]
+    fn tied_modes_are_listed_smallest_first() {
+        let summary = VoteSummary::from_sizes(vec![13, 3, 8, 13, 3, 8, 1]).unwrap();
+        assert_eq!(summary.modes, vec!["3", "8", "13"]);
+    }
+
+    #[test]
+    fn modes_leave_out_values_that_are_not_numbers() {
+        let votes = [
+            Some("M".to_string()),
+            Some("M".to_string()),
+            Some("2".to_string()),
+            None,
+        ];
+        let summary = VoteSummary::from_votes(votes.iter()).unwrap();
+        assert_eq!(summary.modes, vec!["2"]);
+    }
+
+    #[test]
+    fn scales_with_duplicate_values_are_invalid() {
+        let scale = Scale::new("dup", "Dup", &["1", "2", "2"]);
+        assert_eq!(scale.validate(), Err("values are not unique".to_string()));
+    }
+
+    #[test]
+    fn scales_without_values_are_invalid() {
+        let scale = Scale::new("none", "None", &[]);
+        assert_eq!(scale.validate(), Err("values are empty".to_string()));
+    }
+
+    #[test]
+    fn scales_with_an_empty_value_are_invalid() {
+        let scale = Scale::new("blank", "Blank", &["1", " "]);
+        assert_eq!(scale.validate(), Err("a value is empty".to_string()));
+    }
+
+    #[test]
+    fn scales_without_a_name_are_invalid() {
+        assert!(Scale::new("", "Nameless", &["1", "2"]).validate().is_err());
+        assert!(Scale::new("nameless", " ", &["1", "2"]).validate().is_err());
+    }
+
+    fn user(user_id: &str) -> UserData {
+        UserData::new(user_id.to_string(), "Name".to_string(), "mp".to_string())
+    }
+
+    #[test]
+    fn default_color_is_derived_from_the_id() {
+        assert_eq!(user("alice").color, user("alice").color);
+        assert!(DEFAULT_COLORS.contains(&user("alice").color.as_str()));
+    }
+
+    #[test]
+    fn hex_colors_are_accepted() {
+        let mut user = user("alice");
+        assert!(user.set_color("#A1b2C3"));
+        assert_eq!(user.color, "#a1b2c3");
+    }
+
+    #[test]
+    fn colors_that_are_not_hex_are_refused() {
+        let mut user = user("alice");
+        let color = user.color.clone();
+        for invalid in ["red", "#abc", "a1b2c3d", "#a1b2g3", "#a1b2c3d", ""] {
+            assert!(!user.set_color(invalid), "{}", invalid);
+        }
+        assert_eq!(user.color, color);
+    }
+
+    #[test]
+    fn sizes_too_large_for_a_number_are_left_out() {
+        let votes = [
+            Some("99999999999999999999999".to_string()),
+            Some("3".to_string()),
+        ];
+        let summary = VoteSummary::from_votes(votes.iter()).unwrap();
+        assert_eq!(summary.max, 3);
+        assert_eq!(summary.average, 3.0);
+    }
+
+    fn labeled(value: &str, label: &str) -> Scale {
+        let mut scale = Scale::new("sized", "Sized", &["3", "8"]);
+        scale.labels.insert(value.to_string(), label.to_string());
+        scale
+    }
+
+    #[test]
+    fn labels_round_trip() {
+        let scale = labeled("8", "8 - large");
+        let json = serde_json::to_string(&scale).unwrap();
+        let scale: Scale = serde_json::from_str(&json).unwrap();
+        assert_eq!(scale.labels["8"], "8 - large");
+        assert_eq!(scale.values, vec!["3", "8"]);
+        assert_eq!(scale.validate(), Ok(()));
+    }
+
+    #[test]
+    fn scales_without_labels_read_as_unlabeled() {
+        let json = r#"{"name": "plain", "displayName": "Plain", "values": ["1", "2"]}"#;
+        let scale: Scale = serde_json::from_str(json).unwrap();
+        assert!(scale.labels.is_empty());
+    }
+
+    #[test]
+    fn labels_of_unknown_values_are_rejected() {
+        let scale = labeled("13", "huge");
+        assert_eq!(
+            scale.validate(),
+            Err("label for unknown value 13".to_string())
+        );
+    }
 }
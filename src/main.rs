@@ -17,44 +17,140 @@
  */
 
 mod actors;
+mod config;
 mod data;
+mod redact;
+mod rest;
 
 use actix::{Actor, Addr};
+use actix_rt::{signal, time};
+use actix_web::dev::Server;
+use actix_web::http::header;
 use actix_web::{middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 
 use actors::ClientActor;
 use actors::RoomManagerActor;
+use actors::Shutdown;
+use actors::{WireFormat, MSGPACK_PROTOCOL};
+use config::Config;
+use futures::future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// What is logged when RUST_LOG is not set
+const LOG_FILTER: &str = "info";
+/// Address the server listens on when SIZEMATTERS_BIND is not set
+const DEFAULT_BIND: &str = "127.0.0.1:9001";
+/// How long rooms have to export their results once the server is asked to stop
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long clients have to get the shutdown notice before connections are closed
+const SHUTDOWN_NOTICE_WINDOW: Duration = Duration::from_secs(1);
 
 /// do websocket handshake and start `MyWebSocket` actor
 async fn ws_index(
     r: HttpRequest,
     stream: web::Payload,
     room_manager: web::Data<Addr<RoomManagerActor>>,
+    config: web::Data<Config>,
 ) -> Result<HttpResponse, Error> {
-    //println!("{:?}", r);
     let room_manager_addr = room_manager.get_ref().clone();
-    let res = ws::start(ClientActor::new(room_manager_addr), &r, stream);
-    //println!("{:?}", res);
-    res
+    let config = config.get_ref().clone();
+    let ip = r.peer_addr().map(|addr| addr.ip());
+    let client = ClientActor::new(room_manager_addr, config, ip, wire_format(&r));
+    ws::start_with_protocols(client, &[MSGPACK_PROTOCOL], &r, stream)
+}
+
+/// MessagePack if the client asked for its subprotocol, JSON otherwise.
+fn wire_format(r: &HttpRequest) -> WireFormat {
+    let msgpack = r
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|protocols| protocols.to_str().ok())
+        .is_some_and(|protocols| {
+            protocols
+                .split(',')
+                .any(|protocol| protocol.trim() == MSGPACK_PROTOCOL)
+        });
+    if msgpack {
+        WireFormat::MessagePack
+    } else {
+        WireFormat::Json
+    }
+}
+
+/// The addresses to listen on, separated by commas in SIZEMATTERS_BIND.
+fn bind_addrs() -> io::Result<Vec<SocketAddr>> {
+    let bind = std::env::var("SIZEMATTERS_BIND").unwrap_or_else(|_| DEFAULT_BIND.to_string());
+    bind.split(',')
+        .map(|addr| {
+            addr.trim().parse().map_err(|_| {
+                let msg = format!("SIZEMATTERS_BIND: '{}' is not a socket address", addr);
+                io::Error::new(io::ErrorKind::InvalidInput, msg)
+            })
+        })
+        .collect()
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
-    env_logger::init();
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(LOG_FILTER));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
 
-    let room_manager = RoomManagerActor::new().start();
+    let addrs = bind_addrs()?;
+    let config = Config::from_env();
+    redact::init(config.redact_logs);
+    let room_manager = RoomManagerActor::new(config.clone()).start();
+    let app_room_manager = room_manager.clone();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .data(room_manager.clone())
+            .data(app_room_manager.clone())
+            .data(config.clone())
             // enable logger
             .wrap(middleware::Logger::default())
             // websocket route
             .service(web::resource("/").route(web::get().to(ws_index)))
+            .service(
+                web::resource("/rooms/{name}/members").route(web::get().to(rest::room_members)),
+            )
+            .service(web::resource("/admin/rooms").route(web::get().to(rest::admin_rooms)))
     })
-    .bind("127.0.0.1:9001")?
-    .run()
-    .await
+    .disable_signals()
+    .bind(&addrs[..])?;
+    for addr in server.addrs() {
+        info!(%addr, "Listening.");
+    }
+    let server = server.run();
+    actix_rt::spawn(shutdown_on_signal(server.clone(), room_manager));
+    server.await
+}
+
+/// Waits for SIGTERM or Ctrl-C, then tells every client the server is going down before the
+/// server stops. Draining is best-effort, a slow room must not hold the exit.
+async fn shutdown_on_signal(server: Server, room_manager: Addr<RoomManagerActor>) {
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                warn!(error = %err, "Unable to listen for SIGTERM.");
+                future::pending::<()>().await;
+            }
+        }
+    };
+    future::select(Box::pin(terminate), Box::pin(signal::ctrl_c())).await;
+    info!("Shutting down.");
+
+    let drain = room_manager.send(Shutdown);
+    if time::timeout(SHUTDOWN_DRAIN_TIMEOUT, drain).await.is_err() {
+        warn!("Rooms did not finish draining in time.");
+    }
+    // the notices are still on their way to the clients
+    time::delay_for(SHUTDOWN_NOTICE_WINDOW).await;
+    server.stop(true).await;
 }
@@ -0,0 +1,57 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns redaction of identifiers in the logs on or off. Called once on startup.
+pub fn init(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// An identifier as it should appear in the logs: a hash of it when redaction is on.
+/// Every room name or user id written to the logs must go through here.
+pub fn id(value: &str) -> String {
+    if ENABLED.load(Ordering::Relaxed) {
+        format!("{:x}", md5::compute(value))[..12].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::test_util::{self, Log, TestRoom, REDACTION};
+
+    #[actix_rt::test]
+    async fn join_log_has_a_hash_instead_of_the_id() {
+        let _redaction = REDACTION.lock().await;
+        let (log, _guard) = Log::capture();
+        init(true);
+        let mut room = TestRoom::start(test_util::config());
+        room.join("alice").await;
+        let hashed = id("alice");
+        init(false);
+
+        let text = log.text();
+        assert!(text.contains(&hashed));
+        assert!(!text.contains("alice"));
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * SizeMatters - a ticket sizing util
+ * Copyright (C) 2020 Andre Onuki
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::actors::{GetMemberCounts, GetRoomSummaries, RoomManagerActor};
+use crate::config::Config;
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::error;
+
+/// Header carrying the admin secret on admin endpoints.
+const ADMIN_SECRET_HEADER: &str = "X-Admin-Secret";
+
+/// How many active and passive members a room has. Identities are not exposed.
+pub async fn room_members(
+    room_name: web::Path<String>,
+    room_manager: web::Data<Addr<RoomManagerActor>>,
+) -> HttpResponse {
+    let msg = GetMemberCounts {
+        room_name: room_name.into_inner(),
+    };
+    match room_manager.send(msg).await {
+        Ok(Ok(counts)) => HttpResponse::Ok().json(counts),
+        Ok(Err(())) => HttpResponse::NotFound().finish(),
+        Err(err) => {
+            error!(error = %err, "Unable to get member counts.");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The state of every room, for operators. Requires the admin secret.
+pub async fn admin_rooms(
+    r: HttpRequest,
+    room_manager: web::Data<Addr<RoomManagerActor>>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let secret = r
+        .headers()
+        .get(ADMIN_SECRET_HEADER)
+        .and_then(|secret| secret.to_str().ok());
+    if secret.is_none() || config.admin_secret.as_deref() != secret {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match room_manager.send(GetRoomSummaries).await {
+        Ok(Ok(rooms)) => HttpResponse::Ok().json(rooms),
+        Ok(Err(())) => HttpResponse::InternalServerError().finish(),
+        Err(err) => {
+            error!(error = %err, "Unable to get room summaries.");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::test_util::{self, join_through, ROOM};
+    use actix::Actor;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    #[actix_rt::test]
+    async fn members_of_a_room_are_counted() {
+        let room_manager = RoomManagerActor::new(test_util::config()).start();
+        join_through(&room_manager, "alice", false).await;
+        join_through(&room_manager, "bob", false).await;
+        join_through(&room_manager, "carol", true).await;
+        let mut app =
+            test::init_service(App::new().data(room_manager).service(
+                web::resource("/rooms/{name}/members").route(web::get().to(room_members)),
+            ))
+            .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/rooms/{}/members", ROOM))
+            .to_request();
+        let counts: Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(counts, json!({ "active": 2, "passive": 1 }));
+    }
+
+    #[actix_rt::test]
+    async fn members_of_a_missing_room_are_not_found() {
+        let room_manager = RoomManagerActor::new(test_util::config()).start();
+        let mut app =
+            test::init_service(App::new().data(room_manager).service(
+                web::resource("/rooms/{name}/members").route(web::get().to(room_members)),
+            ))
+            .await;
+
+        let req = test::TestRequest::get()
+            .uri("/rooms/missing/members")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    fn admin_config() -> Config {
+        let mut config = test_util::config();
+        config.admin_secret = Some("secret".to_string());
+        config
+    }
+
+    #[actix_rt::test]
+    async fn admin_rooms_lists_the_state_of_every_room() {
+        let config = admin_config();
+        let room_manager = RoomManagerActor::new(config.clone()).start();
+        join_through(&room_manager, "alice", false).await;
+        join_through(&room_manager, "bob", true).await;
+        let mut app = test::init_service(
+            App::new()
+                .data(room_manager)
+                .data(config)
+                .service(web::resource("/admin/rooms").route(web::get().to(admin_rooms))),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/rooms")
+            .header(ADMIN_SECRET_HEADER, "secret")
+            .to_request();
+        let rooms: Value = test::read_response_json(&mut app, req).await;
+        let expected = json!([{
+            "name": ROOM,
+            "members": { "active": 1, "passive": 1 },
+            "selected_scale_name": "fibonacci",
+            "round_in_progress": true,
+            "password_protected": false,
+            "topic": null,
+        }]);
+        assert_eq!(rooms, expected);
+    }
+
+    #[actix_rt::test]
+    async fn admin_rooms_requires_the_secret() {
+        let config = admin_config();
+        let room_manager = RoomManagerActor::new(config.clone()).start();
+        let mut app = test::init_service(
+            App::new()
+                .data(room_manager)
+                .data(config)
+                .service(web::resource("/admin/rooms").route(web::get().to(admin_rooms))),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/admin/rooms").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/rooms")
+            .header(ADMIN_SECRET_HEADER, "guess")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}